@@ -0,0 +1,7 @@
+begin;
+--
+drop index if exists jen.posts_idx_published_visibility_published_at;
+alter table jen.posts
+  drop column if exists published_at;
+--
+commit;
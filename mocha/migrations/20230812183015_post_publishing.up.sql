@@ -0,0 +1,13 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- track when a post went live. drafts (published = false) always have a null published_at
+alter table posts
+  add column if not exists published_at timestamptz;
+--
+-- index the columns used by the anonymous listing endpoints
+create index if not exists posts_idx_published_visibility_published_at on "jen"."posts"("published", "visibility", "published_at");
+--
+commit;
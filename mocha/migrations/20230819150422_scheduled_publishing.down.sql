@@ -0,0 +1,7 @@
+begin;
+--
+drop index if exists jen.posts_idx_publish_at;
+alter table jen.posts
+  drop column if exists publish_at;
+--
+commit;
@@ -0,0 +1,16 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- drafts can be scheduled to go live at a later time. the scheduler clears this column when it
+-- publishes the post
+alter table posts
+  add column if not exists publish_at timestamptz;
+--
+-- the scheduler only ever looks at scheduled drafts, so keep the index small
+create index if not exists posts_idx_publish_at on "jen"."posts"("publish_at")
+where
+  not published and publish_at is not null;
+--
+commit;
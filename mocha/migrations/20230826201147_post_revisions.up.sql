@@ -0,0 +1,22 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- post_revisions table. every edit stores the state of the post right before it was overwritten
+create table if not exists post_revisions(
+  id uuid not null default uuid_generate_v4() primary key,
+  post_id uuid not null references posts(id) on delete cascade,
+  title text not null,
+  content text not null,
+  image_uri text not null,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create or replace trigger update_post_revisions_timestamp
+  before update on post_revisions for each row
+  execute function update_timestamp();
+--
+create index if not exists post_revisions_idx_post_id_created_at on "jen"."post_revisions"("post_id", "created_at");
+--
+commit;
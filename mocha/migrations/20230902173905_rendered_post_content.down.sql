@@ -0,0 +1,7 @@
+begin;
+--
+alter table jen.posts
+  drop column if exists content_html,
+  drop column if exists toc;
+--
+commit;
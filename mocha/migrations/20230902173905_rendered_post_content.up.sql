@@ -0,0 +1,12 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- posts.content holds markdown. the sanitized html and table of contents are rendered by mocha
+-- whenever the content is written and cached here so readers never pay for rendering
+alter table posts
+  add column if not exists content_html text not null default '',
+  add column if not exists toc jsonb not null default '[]' ::jsonb;
+--
+commit;
@@ -0,0 +1,6 @@
+begin;
+--
+alter table jen.posts
+  drop column if exists word_count;
+--
+commit;
@@ -0,0 +1,10 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- read_time (in minutes) and word_count are computed by mocha from the rendered content
+alter table posts
+  add column if not exists word_count int not null default 0;
+--
+commit;
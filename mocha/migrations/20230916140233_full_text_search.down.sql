@@ -0,0 +1,15 @@
+begin;
+--
+drop index if exists jen.tags_search_idx;
+alter table jen.tags
+  drop column if exists search;
+--
+drop index if exists jen.spaces_search_idx;
+alter table jen.spaces
+  drop column if exists search;
+--
+drop index if exists jen.posts_search_idx;
+alter table jen.posts
+  drop column if exists search;
+--
+commit;
@@ -0,0 +1,29 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- search vectors are generated columns so they can never drift from the text they index. titles
+-- and names are weighted above the body so they rank higher
+alter table posts
+  add column if not exists search tsvector generated always as (
+    setweight(to_tsvector('english'::regconfig, title), 'A') ||
+    setweight(to_tsvector('english'::regconfig, content), 'B')
+  ) stored;
+create index if not exists posts_search_idx on posts using gin (search);
+--
+alter table spaces
+  add column if not exists search tsvector generated always as (
+    setweight(to_tsvector('english'::regconfig, space_name), 'A') ||
+    setweight(to_tsvector('english'::regconfig, bio), 'B')
+  ) stored;
+create index if not exists spaces_search_idx on spaces using gin (search);
+--
+alter table tags
+  add column if not exists search tsvector generated always as (
+    setweight(to_tsvector('english'::regconfig, tag_name), 'A') ||
+    setweight(to_tsvector('english'::regconfig, tag_description), 'B')
+  ) stored;
+create index if not exists tags_search_idx on tags using gin (search);
+--
+commit;
@@ -0,0 +1,32 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- comments table. replies point at their parent and at the top level comment of their thread so
+-- a whole thread can be fetched with one index scan. deleted comments are kept as tombstones
+-- (deleted_at set, content cleared) so that their replies stay attached
+create table if not exists comments(
+  id uuid not null default uuid_generate_v4() primary key,
+  post_id uuid not null references posts(id) on delete cascade,
+  user_id uuid not null references users(id) on delete cascade,
+  parent_id uuid references comments(id) on delete cascade,
+  thread_id uuid references comments(id) on delete cascade,
+  depth int not null default 0,
+  content text not null,
+  edited_at timestamptz,
+  deleted_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  check ((parent_id is null) = (thread_id is null))
+);
+create or replace trigger update_comments_timestamp
+  before update on comments for each row
+  execute function update_timestamp();
+--
+create index if not exists comments_idx_post_id_created_at on "jen"."comments"("post_id", "created_at")
+  where parent_id is null;
+create index if not exists comments_idx_thread_id_created_at on "jen"."comments"("thread_id", "created_at");
+create index if not exists comments_idx_parent_id on "jen"."comments"("parent_id");
+--
+commit;
@@ -0,0 +1,24 @@
+begin;
+--
+delete from jen.role_permission_mappings
+  where role_id = jen.get_role_id('mocha-default')
+    and permission_id = jen.get_permission_id('comments:likes:delete');
+--
+create or replace trigger update_comments_timestamp
+  before update on jen.comments for each row
+  execute function jen.update_timestamp();
+create or replace trigger update_posts_timestamp
+  before update on jen.posts for each row
+  execute function jen.update_timestamp();
+--
+drop table if exists jen.comment_likes;
+drop table if exists jen.post_likes;
+drop function if exists jen.count_comment_likes();
+drop function if exists jen.count_post_likes();
+--
+alter table jen.comments
+  drop column if exists like_count;
+alter table jen.posts
+  drop column if exists like_count;
+--
+commit;
@@ -0,0 +1,91 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- post_likes table
+create table if not exists post_likes(
+  id uuid not null default uuid_generate_v4() primary key,
+  post_id uuid not null references posts(id) on delete cascade,
+  user_id uuid not null references users(id) on delete cascade,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  unique (post_id, user_id)
+);
+create or replace trigger update_post_likes_timestamp
+  before update on post_likes for each row
+  execute function update_timestamp();
+--
+-- comment_likes table
+create table if not exists comment_likes(
+  id uuid not null default uuid_generate_v4() primary key,
+  comment_id uuid not null references comments(id) on delete cascade,
+  user_id uuid not null references users(id) on delete cascade,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  unique (comment_id, user_id)
+);
+create or replace trigger update_comment_likes_timestamp
+  before update on comment_likes for each row
+  execute function update_timestamp();
+--
+-- like counts are denormalized onto the liked row so listings never have to aggregate
+alter table posts
+  add column if not exists like_count int not null default 0;
+alter table comments
+  add column if not exists like_count int not null default 0;
+--
+create or replace function count_post_likes()
+  returns trigger
+  as $$
+begin
+  if tg_op = 'INSERT' then
+    update jen.posts set like_count = like_count + 1 where id = new.post_id;
+  else
+    update jen.posts set like_count = like_count - 1 where id = old.post_id;
+  end if;
+  return null;
+end;
+$$
+language plpgsql;
+create or replace trigger count_post_likes
+  after insert or delete on post_likes for each row
+  execute function count_post_likes();
+--
+create or replace function count_comment_likes()
+  returns trigger
+  as $$
+begin
+  if tg_op = 'INSERT' then
+    update jen.comments set like_count = like_count + 1 where id = new.comment_id;
+  else
+    update jen.comments set like_count = like_count - 1 where id = old.comment_id;
+  end if;
+  return null;
+end;
+$$
+language plpgsql;
+create or replace trigger count_comment_likes
+  after insert or delete on comment_likes for each row
+  execute function count_comment_likes();
+--
+-- a like is not an edit. leave updated_at alone when only the counter moves, otherwise feeds and
+-- the sitemap would report the post as changed
+create or replace trigger update_posts_timestamp
+  before update on posts for each row
+  when (old.like_count = new.like_count)
+  execute function update_timestamp();
+create or replace trigger update_comments_timestamp
+  before update on comments for each row
+  when (old.like_count = new.like_count)
+  execute function update_timestamp();
+--
+-- the initial migration let default users like comments but not take the like back
+insert into role_permission_mappings(role_id, permission_id)
+  select get_role_id('mocha-default'), get_permission_id('comments:likes:delete')
+  where not exists (
+    select 1 from role_permission_mappings
+    where role_id = get_role_id('mocha-default')
+      and permission_id = get_permission_id('comments:likes:delete'));
+--
+commit;
@@ -0,0 +1,14 @@
+begin;
+--
+delete from jen.role_permission_mappings
+  where role_id = jen.get_role_id('mocha-default')
+    and permission_id = jen.get_permission_id('stickers:get');
+delete from jen.permissions
+  where permission_name in ('posts:reactions:create', 'posts:reactions:get',
+                            'posts:reactions:delete', 'comments:reactions:create',
+                            'comments:reactions:get', 'comments:reactions:delete');
+--
+drop table if exists jen.comment_reactions;
+drop table if exists jen.post_reactions;
+--
+commit;
@@ -0,0 +1,61 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- post_reactions table. a reader can react to the same post with several different stickers,
+-- but only once with each
+create table if not exists post_reactions(
+  id uuid not null default uuid_generate_v4() primary key,
+  post_id uuid not null references posts(id) on delete cascade,
+  user_id uuid not null references users(id) on delete cascade,
+  sticker_id uuid not null references stickers(id) on delete cascade,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  unique (post_id, sticker_id, user_id)
+);
+create or replace trigger update_post_reactions_timestamp
+  before update on post_reactions for each row
+  execute function update_timestamp();
+--
+-- comment_reactions table
+create table if not exists comment_reactions(
+  id uuid not null default uuid_generate_v4() primary key,
+  comment_id uuid not null references comments(id) on delete cascade,
+  user_id uuid not null references users(id) on delete cascade,
+  sticker_id uuid not null references stickers(id) on delete cascade,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  unique (comment_id, sticker_id, user_id)
+);
+create or replace trigger update_comment_reactions_timestamp
+  before update on comment_reactions for each row
+  execute function update_timestamp();
+--
+create index if not exists post_reactions_idx_sticker_id on "jen"."post_reactions"(sticker_id);
+create index if not exists comment_reactions_idx_sticker_id on "jen"."comment_reactions"(sticker_id);
+--
+insert into jen.permissions(permission_name, permission_description)
+  values
+('posts:reactions:create', 'Allow a user to react to posts with stickers'),
+('posts:reactions:get', 'Allow a user to view the reactions on a post'),
+('posts:reactions:delete', 'Allow a user to remove their reactions from a post'),
+('comments:reactions:create', 'Allow a user to react to comments with stickers'),
+('comments:reactions:get', 'Allow a user to view the reactions on a comment'),
+('comments:reactions:delete', 'Allow a user to remove their reactions from a comment')
+on conflict do nothing;
+--
+-- readers have to be able to list the stickers they can react with
+insert into role_permission_mappings(role_id, permission_id)
+  select r.role_id, get_permission_id(p.permission_name)
+  from (values (get_role_id('mocha-default')), (get_role_id('mocha-admin'))) as r(role_id)
+  cross join (values ('posts:reactions:create'), ('posts:reactions:get'),
+                     ('posts:reactions:delete'), ('comments:reactions:create'),
+                     ('comments:reactions:get'), ('comments:reactions:delete'),
+                     ('stickers:get')) as p(permission_name)
+  where not exists (
+    select 1 from role_permission_mappings m
+    where m.role_id = r.role_id
+      and m.permission_id = get_permission_id(p.permission_name));
+--
+commit;
@@ -0,0 +1,20 @@
+begin;
+--
+delete from jen.permissions
+  where permission_name = 'comments:moderate';
+--
+drop table if exists jen.spam_corpus;
+drop table if exists jen.spam_tokens;
+--
+drop index if exists jen.comments_idx_user_id;
+drop index if exists jen.comments_idx_status_created_at;
+alter table jen.comments
+  drop column if exists moderated_at,
+  drop column if exists moderated_by,
+  drop column if exists trained_as,
+  drop column if exists status;
+--
+drop type if exists jen.spam_label;
+drop type if exists jen.comment_status;
+--
+commit;
@@ -0,0 +1,71 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- comment_status type
+create type comment_status as enum(
+  'pending',
+  'approved',
+  'rejected',
+  'spam'
+);
+--
+-- spam_label type
+create type spam_label as enum(
+  'spam',
+  'ham'
+);
+--
+-- every comment that already exists was published without moderation, so it starts out approved.
+-- trained_as records what the spam filter learned from a comment so the lesson can be taken back
+-- if a moderator changes their mind
+alter table comments
+  add column if not exists status comment_status not null default 'approved',
+  add column if not exists trained_as spam_label,
+  add column if not exists moderated_by uuid references users(id) on delete set null,
+  add column if not exists moderated_at timestamptz;
+alter table comments
+  alter column status set default 'pending';
+--
+create index if not exists comments_idx_status_created_at on "jen"."comments"("status", "created_at")
+  where status <> 'approved';
+create index if not exists comments_idx_user_id on "jen"."comments"("user_id");
+--
+-- naive bayes token counts. each comment counts at most once per token
+create table if not exists spam_tokens(
+  token text not null primary key,
+  spam_count int not null default 0,
+  ham_count int not null default 0,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create or replace trigger update_spam_tokens_timestamp
+  before update on spam_tokens for each row
+  execute function update_timestamp();
+--
+-- how many comments the filter has been trained on, per label
+create table if not exists spam_corpus(
+  label spam_label not null primary key,
+  documents int not null default 0,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create or replace trigger update_spam_corpus_timestamp
+  before update on spam_corpus for each row
+  execute function update_timestamp();
+insert into spam_corpus(label)
+  values ('spam'), ('ham')
+on conflict do nothing;
+--
+insert into jen.permissions(permission_name, permission_description)
+  values ('comments:moderate', 'Allow a user to approve, reject and flag comments as spam')
+on conflict do nothing;
+insert into role_permission_mappings(role_id, permission_id)
+  select get_role_id('mocha-admin'), get_permission_id('comments:moderate')
+  where not exists (
+    select 1 from role_permission_mappings
+    where role_id = get_role_id('mocha-admin')
+      and permission_id = get_permission_id('comments:moderate'));
+--
+commit;
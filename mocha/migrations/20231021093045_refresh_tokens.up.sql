@@ -0,0 +1,29 @@
+begin;
+--
+-- search path
+set search_path to jen;
+--
+-- refresh_tokens table. tokens are opaque to clients and only their sha256 is stored. every
+-- token is used exactly once and replaced by a child in the same family; presenting a used token
+-- again means it leaked, so the whole family is revoked
+create table if not exists refresh_tokens(
+  id uuid not null default uuid_generate_v4() primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  family_id uuid not null,
+  parent_id uuid references refresh_tokens(id) on delete set null,
+  token_hash text not null,
+  expires_at timestamptz not null,
+  used_at timestamptz,
+  revoked_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  unique (token_hash)
+);
+create or replace trigger update_refresh_tokens_timestamp
+  before update on refresh_tokens for each row
+  execute function update_timestamp();
+--
+create index if not exists refresh_tokens_idx_family_id on "jen"."refresh_tokens"("family_id");
+create index if not exists refresh_tokens_idx_user_id on "jen"."refresh_tokens"("user_id");
+--
+commit;
@@ -0,0 +1,6 @@
+begin;
+--
+delete from jen.permissions
+  where permission_name = 'users:tokens:revoke';
+--
+commit;
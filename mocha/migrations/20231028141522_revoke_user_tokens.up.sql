@@ -0,0 +1,15 @@
+begin;
+--
+set search_path to jen;
+--
+insert into permissions(permission_name, permission_description)
+  values ('users:tokens:revoke', 'Allow a user to sign another user out of every device')
+on conflict do nothing;
+insert into role_permission_mappings(role_id, permission_id)
+  select get_role_id('mocha-admin'), get_permission_id('users:tokens:revoke')
+  where not exists (
+    select 1 from role_permission_mappings
+    where role_id = get_role_id('mocha-admin')
+      and permission_id = get_permission_id('users:tokens:revoke'));
+--
+commit;
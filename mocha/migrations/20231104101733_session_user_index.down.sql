@@ -0,0 +1,5 @@
+begin;
+--
+drop index if exists jen.sessions_idx_user_id;
+--
+commit;
@@ -0,0 +1,7 @@
+begin;
+--
+set search_path to jen;
+--
+create index if not exists sessions_idx_user_id on sessions(user_id, updated_at);
+--
+commit;
@@ -0,0 +1,6 @@
+begin;
+--
+drop index if exists jen.sessions_idx_updated_at;
+drop index if exists jen.sessions_idx_created_at;
+--
+commit;
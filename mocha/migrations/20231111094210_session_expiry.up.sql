@@ -0,0 +1,9 @@
+begin;
+--
+set search_path to jen;
+--
+-- the session sweeper deletes by age and by inactivity
+create index if not exists sessions_idx_created_at on sessions(created_at);
+create index if not exists sessions_idx_updated_at on sessions(updated_at);
+--
+commit;
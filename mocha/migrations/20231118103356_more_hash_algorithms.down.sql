@@ -0,0 +1,16 @@
+begin;
+--
+set search_path to jen;
+--
+-- postgres can't drop values from an enum, so the type is rebuilt. this fails on purpose while
+-- any credentials still use scrypt or pbkdf2
+alter type hash_algorithm rename to hash_algorithm_old;
+create type hash_algorithm as enum(
+  'argon2',
+  'bcrypt'
+);
+alter table user_credentials
+  alter column alg type hash_algorithm using alg::text::hash_algorithm;
+drop type hash_algorithm_old;
+--
+commit;
@@ -0,0 +1,8 @@
+begin;
+--
+set search_path to jen;
+--
+alter type hash_algorithm add value if not exists 'scrypt';
+alter type hash_algorithm add value if not exists 'pbkdf2';
+--
+commit;
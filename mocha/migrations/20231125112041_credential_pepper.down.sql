@@ -0,0 +1,6 @@
+begin;
+--
+alter table jen.user_credentials
+  drop column if exists pepper_id;
+--
+commit;
@@ -0,0 +1,10 @@
+begin;
+--
+set search_path to jen;
+--
+-- id of the server side pepper a credential was hashed with. null means it wasn't peppered.
+-- the pepper itself never touches the database
+alter table user_credentials
+  add column if not exists pepper_id text;
+--
+commit;
@@ -0,0 +1,20 @@
+begin;
+--
+set search_path to jen;
+--
+-- only a hash of each reset token is kept, like refresh tokens
+create table if not exists password_reset_tokens(
+  id uuid not null default uuid_generate_v4() primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  token_hash text not null unique,
+  expires_at timestamptz not null,
+  used_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create index if not exists password_reset_tokens_idx_user_id on password_reset_tokens(user_id);
+create or replace trigger update_password_reset_tokens_timestamp
+  before update on password_reset_tokens for each row
+  execute function update_timestamp();
+--
+commit;
@@ -0,0 +1,22 @@
+begin;
+--
+set search_path to jen;
+--
+insert into role_permission_mappings(role_id, permission_id)
+  select get_role_id('mocha-default'), get_permission_id(p.permission_name)
+  from (values ('comments:create'), ('comments:edit'), ('comments:likes:create'),
+               ('posts:likes:create')) as p(permission_name)
+  where not exists (
+    select 1 from role_permission_mappings m
+    where m.role_id = get_role_id('mocha-default')
+      and m.permission_id = get_permission_id(p.permission_name));
+--
+delete from roles where role_name = 'mocha-verified';
+--
+drop table if exists email_change_requests;
+drop table if exists email_verification_tokens;
+--
+alter table users
+  drop column if exists email_verified_at;
+--
+commit;
@@ -0,0 +1,75 @@
+begin;
+--
+set search_path to jen;
+--
+-- null until the user follows the link in their verification email
+alter table users
+  add column if not exists email_verified_at timestamptz;
+--
+-- verification tokens are tied to the address they were mailed to, so a link sent before an email
+-- change can't verify the new address
+create table if not exists email_verification_tokens(
+  id uuid not null default uuid_generate_v4() primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  email text not null,
+  token_hash text not null unique,
+  expires_at timestamptz not null,
+  used_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create index if not exists email_verification_tokens_idx_user_id on email_verification_tokens(user_id);
+create or replace trigger update_email_verification_tokens_timestamp
+  before update on email_verification_tokens for each row
+  execute function update_timestamp();
+--
+-- an email change goes through once both the current and the new address have confirmed it. each
+-- address gets its own token
+create table if not exists email_change_requests(
+  id uuid not null default uuid_generate_v4() primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  new_email text not null,
+  old_token_hash text not null unique,
+  new_token_hash text not null unique,
+  old_confirmed_at timestamptz,
+  new_confirmed_at timestamptz,
+  expires_at timestamptz not null,
+  completed_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create index if not exists email_change_requests_idx_user_id on email_change_requests(user_id);
+create or replace trigger update_email_change_requests_timestamp
+  before update on email_change_requests for each row
+  execute function update_timestamp();
+--
+-- commenting and liking move from the default role to one that is only granted once the user's
+-- email has been verified
+insert into roles(role_name, role_description)
+  values ('mocha-verified', 'Granted once a user has verified their email. Holds the permissions that let a user interact with other people.')
+on conflict do nothing;
+--
+insert into role_permission_mappings(role_id, permission_id)
+  select get_role_id('mocha-verified'), get_permission_id(p.permission_name)
+  from (values ('comments:create'), ('comments:edit'), ('comments:likes:create'),
+               ('posts:likes:create')) as p(permission_name)
+  where not exists (
+    select 1 from role_permission_mappings m
+    where m.role_id = get_role_id('mocha-verified')
+      and m.permission_id = get_permission_id(p.permission_name));
+--
+-- accounts that exist before verification was introduced are trusted as they are, so they keep
+-- the permissions they already had
+update users set email_verified_at = current_timestamp
+  where email_verified_at is null;
+insert into user_role_mappings(user_id, role_id)
+  select u.id, get_role_id('mocha-verified') from users u
+on conflict do nothing;
+--
+delete from role_permission_mappings
+  where role_id = get_role_id('mocha-default')
+    and permission_id in (select id from permissions
+                          where permission_name in ('comments:create', 'comments:edit',
+                                                    'comments:likes:create', 'posts:likes:create'));
+--
+commit;
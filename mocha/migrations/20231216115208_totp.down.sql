@@ -0,0 +1,7 @@
+begin;
+--
+drop table if exists jen.login_challenges;
+drop table if exists jen.totp_recovery_codes;
+drop table if exists jen.user_totp;
+--
+commit;
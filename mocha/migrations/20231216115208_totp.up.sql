@@ -0,0 +1,51 @@
+begin;
+--
+set search_path to jen;
+--
+-- one authenticator per user. the secret is encrypted by the app before it gets here, and stays
+-- unconfirmed until the user proves their app generates the right codes
+create table if not exists user_totp(
+  user_id uuid not null primary key references users(id) on delete cascade,
+  secret bytea not null,
+  confirmed_at timestamptz,
+  -- the time step of the last code accepted, so a code can't be replayed within its window
+  last_used_step bigint,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create or replace trigger update_user_totp_timestamp
+  before update on user_totp for each row
+  execute function update_timestamp();
+--
+create table if not exists totp_recovery_codes(
+  id uuid not null default uuid_generate_v4() primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  code_hash text not null,
+  used_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  unique (user_id, code_hash)
+);
+create or replace trigger update_totp_recovery_codes_timestamp
+  before update on totp_recovery_codes for each row
+  execute function update_timestamp();
+--
+-- a password that checked out for an account with a second factor. it is traded for a session
+-- once the second factor checks out too
+create table if not exists login_challenges(
+  id uuid not null default uuid_generate_v4() primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  token_hash text not null unique,
+  data jsonb not null default '{}'::jsonb,
+  attempts int not null default 0,
+  expires_at timestamptz not null,
+  used_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create index if not exists login_challenges_idx_user_id on login_challenges(user_id);
+create or replace trigger update_login_challenges_timestamp
+  before update on login_challenges for each row
+  execute function update_timestamp();
+--
+commit;
@@ -0,0 +1,7 @@
+begin;
+--
+drop table if exists jen.passkey_challenges;
+drop table if exists jen.user_passkeys;
+drop type if exists jen.passkey_ceremony;
+--
+commit;
@@ -0,0 +1,46 @@
+begin;
+--
+set search_path to jen;
+--
+-- webauthn credentials. the public key is stored as DER so it can be loaded without knowing
+-- which COSE algorithm it came from
+create table if not exists user_passkeys(
+  id uuid not null default uuid_generate_v4() primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  credential_id text not null unique,
+  public_key bytea not null,
+  algorithm int not null,
+  sign_count bigint not null default 0,
+  aaguid uuid not null,
+  name text not null default 'Passkey',
+  last_used_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create index if not exists user_passkeys_idx_user_id on user_passkeys(user_id);
+create or replace trigger update_user_passkeys_timestamp
+  before update on user_passkeys for each row
+  execute function update_timestamp();
+--
+create type passkey_ceremony as enum(
+  'registration',
+  'authentication'
+);
+--
+-- challenges handed to the browser for a ceremony. registration challenges belong to the signed
+-- in user; authentication challenges don't belong to anyone until a passkey answers them
+create table if not exists passkey_challenges(
+  id uuid not null default uuid_generate_v4() primary key,
+  user_id uuid references users(id) on delete cascade,
+  ceremony passkey_ceremony not null,
+  challenge text not null unique,
+  expires_at timestamptz not null,
+  used_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create or replace trigger update_passkey_challenges_timestamp
+  before update on passkey_challenges for each row
+  execute function update_timestamp();
+--
+commit;
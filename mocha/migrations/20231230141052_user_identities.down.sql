@@ -0,0 +1,6 @@
+begin;
+--
+drop table if exists jen.oidc_states;
+drop table if exists jen.user_identities;
+--
+commit;
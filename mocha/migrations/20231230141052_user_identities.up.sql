@@ -0,0 +1,42 @@
+begin;
+--
+set search_path to jen;
+--
+-- accounts at external openid connect providers, linked to the user they sign in as. the subject
+-- is the provider's stable id for the account; the email is only kept for display
+create table if not exists user_identities(
+  id uuid not null default uuid_generate_v4() primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  provider text not null,
+  subject text not null,
+  email text,
+  last_used_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp,
+  unique (provider, subject)
+);
+create index if not exists user_identities_idx_user_id on user_identities(user_id);
+create or replace trigger update_user_identities_timestamp
+  before update on user_identities for each row
+  execute function update_timestamp();
+--
+-- a trip to a provider and back. the state is the lookup key and comes back hashed; the nonce
+-- and pkce verifier tie the provider's answer to this trip. states started by a signed in user
+-- link the account they come back with instead of signing in with it
+create table if not exists oidc_states(
+  id uuid not null default uuid_generate_v4() primary key,
+  provider text not null,
+  state_hash text not null unique,
+  nonce text not null,
+  code_verifier text not null,
+  user_id uuid references users(id) on delete cascade,
+  expires_at timestamptz not null,
+  used_at timestamptz,
+  created_at timestamptz not null default current_timestamp,
+  updated_at timestamptz not null default current_timestamp
+);
+create or replace trigger update_oidc_states_timestamp
+  before update on oidc_states for each row
+  execute function update_timestamp();
+--
+commit;
@@ -0,0 +1,7 @@
+begin;
+--
+drop index if exists jen.refresh_tokens_idx_session_id;
+alter table jen.refresh_tokens
+  drop column if exists session_id;
+--
+commit;
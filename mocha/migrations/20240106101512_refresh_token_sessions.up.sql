@@ -0,0 +1,11 @@
+begin;
+--
+set search_path to jen;
+--
+-- the session a refresh token family was issued alongside, so ending the session revokes the
+-- family too. sessions may live in redis, so there's no foreign key
+alter table refresh_tokens
+  add column if not exists session_id uuid;
+create index if not exists refresh_tokens_idx_session_id on refresh_tokens(session_id);
+--
+commit;
@@ -0,0 +1,7 @@
+begin;
+--
+alter table jen.user_totp
+  drop column if exists failed_attempts,
+  drop column if exists locked_until;
+--
+commit;
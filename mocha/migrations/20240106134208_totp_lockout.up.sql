@@ -0,0 +1,11 @@
+begin;
+--
+set search_path to jen;
+--
+-- wrong second factors across every login challenge, so getting a fresh challenge doesn't reset
+-- the count. the authenticator is locked for a while once too many pile up
+alter table user_totp
+  add column if not exists failed_attempts int not null default 0,
+  add column if not exists locked_until timestamptz;
+--
+commit;
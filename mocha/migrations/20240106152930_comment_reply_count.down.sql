@@ -0,0 +1,15 @@
+begin;
+--
+create or replace trigger update_comments_timestamp
+  before update on jen.comments for each row
+  when (old.like_count = new.like_count)
+  execute function jen.update_timestamp();
+--
+drop trigger if exists count_comment_replies_on_moderation on jen.comments;
+drop trigger if exists count_comment_replies on jen.comments;
+drop function if exists jen.count_comment_replies();
+--
+alter table jen.comments
+  drop column if exists reply_count;
+--
+commit;
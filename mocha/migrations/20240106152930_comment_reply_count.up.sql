@@ -0,0 +1,43 @@
+begin;
+--
+set search_path to jen;
+--
+-- approved replies are denormalized onto their parent the same way likes are, so comment listings
+-- and the moderation queue never have to aggregate
+alter table comments
+  add column if not exists reply_count int not null default 0;
+update comments c set reply_count = r.replies
+  from (select parent_id, count(*) as replies from comments
+        where parent_id is not null and status = 'approved'
+        group by parent_id) r
+  where r.parent_id = c.id;
+--
+create or replace function count_comment_replies()
+  returns trigger
+  as $$
+begin
+  if tg_op <> 'INSERT' and old.parent_id is not null and old.status = 'approved' then
+    update jen.comments set reply_count = reply_count - 1 where id = old.parent_id;
+  end if;
+  if tg_op <> 'DELETE' and new.parent_id is not null and new.status = 'approved' then
+    update jen.comments set reply_count = reply_count + 1 where id = new.parent_id;
+  end if;
+  return null;
+end;
+$$
+language plpgsql;
+create or replace trigger count_comment_replies
+  after insert or delete on comments for each row
+  execute function count_comment_replies();
+create or replace trigger count_comment_replies_on_moderation
+  after update of status on comments for each row
+  when (old.status is distinct from new.status)
+  execute function count_comment_replies();
+--
+-- a new reply is not an edit either
+create or replace trigger update_comments_timestamp
+  before update on comments for each row
+  when (old.like_count = new.like_count and old.reply_count = new.reply_count)
+  execute function update_timestamp();
+--
+commit;
@@ -0,0 +1,184 @@
+use std::time::SystemTime;
+
+use actix_web::{
+    web::{Data, Path},
+    HttpRequest, HttpResponse,
+};
+
+use uuid::Uuid;
+
+use crate::app::{
+    dto::{feeds::GetFeedItems, spaces::GetSpaceById, tags::GetTagById},
+    entities::feeds::FeedItem,
+    errors::AppError,
+    feeds::{self, Feed, FEED_LENGTH},
+    state::AppState,
+    storage::postgres,
+    util,
+};
+
+enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+async fn load_items(state: &AppState, dto: GetFeedItems) -> Result<Vec<FeedItem>, AppError> {
+    postgres::feeds::get_feed_items(&state.storage_layer.pg, dto, FEED_LENGTH)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })
+}
+
+async fn blog_feed(state: &AppState) -> Result<Feed, AppError> {
+    let site_url = &state.config.site_url;
+    let items = load_items(
+        state,
+        GetFeedItems {
+            space_id: None,
+            tag_id: None,
+        },
+    )
+    .await?;
+
+    Ok(Feed {
+        title: state.config.name.clone(),
+        description: format!("Everything published on {}", state.config.name),
+        link: site_url.clone(),
+        self_url: format!("{site_url}/feeds"),
+        items,
+    })
+}
+
+async fn space_feed(state: &AppState, space_id: String) -> Result<Feed, AppError> {
+    let site_url = &state.config.site_url;
+    Uuid::parse_str(&space_id).map_err(|_| AppError::NotFound)?;
+    let space = postgres::spaces::get_space_by_id(
+        &state.storage_layer.pg,
+        GetSpaceById {
+            id: space_id.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::NotFound)?;
+
+    let items = load_items(
+        state,
+        GetFeedItems {
+            space_id: Some(space_id),
+            tag_id: None,
+        },
+    )
+    .await?;
+
+    Ok(Feed {
+        title: space.space_name,
+        description: space.bio,
+        link: feeds::space_url(site_url, &space.id),
+        self_url: format!("{site_url}/feeds/spaces/{}", space.id),
+        items,
+    })
+}
+
+async fn tag_feed(state: &AppState, tag_id: String) -> Result<Feed, AppError> {
+    let site_url = &state.config.site_url;
+    Uuid::parse_str(&tag_id).map_err(|_| AppError::NotFound)?;
+    let tag =
+        postgres::spaces::get_tag_by_id(&state.storage_layer.pg, GetTagById { id: tag_id.clone() })
+            .await
+            .map_err(|e| {
+                log::error!("{e}");
+                AppError::InternalServerError
+            })?
+            .ok_or(AppError::NotFound)?;
+
+    let items = load_items(
+        state,
+        GetFeedItems {
+            space_id: None,
+            tag_id: Some(tag_id),
+        },
+    )
+    .await?;
+
+    Ok(Feed {
+        title: tag.tag_name,
+        description: tag.tag_description,
+        link: feeds::tag_url(site_url, &tag.id),
+        self_url: format!("{site_url}/feeds/tags/{}", tag.id),
+        items,
+    })
+}
+
+/// Render a feed, or answer with a 304 if the reader already has the current version
+fn respond(req: &HttpRequest, state: &AppState, feed: Feed, format: FeedFormat) -> HttpResponse {
+    let (body, content_type) = match format {
+        FeedFormat::Rss => (
+            feeds::rss(&feed, &state.config.site_url),
+            "application/rss+xml; charset=utf-8",
+        ),
+        FeedFormat::Atom => (
+            feeds::atom(&feed, &state.config.site_url),
+            "application/atom+xml; charset=utf-8",
+        ),
+    };
+
+    util::http::conditional(req, body, content_type, SystemTime::from(feed.updated()))
+}
+
+pub async fn rss(
+    req: HttpRequest,
+    state: Data<AppState>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let feed = blog_feed(&state).await?;
+    Ok(respond(&req, &state, feed, FeedFormat::Rss))
+}
+
+pub async fn atom(
+    req: HttpRequest,
+    state: Data<AppState>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let feed = blog_feed(&state).await?;
+    Ok(respond(&req, &state, feed, FeedFormat::Atom))
+}
+
+pub async fn space_rss(
+    req: HttpRequest,
+    state: Data<AppState>,
+    space: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let feed = space_feed(&state, space.into_inner()).await?;
+    Ok(respond(&req, &state, feed, FeedFormat::Rss))
+}
+
+pub async fn space_atom(
+    req: HttpRequest,
+    state: Data<AppState>,
+    space: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let feed = space_feed(&state, space.into_inner()).await?;
+    Ok(respond(&req, &state, feed, FeedFormat::Atom))
+}
+
+pub async fn tag_rss(
+    req: HttpRequest,
+    state: Data<AppState>,
+    tag: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let feed = tag_feed(&state, tag.into_inner()).await?;
+    Ok(respond(&req, &state, feed, FeedFormat::Rss))
+}
+
+pub async fn tag_atom(
+    req: HttpRequest,
+    state: Data<AppState>,
+    tag: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let feed = tag_feed(&state, tag.into_inner()).await?;
+    Ok(respond(&req, &state, feed, FeedFormat::Atom))
+}
@@ -0,0 +1,22 @@
+mod controllers;
+
+use actix_web::web::{self, ServiceConfig};
+
+// feeds only ever contain published, public posts, so readers don't need an account
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/feeds")
+            .route("/rss.xml", web::get().to(controllers::rss))
+            .route("/atom.xml", web::get().to(controllers::atom))
+            .route(
+                "/spaces/{space}/rss.xml",
+                web::get().to(controllers::space_rss),
+            )
+            .route(
+                "/spaces/{space}/atom.xml",
+                web::get().to(controllers::space_atom),
+            )
+            .route("/tags/{tag}/rss.xml", web::get().to(controllers::tag_rss))
+            .route("/tags/{tag}/atom.xml", web::get().to(controllers::tag_atom)),
+    );
+}
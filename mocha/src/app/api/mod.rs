@@ -1 +1,3 @@
+pub mod feeds;
+pub mod sitemap;
 pub mod v1;
@@ -0,0 +1,108 @@
+use std::time::SystemTime;
+
+use actix_web::{
+    web::{Data, Path},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+
+use crate::app::{
+    dto::sitemap::GetSitemapPage,
+    entities::sitemap::{SitemapEntry, SitemapPage},
+    errors::AppError,
+    sitemap::{self, SITEMAP_PAGE_SIZE},
+    state::AppState,
+    storage::postgres,
+    util,
+};
+
+const XML: &str = "application/xml; charset=utf-8";
+
+async fn load_pages(state: &AppState) -> Result<Vec<SitemapPage>, AppError> {
+    postgres::sitemap::get_sitemap_pages(&state.storage_layer.pg, SITEMAP_PAGE_SIZE)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })
+}
+
+async fn load_entries(state: &AppState, page: i64) -> Result<Vec<SitemapEntry>, AppError> {
+    postgres::sitemap::get_sitemap_entries(
+        &state.storage_layer.pg,
+        GetSitemapPage { page },
+        SITEMAP_PAGE_SIZE,
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })
+}
+
+pub async fn robots(state: Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(sitemap::robots(
+            &state.config.launch_mode,
+            &state.config.site_url,
+        ))
+}
+
+/// A small site is served as a single sitemap. Once it outgrows one file this becomes an index of
+/// the pages under /sitemaps instead.
+pub async fn sitemap(
+    req: HttpRequest,
+    state: Data<AppState>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let pages = load_pages(&state).await?;
+    let updated = pages
+        .iter()
+        .map(|page| page.updated_at)
+        .max()
+        .unwrap_or_default();
+
+    let body = if pages.len() > 1 {
+        sitemap::index(&pages, &state.config.site_url)
+    } else {
+        let entries = load_entries(&state, 0).await?;
+        sitemap::urlset(&entries, &state.config.site_url)
+    };
+
+    Ok(util::http::conditional(
+        &req,
+        body,
+        XML,
+        SystemTime::from(updated),
+    ))
+}
+
+pub async fn sitemap_page(
+    req: HttpRequest,
+    state: Data<AppState>,
+    page: Path<i64>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let page = page.into_inner();
+    if page < 0 {
+        return Err(AppError::NotFound);
+    }
+
+    let entries = load_entries(&state, page).await?;
+    if entries.is_empty() {
+        return Err(AppError::NotFound);
+    }
+
+    let updated: DateTime<Utc> = entries
+        .iter()
+        .map(|entry| entry.updated_at)
+        .max()
+        .unwrap_or_default();
+    let body = sitemap::urlset(&entries, &state.config.site_url);
+
+    Ok(util::http::conditional(
+        &req,
+        body,
+        XML,
+        SystemTime::from(updated),
+    ))
+}
@@ -0,0 +1,12 @@
+mod controllers;
+
+use actix_web::web::{self, ServiceConfig};
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.route("/robots.txt", web::get().to(controllers::robots))
+        .route("/sitemap.xml", web::get().to(controllers::sitemap))
+        .route(
+            "/sitemaps/{page}.xml",
+            web::get().to(controllers::sitemap_page),
+        );
+}
@@ -0,0 +1,110 @@
+use actix_web::{
+    web::{Data, Json, Path, ReqData},
+    HttpResponse,
+};
+use actix_web_grants::proc_macro::has_permissions;
+
+use crate::app::{
+    auth::tokens::{self, Claims},
+    dto::{
+        comments::ModerateComment,
+        pagination::{ModerationPaginationOptions, PaginationLimits},
+    },
+    errors::AppError,
+    state::AppState,
+    storage::postgres,
+    types::CommentStatus,
+    util,
+};
+use uuid::Uuid;
+
+#[has_permissions("comments:moderate")]
+pub async fn get_moderation_queue(
+    state: Data<AppState>,
+    pagination: Json<PaginationLimits<ModerationPaginationOptions>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let comments =
+        postgres::comments::get_moderation_queue(&state.storage_layer.pg, pagination.into_inner())
+            .await
+            .map_err(|e| {
+                log::error!("{e}");
+                AppError::InternalServerError
+            })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "comments": comments })))
+}
+
+async fn moderate(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    comment: Path<String>,
+    status: CommentStatus,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = ModerateComment {
+        moderator_id: claim_data.sub,
+        comment_id: comment.into_inner(),
+        status,
+    };
+
+    match postgres::comments::moderate_comment(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })? {
+        1 => Ok(HttpResponse::Ok()
+            .json(serde_json::json!({"msg": "successfully moderated comment", "status": status}))),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("comments:moderate")]
+pub async fn approve_comment(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    comment: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    moderate(state, claims, comment, CommentStatus::Approved).await
+}
+
+#[has_permissions("comments:moderate")]
+pub async fn reject_comment(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    comment: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    moderate(state, claims, comment, CommentStatus::Rejected).await
+}
+
+#[has_permissions("comments:moderate")]
+pub async fn mark_comment_as_spam(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    comment: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    moderate(state, claims, comment, CommentStatus::Spam).await
+}
+
+#[has_permissions("users:tokens:revoke")]
+pub async fn revoke_user_tokens(
+    state: Data<AppState>,
+    user: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let user_id = Uuid::parse_str(&user.into_inner()).map_err(|_| AppError::NotFound)?;
+
+    // nobody is signing back in straight away here, so tokens from this very second go too
+    tokens::revoke_user_tokens(
+        &state.storage_layer,
+        &state.session_manager,
+        &user_id.to_string(),
+        util::time::now() + 1,
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({"msg": "successfully revoked user tokens"})))
+}
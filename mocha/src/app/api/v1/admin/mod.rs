@@ -0,0 +1,37 @@
+mod controllers;
+
+use actix_web::web::{self, ServiceConfig};
+use actix_web_httpauth::middleware::HttpAuthentication;
+
+use crate::app::guards;
+
+pub fn config(cfg: &mut ServiceConfig) {
+    let session = HttpAuthentication::with_fn(guards::session_guard);
+    let jwt = HttpAuthentication::bearer(guards::jwt_guard);
+
+    cfg.service(
+        web::scope("/admin")
+            .wrap(session)
+            .wrap(jwt)
+            .route(
+                "/comments",
+                web::get().to(controllers::get_moderation_queue),
+            )
+            .route(
+                "/comments/{comment}/approve",
+                web::post().to(controllers::approve_comment),
+            )
+            .route(
+                "/comments/{comment}/reject",
+                web::post().to(controllers::reject_comment),
+            )
+            .route(
+                "/comments/{comment}/spam",
+                web::post().to(controllers::mark_comment_as_spam),
+            )
+            .route(
+                "/users/{user}/tokens/revoke",
+                web::post().to(controllers::revoke_user_tokens),
+            ),
+    );
+}
@@ -1,29 +1,65 @@
 use actix_web::{
     cookie::{
         time::{Duration, OffsetDateTime},
-        Cookie,
+        Cookie, SameSite,
     },
-    web::{Data, Json, ReqData},
+    http::header::USER_AGENT,
+    web::{Data, Json, Path, ReqData},
     HttpRequest, HttpResponse,
 };
 
+use std::error::Error;
+
 use crate::app::{
     dto::{
-        auth::{CreateSession, DeleteSession, LoginUser, RegisterUser},
-        users::{CreateUser, GetUserByEmail},
+        auth::{
+            AttemptLoginChallenge, AuthenticationCredential, ChangeEmailRequest,
+            ConfirmEmailChange, ConfirmTotp, CreateEmailChange, CreateEmailVerificationToken,
+            CreateLoginChallenge, CreateOidcState, CreatePasskey, CreatePasskeyChallenge,
+            CreatePasswordResetToken, CreateSession, CreateTotp, CreateUserIdentity,
+            CreateUserWithIdentity, DeletePasskey, DeleteSession, DeleteTotp, DeleteUserIdentity,
+            DeleteUserSessions, EmailTokenRequest, FinishPasskeyRegistration, ForgotPassword,
+            GetPasskeyByCredentialId, GetSessionById, GetSessionsByUserId, GetTotp,
+            GetUserIdentities, GetUserIdentity, GetUserPasskeys, LoginUser, OidcCallback,
+            RefreshTokenRequest, RegisterUser, ReserveTotpAttempt, ResetPassword,
+            ResetPasswordRequest, RevokeRefreshTokenFamily, RotateRefreshToken, SecondFactor,
+            TotpCode, TotpLogin, UseOidcState, UsePasskey, UsePasskeyChallenge, UseRecoveryCode,
+            UseTotpStep, UseUserIdentity, VerifyEmail,
+        },
+        users::{CreateUser, GetUserByEmail, GetUserById, UpdateUserCredentials},
+    },
+    entities::auth::{
+        ActiveSession, EmailChangeOutcome, OidcState, RefreshOutcome, Session, UserTotp,
     },
-    entities::auth::Session,
     errors::AppError,
     launch::LaunchMode,
+    mail::Email,
     state::AppState,
     storage::postgres,
+    util,
+};
+
+use crate::app::auth::{
+    oidc::{self, ExternalIdentity, OidcError},
+    tokens::{self, Claims},
+    totp,
+    webauthn::{self, Ceremony},
 };
 
-use crate::app::auth::tokens::Claims;
+fn device_info(req: &HttpRequest) -> serde_json::Value {
+    let user_agent = req
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|ua| ua.to_str().ok());
+    let connection_info = req.connection_info();
+    let ip = connection_info.realip_remote_addr();
+    serde_json::json!({ "user_agent": user_agent, "ip": ip })
+}
 
 pub async fn register(
     state: Data<AppState>,
     data: Json<RegisterUser>,
+    req: HttpRequest,
 ) -> actix_web::Result<HttpResponse, AppError> {
     // Do NOT allow registration in production.
     if state.config.launch_mode == LaunchMode::Production {
@@ -32,6 +68,8 @@ pub async fn register(
     };
 
     let raw_data = data.into_inner();
+    let first_name = raw_data.first_name.clone();
+    let email = raw_data.email.clone();
 
     let alg = state.credential_manager.algorithm.clone();
 
@@ -43,6 +81,7 @@ pub async fn register(
         image_uri: raw_data.image_uri,
         hashed_password: None,
         algorithm: None,
+        pepper_id: None,
     };
 
     if let Some(plaintext) = raw_data.password {
@@ -52,6 +91,7 @@ pub async fn register(
             .map_err(|_| AppError::InternalServerError)?;
         dto.hashed_password = Some(hashed_password);
         dto.algorithm = Some(alg);
+        dto.pepper_id = state.credential_manager.pepper_id();
     }
 
     let new_user_id = postgres::users::create_user(&state.storage_layer.pg, dto)
@@ -61,13 +101,18 @@ pub async fn register(
             AppError::InternalServerError
         })?;
 
+    // the account works without a verified email, so a mail failure shouldn't fail the signup
+    if let Err(e) = send_verification_email(&state, &new_user_id, &first_name, &email).await {
+        log::error!("error sending verification email: {e}");
+    }
+
     let session_id = state
         .session_manager
         .start_session(
             &state.storage_layer,
             CreateSession {
                 user_id: new_user_id.clone(),
-                data: serde_json::json!({}),
+                data: device_info(&req),
                 created_at: chrono::offset::Utc::now(),
                 updated_at: chrono::offset::Utc::now(),
             },
@@ -87,9 +132,16 @@ pub async fn register(
         .await
         .map_err(|_| AppError::InternalServerError)?;
 
-    let mut res = HttpResponse::Ok().json(
-        serde_json::json!({"msg": "successfully created new user", "access_token": access_token}),
-    );
+    let refresh_token =
+        tokens::issue_refresh_token(&state.storage_layer, &new_user_id, &session_id)
+            .await
+            .map_err(|_| AppError::InternalServerError)?;
+
+    let mut res = HttpResponse::Ok().json(serde_json::json!({
+        "msg": "successfully created new user",
+        "access_token": access_token,
+        "refresh_token": refresh_token
+    }));
 
     let mut cookie = Cookie::new("mocha_session", &session_cookie);
     cookie.set_http_only(true);
@@ -135,6 +187,188 @@ pub async fn token(
     }
 }
 
+/// Trade a refresh token for a new access token and a replacement refresh token
+pub async fn refresh(
+    state: Data<AppState>,
+    data: Json<RefreshTokenRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let (refresh_token, new_token_hash) = tokens::new_refresh_token();
+    let dto = RotateRefreshToken {
+        token_hash: tokens::hash_token(&data.refresh_token),
+        new_token_hash,
+        expires_in: tokens::REFRESH_TOKEN_LIFETIME as i64,
+    };
+
+    match postgres::tokens::rotate_refresh_token(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })? {
+        RefreshOutcome::Rotated { user_id } => {
+            let access_token = Claims::new_signed(&state.storage_layer, &user_id.to_string())
+                .await
+                .map_err(|_| AppError::InternalServerError)?;
+
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "access_token": access_token,
+                "refresh_token": refresh_token
+            })))
+        }
+        RefreshOutcome::Reused => {
+            log::warn!("a refresh token was used twice. its token family has been revoked");
+            Err(AppError::Unauthorized)
+        }
+        RefreshOutcome::Invalid => Err(AppError::Unauthorized),
+    }
+}
+
+pub async fn revoke(
+    state: Data<AppState>,
+    data: Json<RefreshTokenRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let dto = RevokeRefreshTokenFamily {
+        token_hash: tokens::hash_token(&data.refresh_token),
+    };
+
+    match postgres::tokens::revoke_refresh_token_family(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+    {
+        0 => Err(AppError::NotFound),
+        _ => Ok(HttpResponse::NoContent().finish()),
+    }
+}
+
+async fn upgrade_hash(state: &AppState, user_id: &str, plaintext: &str) {
+    let hashed_password = match state.credential_manager.create_hash(plaintext.as_bytes()) {
+        Ok(hash) => hash,
+        Err(e) => {
+            log::error!("error rehashing credentials: {e}");
+            return;
+        }
+    };
+
+    let dto = UpdateUserCredentials {
+        user_id: user_id.to_owned(),
+        hashed_password,
+        algorithm: state.credential_manager.algorithm.clone(),
+        pepper_id: state.credential_manager.pepper_id(),
+    };
+    if let Err(e) = postgres::users::update_user_credentials(&state.storage_layer.pg, dto).await {
+        log::error!("error storing rehashed credentials: {e}");
+    }
+}
+
+async fn sign_in(
+    state: &AppState,
+    user_id: &str,
+    device: serde_json::Value,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let access_token = Claims::new_signed(&state.storage_layer, user_id)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let session_id = state
+        .session_manager
+        .start_session(
+            &state.storage_layer,
+            CreateSession {
+                user_id: user_id.to_owned(),
+                data: device,
+                created_at: chrono::offset::Utc::now(),
+                updated_at: chrono::offset::Utc::now(),
+            },
+        )
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let refresh_token = tokens::issue_refresh_token(&state.storage_layer, user_id, &session_id)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let session_cookie = state
+        .session_manager
+        .create_signed_cookie(&session_id)
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let mut res = HttpResponse::Ok().json(serde_json::json!({
+        "access_token": access_token,
+        "refresh_token": refresh_token
+    }));
+
+    let mut cookie = Cookie::new("mocha_session", &session_cookie);
+    cookie.set_http_only(true);
+
+    let mut cookie_expiration = OffsetDateTime::now_utc();
+    cookie_expiration += Duration::weeks(52);
+    cookie.set_expires(cookie_expiration);
+    cookie.set_path("/");
+
+    match state.config.launch_mode {
+        LaunchMode::Production | LaunchMode::Staging => cookie.set_secure(true),
+        _ => (),
+    };
+
+    res.add_cookie(&cookie)
+        .map_err(|_| AppError::InternalServerError)?;
+    Ok(res)
+}
+
+async fn start_login_challenge(
+    state: &AppState,
+    user_id: &str,
+    req: &HttpRequest,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let (challenge, token_hash) = tokens::new_login_challenge();
+    postgres::totp::create_login_challenge(
+        &state.storage_layer.pg,
+        CreateLoginChallenge {
+            user_id: user_id.to_owned(),
+            token_hash,
+            data: device_info(req),
+            expires_in: tokens::LOGIN_CHALLENGE_LIFETIME as i64,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "enter a code from your authenticator app to finish signing in",
+        "mfa_required": true,
+        "challenge": challenge,
+        "expires_in": tokens::LOGIN_CHALLENGE_LIFETIME
+    })))
+}
+
+async fn finish_first_factor(
+    state: &AppState,
+    user_id: &str,
+    req: &HttpRequest,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let totp = postgres::totp::get_totp(
+        &state.storage_layer.pg,
+        GetTotp {
+            user_id: user_id.to_owned(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    match totp {
+        Some(totp) if totp.confirmed_at.is_some() => {
+            start_login_challenge(state, user_id, req).await
+        }
+        _ => sign_in(state, user_id, device_info(req)).await,
+    }
+}
+
 pub async fn login(
     state: Data<AppState>,
     data: Json<LoginUser>,
@@ -170,49 +404,19 @@ pub async fn login(
         Some(user) => {
             let candidate = raw_data.password;
             let hash = user.credential_hash;
-            if state.credential_manager.verify_hash(&candidate, &hash) {
-                let access_token = Claims::new_signed(&state.storage_layer, &user.id.to_string())
-                    .await
-                    .map_err(|_| AppError::InternalServerError)?;
-
-                let session_id = state
-                    .session_manager
-                    .start_session(
-                        &state.storage_layer,
-                        CreateSession {
-                            user_id: user.id.clone().to_string(),
-                            data: serde_json::json!({}),
-                            created_at: chrono::offset::Utc::now(),
-                            updated_at: chrono::offset::Utc::now(),
-                        },
-                    )
-                    .await
-                    .map_err(|_| AppError::InternalServerError)?;
-
-                let session_cookie = state
-                    .session_manager
-                    .create_signed_cookie(&session_id)
-                    .map_err(|_| AppError::InternalServerError)?;
+            let pepper_id = user.pepper_id.as_deref();
+            if state
+                .credential_manager
+                .verify_hash(&candidate, &hash, &user.alg, pepper_id)
+            {
+                if state
+                    .credential_manager
+                    .needs_rehash(&hash, &user.alg, pepper_id)
+                {
+                    upgrade_hash(&state, &user.id.to_string(), &candidate).await;
+                }
 
-                let mut res =
-                    HttpResponse::Ok().json(serde_json::json!({ "access_token": access_token }));
-
-                let mut cookie = Cookie::new("mocha_session", &session_cookie);
-                cookie.set_http_only(true);
-
-                let mut cookie_expiration = OffsetDateTime::now_utc();
-                cookie_expiration += Duration::weeks(52);
-                cookie.set_expires(cookie_expiration);
-                cookie.set_path("/");
-
-                match state.config.launch_mode {
-                    LaunchMode::Production | LaunchMode::Staging => cookie.set_secure(true),
-                    _ => (),
-                };
-
-                res.add_cookie(&cookie)
-                    .map_err(|_| AppError::InternalServerError)?;
-                Ok(res)
+                finish_first_factor(&state, &user.id.to_string(), &req).await
             } else {
                 Err(AppError::Unauthorized)
             }
@@ -221,21 +425,1355 @@ pub async fn login(
     }
 }
 
+/// Also revokes the refresh tokens issued with the session
 pub async fn logout(
     state: Data<AppState>,
-    session: ReqData<Session>,
+    claims: ReqData<Claims>,
+    session: Option<ReqData<Session>>,
 ) -> actix_web::Result<HttpResponse, AppError> {
-    let dto = DeleteSession {
-        id: session.id.clone().to_string(),
+    tokens::revoke_access_token(&state.storage_layer, &claims)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+
+    if let Some(session) = session {
+        let dto = DeleteSession {
+            id: session.id.clone().to_string(),
+        };
+        state
+            .session_manager
+            .end_session(&state.storage_layer, dto)
+            .await
+            .map_err(|_| AppError::InternalServerError)?;
+    }
+
+    let mut res = HttpResponse::Ok().json(serde_json::json!({"msg": "successfully logged out"}));
+
+    res.del_cookie("mocha_session");
+    Ok(res)
+}
+
+pub async fn get_sessions(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    session: Option<ReqData<Session>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let current = session.map(|s| s.id);
+    let dto = GetSessionsByUserId {
+        user_id: claims.into_inner().sub,
+    };
+
+    let sessions: Vec<ActiveSession> = state
+        .session_manager
+        .get_user_sessions(&state.storage_layer, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .into_iter()
+        .map(|s| {
+            let is_current = Some(s.id) == current;
+            ActiveSession::new(s, is_current)
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "sessions": sessions })))
+}
+
+/// Sign out a single device
+pub async fn delete_session(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    session_id: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let dto = GetSessionById {
+        id: session_id.into_inner(),
     };
+
+    let session = state
+        .session_manager
+        .get_session(&state.storage_layer, dto)
+        .await
+        .map_err(|_| AppError::NotFound)?;
+    if session.user_id.to_string() != claims.sub {
+        return Err(AppError::NotFound);
+    }
+
     state
         .session_manager
-        .end_session(&state.storage_layer, dto)
+        .end_session(
+            &state.storage_layer,
+            DeleteSession {
+                id: session.id.to_string(),
+            },
+        )
         .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Sign out every device except the one making the request
+pub async fn delete_other_sessions(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    session: Option<ReqData<Session>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let dto = DeleteUserSessions {
+        user_id: claims.into_inner().sub,
+        except: session.map(|s| s.id.to_string()),
+    };
+
+    let ended = state
+        .session_manager
+        .end_user_sessions(&state.storage_layer, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .json(serde_json::json!({"msg": "successfully ended sessions", "ended": ended})))
+}
+
+/// Responds the same way, and as quickly, whether or not the address has an account
+pub async fn forgot_password(
+    state: Data<AppState>,
+    data: Json<ForgotPassword>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let email = data.into_inner().email;
+    tokio::spawn(async move {
+        if let Err(e) = send_password_reset_email(&state, email).await {
+            log::error!("error sending password reset email: {e}");
+        }
+    });
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "msg": "if an account exists for that address, a reset link is on its way"
+    })))
+}
+
+async fn send_password_reset_email(
+    state: &AppState,
+    email: String,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let user = postgres::users::get_user_by_email(
+        &state.storage_layer.pg,
+        GetUserByEmail {
+            email: email.clone(),
+        },
+    )
+    .await?;
+    let Some(user) = user else {
+        return Ok(());
+    };
+
+    let (token, token_hash) = tokens::new_password_reset_token();
+    postgres::password_resets::create_password_reset_token(
+        &state.storage_layer.pg,
+        CreatePasswordResetToken {
+            user_id: user.id.to_string(),
+            token_hash,
+            expires_in: tokens::PASSWORD_RESET_TOKEN_LIFETIME as i64,
+        },
+    )
+    .await?;
+
+    let message = Email {
+        to: email,
+        subject: "Reset your password".to_owned(),
+        body: format!(
+            "Hi {},\n\nSomeone asked to reset the password for your account. If it was you, \
+             follow this link within {} minutes to choose a new one:\n\n{}/reset-password?token={}\n\n\
+             If it wasn't, you can ignore this email and your password will stay the same.\n",
+            user.first_name,
+            tokens::PASSWORD_RESET_TOKEN_LIFETIME / 60,
+            state.config.site_url,
+            token,
+        ),
+    };
+    state.mailer.send(message).await
+}
+
+/// Revokes every session and token the account had
+pub async fn reset_password(
+    state: Data<AppState>,
+    data: Json<ResetPasswordRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let raw_data = data.into_inner();
+    if raw_data.password.is_empty() {
+        return Err(AppError::BadRequest);
+    }
+
+    let hashed_password = state
+        .credential_manager
+        .create_hash(raw_data.password.as_bytes())
         .map_err(|_| AppError::InternalServerError)?;
+    let dto = ResetPassword {
+        token_hash: tokens::hash_token(&raw_data.token),
+        hashed_password,
+        algorithm: state.credential_manager.algorithm.clone(),
+        pepper_id: state.credential_manager.pepper_id(),
+    };
 
-    let mut res = HttpResponse::Ok().json(serde_json::json!({"msg": "successfully logged out"}));
+    let user_id = postgres::password_resets::reset_password(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::Unauthorized)?
+        .to_string();
 
+    tokens::revoke_user_tokens(
+        &state.storage_layer,
+        &state.session_manager,
+        &user_id,
+        util::time::now(),
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    let mut res =
+        HttpResponse::Ok().json(serde_json::json!({"msg": "successfully reset password"}));
     res.del_cookie("mocha_session");
     Ok(res)
 }
+
+async fn send_verification_email(
+    state: &AppState,
+    user_id: &str,
+    first_name: &str,
+    email: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (token, token_hash) = tokens::new_email_token();
+    postgres::emails::create_email_verification_token(
+        &state.storage_layer.pg,
+        CreateEmailVerificationToken {
+            user_id: user_id.to_owned(),
+            email: email.to_owned(),
+            token_hash,
+            expires_in: tokens::EMAIL_VERIFICATION_TOKEN_LIFETIME as i64,
+        },
+    )
+    .await?;
+
+    state
+        .mailer
+        .send(Email {
+            to: email.to_owned(),
+            subject: "Verify your email".to_owned(),
+            body: format!(
+                "Hi {first_name},\n\nFollow this link within {} hours to verify your email:\n\n\
+                 {}/verify-email?token={token}\n\n\
+                 Until you do, you won't be able to comment on or like posts.\n",
+                tokens::EMAIL_VERIFICATION_TOKEN_LIFETIME / 60 / 60,
+                state.config.site_url,
+            ),
+        })
+        .await
+}
+
+/// Clients need a new access token before the verified permissions show up
+pub async fn verify_email(
+    state: Data<AppState>,
+    data: Json<EmailTokenRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let dto = VerifyEmail {
+        token_hash: tokens::hash_token(&data.token),
+    };
+    postgres::emails::verify_email(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::Unauthorized)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({"msg": "successfully verified email"})))
+}
+
+pub async fn resend_verification_email(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let user = postgres::users::get_user_by_id(
+        &state.storage_layer.pg,
+        GetUserById {
+            id: claims.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::NotFound)?;
+
+    if user.email_verified_at.is_some() {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({"msg": "email already verified"})));
+    }
+
+    send_verification_email(&state, &claims.sub, &user.first_name, &user.email)
+        .await
+        .map_err(|e| {
+            log::error!("error sending verification email: {e}");
+            AppError::InternalServerError
+        })?;
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({"msg": "verification email sent"})))
+}
+
+/// Nothing changes until the links mailed to both the old and new address are followed
+pub async fn change_email(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    data: Json<ChangeEmailRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let new_email = data.into_inner().email.trim().to_owned();
+    if !new_email.contains('@') || new_email.contains(char::is_whitespace) {
+        return Err(AppError::BadRequest);
+    }
+
+    let user = postgres::users::get_user_by_id(
+        &state.storage_layer.pg,
+        GetUserById {
+            id: claims.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::NotFound)?;
+    if user.email == new_email {
+        return Err(AppError::BadRequest);
+    }
+
+    let existing = postgres::users::get_user_by_email(
+        &state.storage_layer.pg,
+        GetUserByEmail {
+            email: new_email.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if existing.is_some() {
+        return Err(AppError::Conflict);
+    }
+
+    let (old_token, old_token_hash) = tokens::new_email_token();
+    let (new_token, new_token_hash) = tokens::new_email_token();
+    postgres::emails::create_email_change(
+        &state.storage_layer.pg,
+        CreateEmailChange {
+            user_id: claims.sub.clone(),
+            new_email: new_email.clone(),
+            old_token_hash,
+            new_token_hash,
+            expires_in: tokens::EMAIL_CHANGE_TOKEN_LIFETIME as i64,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    let link = |token: &str| format!("{}/confirm-email?token={token}", state.config.site_url);
+    let minutes = tokens::EMAIL_CHANGE_TOKEN_LIFETIME / 60;
+    let messages = [
+        Email {
+            to: user.email.clone(),
+            subject: "Confirm your email change".to_owned(),
+            body: format!(
+                "Hi {},\n\nSomeone asked to change the email on your account to {new_email}. If \
+                 it was you, follow this link within {minutes} minutes to confirm:\n\n{}\n\n\
+                 If it wasn't, don't follow the link and change your password.\n",
+                user.first_name,
+                link(&old_token),
+            ),
+        },
+        Email {
+            to: new_email.clone(),
+            subject: "Confirm your new email".to_owned(),
+            body: format!(
+                "Hi {},\n\nFollow this link within {minutes} minutes to make this the email for \
+                 your account:\n\n{}\n\nThe change also has to be confirmed from {}.\n",
+                user.first_name,
+                link(&new_token),
+                user.email,
+            ),
+        },
+    ];
+    for message in messages {
+        state.mailer.send(message).await.map_err(|e| {
+            log::error!("error sending email change confirmation: {e}");
+            AppError::InternalServerError
+        })?;
+    }
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "msg": "follow the links sent to your current and your new email to confirm the change"
+    })))
+}
+
+pub async fn confirm_email_change(
+    state: Data<AppState>,
+    data: Json<EmailTokenRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let dto = ConfirmEmailChange {
+        token_hash: tokens::hash_token(&data.token),
+    };
+    let outcome = postgres::emails::confirm_email_change(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+
+    match outcome {
+        EmailChangeOutcome::Pending => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "msg": "confirmed. the change goes through once the other address confirms too",
+            "complete": false
+        }))),
+        EmailChangeOutcome::Completed { email, .. } => Ok(HttpResponse::Ok().json(
+            serde_json::json!({"msg": "successfully changed email", "email": email, "complete": true}),
+        )),
+        EmailChangeOutcome::Taken => Err(AppError::Conflict),
+        EmailChangeOutcome::Invalid => Err(AppError::Unauthorized),
+    }
+}
+
+async fn check_second_factor(
+    state: &AppState,
+    totp: &UserTotp,
+    factor: &SecondFactor,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let user_id = totp.user_id.to_string();
+    let reserved = postgres::totp::reserve_totp_attempt(
+        &state.storage_layer.pg,
+        ReserveTotpAttempt {
+            user_id: user_id.clone(),
+            max_failures: totp::MAX_FAILED_ATTEMPTS,
+            lockout: totp::LOCKOUT,
+        },
+    )
+    .await?;
+    if !reserved {
+        log::warn!("second factor for user {user_id} is locked after too many failed attempts");
+        return Ok(false);
+    }
+
+    let passed = verify_second_factor(state, totp, factor).await?;
+    if passed {
+        postgres::totp::reset_totp_failures(&state.storage_layer.pg, GetTotp { user_id }).await?;
+    }
+    Ok(passed)
+}
+
+async fn verify_second_factor(
+    state: &AppState,
+    totp: &UserTotp,
+    factor: &SecondFactor,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let user_id = totp.user_id.to_string();
+    if let Some(code) = &factor.code {
+        let secret = state.totp_manager.decrypt_secret(&user_id, &totp.secret)?;
+        let Some(step) = state
+            .totp_manager
+            .verify(&secret, code, totp.last_used_step)
+        else {
+            return Ok(false);
+        };
+        return postgres::totp::use_totp_step(
+            &state.storage_layer.pg,
+            UseTotpStep { user_id, step },
+        )
+        .await;
+    }
+    if let Some(recovery_code) = &factor.recovery_code {
+        let code_hash = tokens::hash_token(&totp::normalize_recovery_code(recovery_code));
+        return postgres::totp::use_recovery_code(
+            &state.storage_layer.pg,
+            UseRecoveryCode { user_id, code_hash },
+        )
+        .await;
+    }
+    Ok(false)
+}
+
+pub async fn login_totp(
+    state: Data<AppState>,
+    data: Json<TotpLogin>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let raw_data = data.into_inner();
+    let challenge = postgres::totp::attempt_login_challenge(
+        &state.storage_layer.pg,
+        AttemptLoginChallenge {
+            token_hash: tokens::hash_token(&raw_data.challenge),
+            max_attempts: tokens::LOGIN_CHALLENGE_MAX_ATTEMPTS,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::Unauthorized)?;
+
+    let user_id = challenge.user_id.to_string();
+    let totp = postgres::totp::get_totp(
+        &state.storage_layer.pg,
+        GetTotp {
+            user_id: user_id.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .filter(|t| t.confirmed_at.is_some())
+    .ok_or(AppError::Unauthorized)?;
+
+    let passed = check_second_factor(&state, &totp, &raw_data.factor)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+    if !passed {
+        return Err(AppError::Unauthorized);
+    }
+
+    let completed = postgres::totp::complete_login_challenge(&state.storage_layer.pg, challenge.id)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+    if !completed {
+        return Err(AppError::Unauthorized);
+    }
+
+    sign_in(&state, &user_id, challenge.data).await
+}
+
+pub async fn enroll_totp(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let user = postgres::users::get_user_by_id(
+        &state.storage_layer.pg,
+        GetUserById {
+            id: claims.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::NotFound)?;
+
+    let secret = state.totp_manager.new_secret();
+    let encrypted = state
+        .totp_manager
+        .encrypt_secret(&claims.sub, &secret)
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+    let created = postgres::totp::create_totp(
+        &state.storage_layer.pg,
+        CreateTotp {
+            user_id: claims.sub.clone(),
+            secret: encrypted,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if !created {
+        return Err(AppError::Conflict);
+    }
+
+    let otpauth_uri = state.totp_manager.provisioning_uri(&user.email, &secret);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "add this to your authenticator app, then confirm it with a code",
+        "otpauth_uri": otpauth_uri
+    })))
+}
+
+/// The recovery codes are only ever shown in this response
+pub async fn confirm_totp(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    data: Json<TotpCode>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let totp = postgres::totp::get_totp(
+        &state.storage_layer.pg,
+        GetTotp {
+            user_id: claims.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .filter(|t| t.confirmed_at.is_none())
+    .ok_or(AppError::BadRequest)?;
+
+    let secret = state
+        .totp_manager
+        .decrypt_secret(&claims.sub, &totp.secret)
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+    let step = state
+        .totp_manager
+        .verify(&secret, &data.code, None)
+        .ok_or(AppError::Unauthorized)?;
+
+    let recovery_codes = totp::new_recovery_codes();
+    let confirmed = postgres::totp::confirm_totp(
+        &state.storage_layer.pg,
+        ConfirmTotp {
+            user_id: claims.sub.clone(),
+            step,
+            recovery_code_hashes: recovery_codes
+                .iter()
+                .map(|c| tokens::hash_token(&totp::normalize_recovery_code(c)))
+                .collect(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if !confirmed {
+        return Err(AppError::Conflict);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "successfully turned on two-factor authentication",
+        "recovery_codes": recovery_codes
+    })))
+}
+
+/// Takes a current code or a recovery code
+pub async fn delete_totp(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    data: Json<SecondFactor>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let totp = postgres::totp::get_totp(
+        &state.storage_layer.pg,
+        GetTotp {
+            user_id: claims.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::NotFound)?;
+
+    // a pending authenticator never protected anything, so it can go without a code
+    if totp.confirmed_at.is_some() {
+        let passed = check_second_factor(&state, &totp, &data)
+            .await
+            .map_err(|e| {
+                log::error!("{e}");
+                AppError::InternalServerError
+            })?;
+        if !passed {
+            return Err(AppError::Unauthorized);
+        }
+    }
+
+    postgres::totp::delete_totp(
+        &state.storage_layer.pg,
+        DeleteTotp {
+            user_id: claims.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    Ok(HttpResponse::Ok()
+        .json(serde_json::json!({"msg": "successfully turned off two-factor authentication"})))
+}
+
+pub async fn start_passkey_registration(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let user = postgres::users::get_user_by_id(
+        &state.storage_layer.pg,
+        GetUserById {
+            id: claims.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::NotFound)?;
+
+    // stops the browser from registering an authenticator the user has already added
+    let existing: Vec<String> = postgres::passkeys::get_user_passkeys(
+        &state.storage_layer.pg,
+        GetUserPasskeys {
+            user_id: claims.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .into_iter()
+    .map(|p| p.credential_id)
+    .collect();
+
+    let challenge = webauthn::new_challenge();
+    postgres::passkeys::create_passkey_challenge(
+        &state.storage_layer.pg,
+        CreatePasskeyChallenge {
+            user_id: Some(claims.sub.clone()),
+            ceremony: Ceremony::Registration,
+            challenge: challenge.clone(),
+            expires_in: webauthn::PASSKEY_CHALLENGE_LIFETIME as i64,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    let options = state.passkey_manager.creation_options(
+        &challenge,
+        &user.id,
+        &user.email,
+        &format!("{} {}", user.first_name, user.last_name),
+        &existing,
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "publicKey": options })))
+}
+
+pub async fn finish_passkey_registration(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    data: Json<FinishPasskeyRegistration>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let data = data.into_inner();
+    let registration = state
+        .passkey_manager
+        .verify_registration(&data.credential)
+        .map_err(|e| {
+            log::info!("rejected passkey registration: {e}");
+            AppError::BadRequest
+        })?;
+
+    let used = postgres::passkeys::use_passkey_challenge(
+        &state.storage_layer.pg,
+        UsePasskeyChallenge {
+            challenge: registration.challenge.clone(),
+            ceremony: Ceremony::Registration,
+            user_id: Some(claims.sub.clone()),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if !used {
+        return Err(AppError::Unauthorized);
+    }
+
+    let registered = postgres::passkeys::get_passkey_by_credential_id(
+        &state.storage_layer.pg,
+        GetPasskeyByCredentialId {
+            credential_id: registration.credential_id.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if registered.is_some() {
+        return Err(AppError::Conflict);
+    }
+
+    let name = data
+        .name
+        .map(|n| n.trim().chars().take(64).collect::<String>())
+        .filter(|n| !n.is_empty())
+        .unwrap_or("Passkey".to_owned());
+    let id = postgres::passkeys::create_passkey(
+        &state.storage_layer.pg,
+        CreatePasskey {
+            user_id: claims.sub.clone(),
+            credential_id: registration.credential_id,
+            public_key: registration.public_key,
+            algorithm: registration.algorithm,
+            sign_count: registration.sign_count as i64,
+            aaguid: registration.aaguid,
+            name,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({"msg": "successfully added passkey", "id": id})))
+}
+
+pub async fn start_passkey_login(
+    state: Data<AppState>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let challenge = webauthn::new_challenge();
+    postgres::passkeys::create_passkey_challenge(
+        &state.storage_layer.pg,
+        CreatePasskeyChallenge {
+            user_id: None,
+            ceremony: Ceremony::Authentication,
+            challenge: challenge.clone(),
+            expires_in: webauthn::PASSKEY_CHALLENGE_LIFETIME as i64,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    let options = state.passkey_manager.request_options(&challenge);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "publicKey": options })))
+}
+
+/// Passkeys verify the user, so they skip the TOTP challenge
+pub async fn finish_passkey_login(
+    state: Data<AppState>,
+    data: Json<AuthenticationCredential>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let credential = data.into_inner();
+    let passkey = postgres::passkeys::get_passkey_by_credential_id(
+        &state.storage_layer.pg,
+        GetPasskeyByCredentialId {
+            credential_id: credential.raw_id.trim_end_matches('=').to_owned(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::Unauthorized)?;
+
+    let assertion = state
+        .passkey_manager
+        .verify_authentication(&credential, &passkey)
+        .map_err(|e| {
+            log::info!("rejected passkey {}: {e}", passkey.id);
+            AppError::Unauthorized
+        })?;
+
+    let used = postgres::passkeys::use_passkey_challenge(
+        &state.storage_layer.pg,
+        UsePasskeyChallenge {
+            challenge: assertion.challenge,
+            ceremony: Ceremony::Authentication,
+            user_id: None,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if !used {
+        return Err(AppError::Unauthorized);
+    }
+
+    // checked again here so two copies of an authenticator racing each other can't both get in
+    let counted = postgres::passkeys::use_passkey(
+        &state.storage_layer.pg,
+        UsePasskey {
+            id: passkey.id.to_string(),
+            sign_count: assertion.sign_count as i64,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if !counted {
+        return Err(AppError::Unauthorized);
+    }
+
+    sign_in(&state, &passkey.user_id.to_string(), device_info(&req)).await
+}
+
+pub async fn get_passkeys(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let passkeys = postgres::passkeys::get_user_passkeys(
+        &state.storage_layer.pg,
+        GetUserPasskeys {
+            user_id: claims.into_inner().sub,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "passkeys": passkeys })))
+}
+
+pub async fn delete_passkey(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    passkey_id: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let id = passkey_id.into_inner();
+    if uuid::Uuid::parse_str(&id).is_err() {
+        return Err(AppError::NotFound);
+    }
+
+    let deleted = postgres::passkeys::delete_passkey(
+        &state.storage_layer.pg,
+        DeletePasskey {
+            id,
+            user_id: claims.into_inner().sub,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if deleted == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_oidc_providers(
+    state: Data<AppState>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok()
+        .json(serde_json::json!({ "providers": state.oidc_manager.provider_names() })))
+}
+
+fn oidc_state_cookie(state: &AppState, value: &str) -> Cookie<'static> {
+    let mut cookie = Cookie::new("mocha_oidc_state", value.to_owned());
+    cookie.set_http_only(true);
+    cookie.set_path("/");
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_max_age(Duration::seconds(tokens::OIDC_STATE_LIFETIME as i64));
+
+    match state.config.launch_mode {
+        LaunchMode::Production | LaunchMode::Staging => cookie.set_secure(true),
+        _ => (),
+    };
+    cookie
+}
+
+async fn start_oidc(
+    state: &AppState,
+    provider: &str,
+    user_id: Option<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let (oidc_state, state_hash) = tokens::new_oidc_state();
+    let nonce = oidc::new_nonce();
+    let code_verifier = oidc::new_code_verifier();
+
+    let authorization_url = state
+        .oidc_manager
+        .authorization_url(provider, &oidc_state, &nonce, &code_verifier)
+        .await
+        .map_err(|e| match e {
+            OidcError::UnknownProvider => AppError::NotFound,
+            e => {
+                log::error!("{e}");
+                AppError::InternalServerError
+            }
+        })?;
+
+    postgres::identities::create_oidc_state(
+        &state.storage_layer.pg,
+        CreateOidcState {
+            provider: provider.to_owned(),
+            state_hash,
+            nonce,
+            code_verifier,
+            user_id,
+            expires_in: tokens::OIDC_STATE_LIFETIME as i64,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    let mut res = HttpResponse::Ok().json(serde_json::json!({
+        "authorization_url": authorization_url,
+        "expires_in": tokens::OIDC_STATE_LIFETIME
+    }));
+    res.add_cookie(&oidc_state_cookie(state, &oidc_state))
+        .map_err(|_| AppError::InternalServerError)?;
+    Ok(res)
+}
+
+async fn complete_oidc(
+    state: &AppState,
+    data: OidcCallback,
+    req: &HttpRequest,
+) -> actix_web::Result<(OidcState, ExternalIdentity), AppError> {
+    // the state has to come back to the browser that started the trip
+    match req.cookie("mocha_oidc_state") {
+        Some(cookie) if cookie.value() == data.state => (),
+        _ => return Err(AppError::Unauthorized),
+    }
+
+    let oidc_state = postgres::identities::use_oidc_state(
+        &state.storage_layer.pg,
+        UseOidcState {
+            state_hash: tokens::hash_token(&data.state),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::Unauthorized)?;
+
+    let identity = state
+        .oidc_manager
+        .exchange_code(
+            &oidc_state.provider,
+            &data.code,
+            &oidc_state.code_verifier,
+            &oidc_state.nonce,
+        )
+        .await
+        .map_err(|e| match e {
+            OidcError::UnknownProvider => AppError::NotFound,
+            OidcError::Provider(_) | OidcError::IssuerMismatch => {
+                log::error!("{e}");
+                AppError::InternalServerError
+            }
+            e => {
+                log::info!("rejected sign in with {}: {e}", oidc_state.provider);
+                AppError::Unauthorized
+            }
+        })?;
+    Ok((oidc_state, identity))
+}
+
+async fn sign_up_with_identity(
+    state: &AppState,
+    identity: ExternalIdentity,
+    req: &HttpRequest,
+) -> actix_web::Result<HttpResponse, AppError> {
+    if state.config.launch_mode == LaunchMode::Production {
+        return Err(AppError::Forbidden);
+    }
+    let email = identity.email.clone().ok_or(AppError::BadRequest)?;
+
+    // an existing account has to sign in some other way and link the provider itself
+    let existing = postgres::users::get_user_by_email(
+        &state.storage_layer.pg,
+        GetUserByEmail {
+            email: email.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if existing.is_some() {
+        return Err(AppError::Conflict);
+    }
+
+    let local_part = email.split('@').next().unwrap_or("");
+    let handle: String = identity
+        .username
+        .as_deref()
+        .unwrap_or(local_part)
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .take(24)
+        .collect::<String>()
+        .to_lowercase();
+    let handle = match handle.is_empty() {
+        true => "user".to_owned(),
+        false => handle,
+    };
+    let username = format!("{handle}-{}", util::rng::random_string(6).to_lowercase());
+
+    let full_name = identity.name.clone().unwrap_or_default();
+    let (given, family) = full_name
+        .trim()
+        .split_once(' ')
+        .unwrap_or((full_name.trim(), ""));
+    let first_name = identity
+        .first_name
+        .clone()
+        .or(Some(given.to_owned()).filter(|n| !n.is_empty()))
+        .unwrap_or(handle);
+    let last_name = identity
+        .last_name
+        .clone()
+        .unwrap_or(family.trim().to_owned());
+
+    let user_id = postgres::identities::create_user_with_identity(
+        &state.storage_layer.pg,
+        CreateUserWithIdentity {
+            user: CreateUser {
+                first_name: first_name.clone(),
+                last_name,
+                email: email.clone(),
+                username,
+                image_uri: identity.picture.clone().unwrap_or_default(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+            provider: identity.provider,
+            subject: identity.subject,
+            email_verified: identity.email_verified,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    if !identity.email_verified {
+        if let Err(e) = send_verification_email(state, &user_id, &first_name, &email).await {
+            log::error!("error sending verification email: {e}");
+        }
+    }
+
+    sign_in(state, &user_id, device_info(req)).await
+}
+
+pub async fn start_oidc_login(
+    state: Data<AppState>,
+    provider: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    start_oidc(&state, &provider, None).await
+}
+
+/// External accounts nobody has linked get a new account of their own
+pub async fn oidc_callback(
+    state: Data<AppState>,
+    data: Json<OidcCallback>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let (oidc_state, identity) = complete_oidc(&state, data.into_inner(), &req).await?;
+    // links finish at their own endpoint, where the user they're for has to be signed in
+    if oidc_state.user_id.is_some() {
+        return Err(AppError::BadRequest);
+    }
+
+    let linked = postgres::identities::get_user_identity(
+        &state.storage_layer.pg,
+        GetUserIdentity {
+            provider: identity.provider.clone(),
+            subject: identity.subject.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    let mut res = match linked {
+        Some(linked) => {
+            postgres::identities::use_user_identity(
+                &state.storage_layer.pg,
+                UseUserIdentity {
+                    id: linked.id.to_string(),
+                    email: identity.email,
+                },
+            )
+            .await
+            .map_err(|e| {
+                log::error!("{e}");
+                AppError::InternalServerError
+            })?;
+            // the provider only stands in for the password, so a second factor is still asked for
+            finish_first_factor(&state, &linked.user_id.to_string(), &req).await?
+        }
+        None => sign_up_with_identity(&state, identity, &req).await?,
+    };
+
+    res.add_removal_cookie(&oidc_state_cookie(&state, ""))
+        .map_err(|_| AppError::InternalServerError)?;
+    Ok(res)
+}
+
+pub async fn start_oidc_link(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    provider: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    start_oidc(&state, &provider, Some(claims.sub.clone())).await
+}
+
+pub async fn finish_oidc_link(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    data: Json<OidcCallback>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let (oidc_state, identity) = complete_oidc(&state, data.into_inner(), &req).await?;
+    if oidc_state.user_id.map(|id| id.to_string()).as_ref() != Some(&claims.sub) {
+        return Err(AppError::Unauthorized);
+    }
+
+    let id = postgres::identities::create_user_identity(
+        &state.storage_layer.pg,
+        CreateUserIdentity {
+            user_id: claims.sub.clone(),
+            provider: identity.provider.clone(),
+            subject: identity.subject.clone(),
+            email: identity.email,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    // linking the same account twice is fine, but it can't belong to two users
+    if id.is_none() {
+        let existing = postgres::identities::get_user_identity(
+            &state.storage_layer.pg,
+            GetUserIdentity {
+                provider: identity.provider,
+                subject: identity.subject,
+            },
+        )
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+        if existing.map(|i| i.user_id.to_string()).as_ref() != Some(&claims.sub) {
+            return Err(AppError::Conflict);
+        }
+    }
+
+    let mut res =
+        HttpResponse::Ok().json(serde_json::json!({"msg": "successfully linked account"}));
+    res.add_removal_cookie(&oidc_state_cookie(&state, ""))
+        .map_err(|_| AppError::InternalServerError)?;
+    Ok(res)
+}
+
+pub async fn get_identities(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let identities = postgres::identities::get_user_identities(
+        &state.storage_layer.pg,
+        GetUserIdentities {
+            user_id: claims.into_inner().sub,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "identities": identities })))
+}
+
+/// Refused if it's the only way left to sign in
+pub async fn delete_identity(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    identity_id: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let id = identity_id.into_inner();
+    let identities = postgres::identities::get_user_identities(
+        &state.storage_layer.pg,
+        GetUserIdentities {
+            user_id: claims.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if !identities.iter().any(|i| i.id.to_string() == id) {
+        return Err(AppError::NotFound);
+    }
+
+    let deleted = postgres::identities::delete_user_identity(
+        &state.storage_layer.pg,
+        DeleteUserIdentity {
+            id,
+            user_id: claims.into_inner().sub,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+    if deleted == 0 {
+        return Err(AppError::Conflict);
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -12,6 +12,54 @@ pub fn config(cfg: &mut ServiceConfig) {
         web::scope("/auth")
             .route("/register", web::post().to(controllers::register))
             .route("/login", web::post().to(controllers::login))
+            // the challenge from a login that needs a second factor stands in for a session here
+            .route("/login/totp", web::post().to(controllers::login_totp))
+            // passkeys sign in on their own, so their ceremony doesn't need anything either
+            .route(
+                "/passkeys/login/start",
+                web::post().to(controllers::start_passkey_login),
+            )
+            .route(
+                "/passkeys/login/finish",
+                web::post().to(controllers::finish_passkey_login),
+            )
+            .route(
+                "/oidc/providers",
+                web::get().to(controllers::get_oidc_providers),
+            )
+            .route(
+                "/oidc/{provider}/start",
+                web::post().to(controllers::start_oidc_login),
+            )
+            .route("/oidc/callback", web::post().to(controllers::oidc_callback))
+            // refresh tokens are bearer credentials in their own right, so neither of these needs
+            // a session or an access token
+            .route("/refresh", web::post().to(controllers::refresh))
+            .route("/revoke", web::post().to(controllers::revoke))
+            .route(
+                "/password/forgot",
+                web::post().to(controllers::forgot_password),
+            )
+            .route(
+                "/password/reset",
+                web::post().to(controllers::reset_password),
+            )
+            // email links can be opened on any device, so the tokens in them are all these need
+            .route("/email/verify", web::post().to(controllers::verify_email))
+            .route(
+                "/email/change/confirm",
+                web::post().to(controllers::confirm_email_change),
+            )
+            .service(
+                web::scope("/email")
+                    .wrap(session.clone())
+                    .wrap(jwt.clone())
+                    .route(
+                        "/verify/resend",
+                        web::post().to(controllers::resend_verification_email),
+                    )
+                    .route("/change", web::post().to(controllers::change_email)),
+            )
             .service(
                 web::scope("/token")
                     .wrap(session.clone())
@@ -19,9 +67,58 @@ pub fn config(cfg: &mut ServiceConfig) {
             )
             .service(
                 web::scope("/logout")
+                    .wrap(session.clone())
+                    .wrap(jwt.clone())
+                    .route("", web::post().to(controllers::logout)),
+            )
+            .service(
+                web::scope("/2fa")
+                    .wrap(session.clone())
+                    .wrap(jwt.clone())
+                    .route("/totp", web::post().to(controllers::enroll_totp))
+                    .route("/totp", web::delete().to(controllers::delete_totp))
+                    .route("/totp/confirm", web::post().to(controllers::confirm_totp)),
+            )
+            .service(
+                web::scope("/passkeys")
+                    .wrap(session.clone())
+                    .wrap(jwt.clone())
+                    .route(
+                        "/register/start",
+                        web::post().to(controllers::start_passkey_registration),
+                    )
+                    .route(
+                        "/register/finish",
+                        web::post().to(controllers::finish_passkey_registration),
+                    )
+                    .route("", web::get().to(controllers::get_passkeys))
+                    .route("/{passkey}", web::delete().to(controllers::delete_passkey)),
+            )
+            .service(
+                web::scope("/identities")
+                    .wrap(session.clone())
+                    .wrap(jwt.clone())
+                    .route("", web::get().to(controllers::get_identities))
+                    .route(
+                        "/{provider}/link",
+                        web::post().to(controllers::start_oidc_link),
+                    )
+                    .route(
+                        "/link/callback",
+                        web::post().to(controllers::finish_oidc_link),
+                    )
+                    .route(
+                        "/{identity}",
+                        web::delete().to(controllers::delete_identity),
+                    ),
+            )
+            .service(
+                web::scope("/sessions")
                     .wrap(session)
                     .wrap(jwt)
-                    .route("", web::post().to(controllers::logout)),
+                    .route("", web::get().to(controllers::get_sessions))
+                    .route("", web::delete().to(controllers::delete_other_sessions))
+                    .route("/{session}", web::delete().to(controllers::delete_session)),
             ),
     );
 }
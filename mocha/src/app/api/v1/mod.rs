@@ -2,6 +2,7 @@ mod admin;
 mod auth;
 mod controllers;
 mod posts;
+mod search;
 mod users;
 
 use actix_web::web;
@@ -13,6 +14,8 @@ pub fn config(cfg: &mut ServiceConfig) {
             web::scope("/v1")
                 .configure(auth::config)
                 .configure(users::config)
-                .configure(posts::config),
+                .configure(posts::config)
+                .configure(search::config)
+                .configure(admin::config),
         );
 }
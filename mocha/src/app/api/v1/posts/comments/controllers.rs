@@ -0,0 +1,419 @@
+use actix_web::{
+    web::{Data, Json, Path, ReqData},
+    HttpResponse,
+};
+use actix_web_grants::proc_macro::has_permissions;
+use chrono::{Duration, Utc};
+
+use crate::app::{
+    auth::tokens::Claims,
+    dto::{
+        comments::{
+            CreateComment, DeleteComment, EditComment, GetCommentById, GetThread, GetThreads,
+        },
+        likes::{GetCommentLikes, LikeComment, UnlikeComment},
+        pagination::{CommentPaginationOptions, PaginationLimits, ReactionPaginationOptions},
+        reactions::{
+            DeleteCommentReaction, GetCommentReactions, GetCommentReactors, ReactToComment,
+        },
+    },
+    errors::AppError,
+    spam,
+    state::AppState,
+    storage::postgres,
+};
+
+use super::requests::{CreateCommentRequest, EditCommentRequest};
+
+const MAX_COMMENT_LENGTH: usize = 10_000;
+
+fn validate_content(content: &str) -> Result<(), AppError> {
+    if content.trim().is_empty() || content.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(AppError::BadRequest);
+    }
+    Ok(())
+}
+
+#[has_permissions("comments:create")]
+pub async fn create_comment(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+    data: Json<CreateCommentRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let info = data.into_inner();
+    validate_content(&info.content)?;
+
+    let tokens = spam::tokenize(&info.content);
+    let stats = postgres::spam::get_spam_stats(&state.storage_layer.pg, &tokens)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+
+    let dto = CreateComment {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+        parent_id: info.parent_id,
+        content: info.content,
+        spam: spam::is_spam(&stats, state.config.spam_threshold),
+    };
+
+    let (comment_id, status) = postgres::comments::create_comment(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "successfully created new comment",
+        "comment_id": comment_id,
+        "status": status
+    })))
+}
+
+#[has_permissions("comments:get")]
+pub async fn get_comment(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id) = path.into_inner();
+    let dto = GetCommentById {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+    };
+
+    let maybe_comment = postgres::comments::get_comment(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    match maybe_comment {
+        Some(comment) => Ok(HttpResponse::Ok().json(serde_json::json!({ "comment": comment }))),
+        None => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("comments:get")]
+pub async fn get_threads(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+    pagination: Json<PaginationLimits<CommentPaginationOptions>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = GetThreads {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+    };
+
+    let threads =
+        postgres::comments::get_threads(&state.storage_layer.pg, dto, pagination.into_inner())
+            .await
+            .map_err(|e| {
+                log::error!("{e}");
+                AppError::InternalServerError
+            })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "comments": threads })))
+}
+
+#[has_permissions("comments:get")]
+pub async fn get_thread(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+    pagination: Json<PaginationLimits<CommentPaginationOptions>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, thread_id) = path.into_inner();
+    let dto = GetThread {
+        user_id: claim_data.sub,
+        post_id,
+        thread_id,
+    };
+
+    let replies =
+        postgres::comments::get_thread(&state.storage_layer.pg, dto, pagination.into_inner())
+            .await
+            .map_err(|e| {
+                log::error!("{e}");
+                AppError::InternalServerError
+            })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "replies": replies })))
+}
+
+#[has_permissions("comments:edit")]
+pub async fn edit_comment(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+    data: Json<EditCommentRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id) = path.into_inner();
+    let info = data.into_inner();
+    validate_content(&info.content)?;
+
+    // look the comment up first so that an expired edit window can be told apart from a comment
+    // that doesn't exist or belongs to someone else
+    let comment = postgres::comments::get_comment(
+        &state.storage_layer.pg,
+        GetCommentById {
+            user_id: claim_data.sub.clone(),
+            post_id: post_id.clone(),
+            comment_id: comment_id.clone(),
+        },
+    )
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::NotFound)?;
+
+    if comment.user_id.map(|id| id.to_string()) != Some(claim_data.sub.clone()) {
+        return Err(AppError::NotFound);
+    }
+    if comment.created_at + Duration::seconds(state.config.comment_edit_window) < Utc::now() {
+        return Err(AppError::Forbidden);
+    }
+
+    let tokens = spam::tokenize(&info.content);
+    let stats = postgres::spam::get_spam_stats(&state.storage_layer.pg, &tokens)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+
+    let dto = EditComment {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+        content: info.content,
+        edit_window: state.config.comment_edit_window,
+        spam: spam::is_spam(&stats, state.config.spam_threshold),
+    };
+
+    let status = postgres::comments::edit_comment(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::Forbidden)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "successfully edited comment",
+        "status": status
+    })))
+}
+
+#[has_permissions("comments:delete")]
+pub async fn delete_comment(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id) = path.into_inner();
+    let dto = DeleteComment {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+    };
+
+    match postgres::comments::delete_comment(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+    {
+        1 => Ok(HttpResponse::NoContent().finish()),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("comments:likes:create")]
+pub async fn like_comment(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id) = path.into_inner();
+    let dto = LikeComment {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+    };
+
+    let likes = postgres::likes::like_comment(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "likes": likes })))
+}
+
+#[has_permissions("comments:likes:delete")]
+pub async fn unlike_comment(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id) = path.into_inner();
+    let dto = UnlikeComment {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+    };
+
+    let likes = postgres::likes::unlike_comment(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "likes": likes })))
+}
+
+#[has_permissions("comments:likes:get-count")]
+pub async fn get_comment_likes(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id) = path.into_inner();
+    let dto = GetCommentLikes {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+    };
+
+    let likes = postgres::likes::get_comment_likes(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "likes": likes })))
+}
+
+#[has_permissions("comments:reactions:create")]
+pub async fn react_to_comment(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id, sticker_id) = path.into_inner();
+    let dto = ReactToComment {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+        sticker_id,
+    };
+
+    match postgres::reactions::react_to_comment(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })? {
+        true => {
+            Ok(HttpResponse::Ok()
+                .json(serde_json::json!({"msg": "successfully reacted to comment"})))
+        }
+        false => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("comments:reactions:delete")]
+pub async fn delete_comment_reaction(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id, sticker_id) = path.into_inner();
+    let dto = DeleteCommentReaction {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+        sticker_id,
+    };
+
+    match postgres::reactions::delete_comment_reaction(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+    {
+        1 => Ok(HttpResponse::NoContent().finish()),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("comments:reactions:get")]
+pub async fn get_comment_reactions(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id) = path.into_inner();
+    let dto = GetCommentReactions {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+    };
+
+    let reactions = postgres::reactions::get_comment_reactions(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "reactions": reactions })))
+}
+
+#[has_permissions("comments:reactions:get")]
+pub async fn get_comment_reactors(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String, String)>,
+    pagination: Json<PaginationLimits<ReactionPaginationOptions>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, comment_id, sticker_id) = path.into_inner();
+    let dto = GetCommentReactors {
+        user_id: claim_data.sub,
+        post_id,
+        comment_id,
+        sticker_id,
+    };
+
+    let reactors = postgres::reactions::get_comment_reactors(
+        &state.storage_layer.pg,
+        dto,
+        pagination.into_inner(),
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "reactors": reactors })))
+}
@@ -0,0 +1,58 @@
+mod controllers;
+mod requests;
+
+use actix_web::web::{self, ServiceConfig};
+
+/// Comment routes live under a post. They are configured inside the authenticated posts scope, so
+/// the session and jwt guards are already applied.
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.route("/{post}/comments", web::get().to(controllers::get_threads))
+        .route(
+            "/{post}/comments",
+            web::post().to(controllers::create_comment),
+        )
+        .route(
+            "/{post}/comments/{comment}",
+            web::get().to(controllers::get_comment),
+        )
+        .route(
+            "/{post}/comments/{comment}",
+            web::put().to(controllers::edit_comment),
+        )
+        .route(
+            "/{post}/comments/{comment}",
+            web::delete().to(controllers::delete_comment),
+        )
+        .route(
+            "/{post}/comments/{comment}/thread",
+            web::get().to(controllers::get_thread),
+        )
+        .route(
+            "/{post}/comments/{comment}/likes",
+            web::get().to(controllers::get_comment_likes),
+        )
+        .route(
+            "/{post}/comments/{comment}/likes",
+            web::put().to(controllers::like_comment),
+        )
+        .route(
+            "/{post}/comments/{comment}/likes",
+            web::delete().to(controllers::unlike_comment),
+        )
+        .route(
+            "/{post}/comments/{comment}/reactions",
+            web::get().to(controllers::get_comment_reactions),
+        )
+        .route(
+            "/{post}/comments/{comment}/reactions/{sticker}",
+            web::get().to(controllers::get_comment_reactors),
+        )
+        .route(
+            "/{post}/comments/{comment}/reactions/{sticker}",
+            web::put().to(controllers::react_to_comment),
+        )
+        .route(
+            "/{post}/comments/{comment}/reactions/{sticker}",
+            web::delete().to(controllers::delete_comment_reaction),
+        );
+}
@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateCommentRequest {
+    pub parent_id: Option<String>,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditCommentRequest {
+    pub content: String,
+}
@@ -4,21 +4,35 @@ use actix_web::{
     HttpResponse,
 };
 use actix_web_grants::proc_macro::has_permissions;
+use uuid::Uuid;
 
 use crate::app::{
     auth::tokens::Claims,
-    dto::stickers::{
-        CreateSticker, CreateStickers, DeleteSticker, EditSticker, GetAvailableStickers,
-        GetStickersByUser,
+    dto::{
+        likes::{GetPostLikes, LikePost, UnlikePost},
+        pagination::{PaginationLimits, PostPaginationOptions, ReactionPaginationOptions},
+        posts::{
+            CreatePost, DeletePost, EditPost, GetAvailablePosts, GetPostById, GetPostRevisionById,
+            GetPostRevisions, GetPostsByUser, GetPublishedPostById, PublishPost,
+            RestorePostRevision, SchedulePost, UnpublishPost,
+        },
+        reactions::{DeletePostReaction, GetPostReactions, GetPostReactors, ReactToPost},
+        stickers::{
+            CreateSticker, CreateStickers, DeleteSticker, EditSticker, GetAvailableStickers,
+            GetStickersByUser,
+        },
     },
     errors::AppError,
+    markdown,
     state::AppState,
     storage::postgres,
     types::AssetVisibility,
-    upload,
+    upload, util,
 };
 
-use super::requests::EditStickerRequest;
+use super::requests::{
+    CreatePostRequest, EditPostRequest, EditStickerRequest, SchedulePostRequest,
+};
 
 #[has_permissions("stickers:create")]
 pub async fn create_stickers(
@@ -143,3 +157,616 @@ pub async fn delete_sticker(
         _ => Err(AppError::NotFound),
     }
 }
+
+/// Read time is always derived from the rendered content rather than trusted from the client.
+fn read_time(state: &AppState, rendered: &markdown::RenderedContent, stickers: usize) -> i32 {
+    markdown::read_time(
+        rendered.word_count,
+        rendered.image_count,
+        stickers,
+        state.config.words_per_minute,
+        state.config.image_read_seconds,
+        state.config.sticker_read_seconds,
+    )
+}
+
+/// Stickers are only attached once each, so only count them once towards the read time
+fn distinct_stickers(stickers: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut ids = stickers
+        .iter()
+        .map(|id| Uuid::parse_str(id))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| AppError::BadRequest)?;
+    ids.sort_unstable();
+    ids.dedup();
+    Ok(ids.into_iter().map(|id| id.to_string()).collect())
+}
+
+#[has_permissions("posts:create")]
+pub async fn create_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    data: Json<CreatePostRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let info = data.into_inner();
+    let rendered = markdown::render(&info.content);
+    let stickers = distinct_stickers(info.stickers)?;
+    let read_time = read_time(&state, &rendered, stickers.len());
+    let dto = CreatePost {
+        user_id: claim_data.sub,
+        space_id: info.space_id,
+        title: info.title,
+        content: info.content,
+        content_html: rendered.html,
+        toc: serde_json::to_value(rendered.toc).map_err(|_| AppError::InternalServerError)?,
+        image_uri: info.image_uri,
+        private: info.private,
+        tags: info.tags,
+        stickers,
+        read_time,
+        word_count: rendered.word_count as i32,
+    };
+
+    let post_id = postgres::posts::create_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::BadRequest)?;
+
+    Ok(HttpResponse::Ok()
+        .json(serde_json::json!({"msg": "successfully created new post", "post_id": post_id})))
+}
+
+pub async fn get_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = GetPostById {
+        post_id: post.into_inner(),
+        user_id: claim_data.sub,
+    };
+
+    let maybe_post = postgres::posts::get_post_by_id(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    match maybe_post {
+        Some(post) => Ok(HttpResponse::Ok().json(serde_json::json!({ "post": post }))),
+        None => Err(AppError::NotFound),
+    }
+}
+
+pub async fn get_posts(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    pagination: Json<PaginationLimits<PostPaginationOptions>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = GetAvailablePosts {
+        user_id: claim_data.sub,
+    };
+
+    let posts = postgres::posts::get_posts(&state.storage_layer.pg, dto, pagination.into_inner())
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "posts": posts })))
+}
+
+pub async fn get_published_post(
+    state: Data<AppState>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let dto = GetPublishedPostById {
+        post_id: post.into_inner(),
+    };
+
+    let maybe_post = postgres::posts::get_published_post_by_id(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    match maybe_post {
+        Some(post) => Ok(HttpResponse::Ok().json(serde_json::json!({ "post": post }))),
+        None => Err(AppError::NotFound),
+    }
+}
+
+pub async fn get_published_posts(
+    state: Data<AppState>,
+    pagination: Json<PaginationLimits<PostPaginationOptions>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let posts =
+        postgres::posts::get_published_posts(&state.storage_layer.pg, pagination.into_inner())
+            .await
+            .map_err(|e| {
+                log::error!("{e}");
+                AppError::InternalServerError
+            })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "posts": posts })))
+}
+
+pub async fn get_drafts(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    pagination: Json<PaginationLimits<PostPaginationOptions>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = GetPostsByUser {
+        user_id: claim_data.sub,
+    };
+
+    let drafts = postgres::posts::get_drafts(&state.storage_layer.pg, dto, pagination.into_inner())
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "drafts": drafts })))
+}
+
+#[has_permissions("posts:edit")]
+pub async fn edit_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+    data: Json<EditPostRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let info = data.into_inner();
+    let rendered = markdown::render(&info.content);
+    let stickers = distinct_stickers(info.stickers)?;
+    let read_time = read_time(&state, &rendered, stickers.len());
+    let dto = EditPost {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+        title: info.title,
+        private: info.private,
+        tags: info.tags,
+        stickers,
+        content: info.content,
+        content_html: rendered.html,
+        toc: serde_json::to_value(rendered.toc).map_err(|_| AppError::InternalServerError)?,
+        image_uri: info.image_uri,
+        read_time,
+        word_count: rendered.word_count as i32,
+    };
+
+    match postgres::posts::edit_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })? {
+        Some(1) => {
+            Ok(HttpResponse::Ok().json(serde_json::json!({"msg": "successfully edited post"})))
+        }
+        Some(_) => Err(AppError::NotFound),
+        None => Err(AppError::BadRequest),
+    }
+}
+
+#[has_permissions("posts:delete")]
+pub async fn delete_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = DeletePost {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+    };
+    match postgres::posts::delete_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+    {
+        1 => Ok(HttpResponse::NoContent().finish()),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("posts:edit")]
+pub async fn publish_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = PublishPost {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+    };
+    match postgres::posts::publish_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+    {
+        1 => Ok(HttpResponse::Ok().json(serde_json::json!({"msg": "successfully published post"}))),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("posts:edit")]
+pub async fn unpublish_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = UnpublishPost {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+    };
+    match postgres::posts::unpublish_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+    {
+        1 => Ok(
+            HttpResponse::Ok().json(serde_json::json!({"msg": "successfully unpublished post"}))
+        ),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("posts:edit")]
+pub async fn schedule_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+    data: Json<SchedulePostRequest>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let info = data.into_inner();
+
+    if info.publish_at <= chrono::offset::Utc::now() {
+        return Err(AppError::BadRequest);
+    }
+
+    let dto = SchedulePost {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+        publish_at: Some(info.publish_at),
+    };
+    match postgres::posts::schedule_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+    {
+        1 => Ok(HttpResponse::Ok().json(serde_json::json!({"msg": "successfully scheduled post"}))),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("posts:edit")]
+pub async fn unschedule_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = SchedulePost {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+        publish_at: None,
+    };
+    match postgres::posts::schedule_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+    {
+        1 => Ok(HttpResponse::NoContent().finish()),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("posts:edit")]
+pub async fn get_post_revisions(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = GetPostRevisions {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+    };
+
+    let revisions = postgres::posts::get_post_revisions(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "revisions": revisions })))
+}
+
+/// Diff two revisions of a post. Passing `current` as the second revision diffs against the live
+/// version of the post instead.
+#[has_permissions("posts:edit")]
+pub async fn diff_post_revisions(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, from, to) = path.into_inner();
+
+    let old = postgres::posts::get_post_revision(
+        &state.storage_layer.pg,
+        GetPostRevisionById {
+            user_id: claim_data.sub.clone(),
+            post_id: post_id.clone(),
+            revision_id: from.clone(),
+        },
+    )
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::NotFound)?;
+
+    let (title, content, image_uri) = if to == "current" {
+        let post = postgres::posts::get_post_by_id(
+            &state.storage_layer.pg,
+            GetPostById {
+                post_id,
+                user_id: claim_data.sub,
+            },
+        )
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or(AppError::NotFound)?;
+        (post.title, post.content, post.image_uri)
+    } else {
+        let revision = postgres::posts::get_post_revision(
+            &state.storage_layer.pg,
+            GetPostRevisionById {
+                user_id: claim_data.sub,
+                post_id,
+                revision_id: to.clone(),
+            },
+        )
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or(AppError::NotFound)?;
+        (revision.title, revision.content, revision.image_uri)
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "diff": {
+            "title": util::diff::unified(&old.title, &title, &from, &to),
+            "content": util::diff::unified(&old.content, &content, &from, &to),
+            "image_uri": util::diff::unified(&old.image_uri, &image_uri, &from, &to),
+        }
+    })))
+}
+
+#[has_permissions("posts:edit")]
+pub async fn restore_post_revision(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, revision_id) = path.into_inner();
+
+    let revision = postgres::posts::get_post_revision(
+        &state.storage_layer.pg,
+        GetPostRevisionById {
+            user_id: claim_data.sub.clone(),
+            post_id: post_id.clone(),
+            revision_id: revision_id.clone(),
+        },
+    )
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::NotFound)?;
+
+    // revisions don't track stickers, so the restored post keeps the ones it has now
+    let current = postgres::posts::get_post_by_id(
+        &state.storage_layer.pg,
+        GetPostById {
+            post_id: post_id.clone(),
+            user_id: claim_data.sub.clone(),
+        },
+    )
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::NotFound)?;
+
+    let rendered = markdown::render(&revision.content);
+    let read_time = read_time(&state, &rendered, current.stickers.len());
+    let dto = RestorePostRevision {
+        user_id: claim_data.sub,
+        post_id,
+        revision_id,
+        content_html: rendered.html,
+        toc: serde_json::to_value(rendered.toc).map_err(|_| AppError::InternalServerError)?,
+        read_time,
+        word_count: rendered.word_count as i32,
+    };
+    match postgres::posts::restore_post_revision(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })? {
+        1 => Ok(HttpResponse::Ok().json(serde_json::json!({"msg": "successfully restored post"}))),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("posts:likes:create")]
+pub async fn like_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = LikePost {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+    };
+
+    let likes = postgres::likes::like_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "likes": likes })))
+}
+
+#[has_permissions("posts:likes:delete")]
+pub async fn unlike_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = UnlikePost {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+    };
+
+    let likes = postgres::likes::unlike_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "likes": likes })))
+}
+
+#[has_permissions("posts:likes:get-count")]
+pub async fn get_post_likes(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = GetPostLikes {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+    };
+
+    let likes = postgres::likes::get_post_likes(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "likes": likes })))
+}
+
+#[has_permissions("posts:reactions:create")]
+pub async fn react_to_post(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, sticker_id) = path.into_inner();
+    let dto = ReactToPost {
+        user_id: claim_data.sub,
+        post_id,
+        sticker_id,
+    };
+
+    match postgres::reactions::react_to_post(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })? {
+        true => {
+            Ok(HttpResponse::Ok().json(serde_json::json!({"msg": "successfully reacted to post"})))
+        }
+        false => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("posts:reactions:delete")]
+pub async fn delete_post_reaction(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, sticker_id) = path.into_inner();
+    let dto = DeletePostReaction {
+        user_id: claim_data.sub,
+        post_id,
+        sticker_id,
+    };
+
+    match postgres::reactions::delete_post_reaction(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+    {
+        1 => Ok(HttpResponse::NoContent().finish()),
+        _ => Err(AppError::NotFound),
+    }
+}
+
+#[has_permissions("posts:reactions:get")]
+pub async fn get_post_reactions(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    post: Path<String>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let dto = GetPostReactions {
+        user_id: claim_data.sub,
+        post_id: post.into_inner(),
+    };
+
+    let reactions = postgres::reactions::get_post_reactions(&state.storage_layer.pg, dto)
+        .await
+        .map_err(|e| {
+            log::error!("{e}");
+            AppError::InternalServerError
+        })?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "reactions": reactions })))
+}
+
+#[has_permissions("posts:reactions:get")]
+pub async fn get_post_reactors(
+    state: Data<AppState>,
+    claims: ReqData<Claims>,
+    path: Path<(String, String)>,
+    pagination: Json<PaginationLimits<ReactionPaginationOptions>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let claim_data = claims.into_inner();
+    let (post_id, sticker_id) = path.into_inner();
+    let dto = GetPostReactors {
+        user_id: claim_data.sub,
+        post_id,
+        sticker_id,
+    };
+
+    let reactors = postgres::reactions::get_post_reactors(
+        &state.storage_layer.pg,
+        dto,
+        pagination.into_inner(),
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?
+    .ok_or(AppError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "reactors": reactors })))
+}
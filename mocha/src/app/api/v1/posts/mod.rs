@@ -1,3 +1,4 @@
+mod comments;
 mod controllers;
 mod requests;
 mod spaces;
@@ -12,18 +13,84 @@ pub fn config(cfg: &mut ServiceConfig) {
     let jwt = HttpAuthentication::bearer(guards::jwt_guard);
 
     cfg.service(
-        web::scope("/posts").configure(spaces::config).service(
-            web::scope("/stickers")
-                .wrap(session)
-                .wrap(jwt)
-                .route("", web::get().to(controllers::get_user_created_stickers))
-                .route("", web::post().to(controllers::create_stickers))
-                .route("/{sticker}", web::put().to(controllers::edit_sticker))
-                .route("/{sticker}", web::delete().to(controllers::delete_sticker))
-                .route(
-                    "/available",
-                    web::get().to(controllers::get_available_stickers),
-                ),
-        ),
+        web::scope("/posts")
+            .configure(spaces::config)
+            .service(
+                web::scope("/stickers")
+                    .wrap(session.clone())
+                    .wrap(jwt.clone())
+                    .route("", web::get().to(controllers::get_user_created_stickers))
+                    .route("", web::post().to(controllers::create_stickers))
+                    .route("/{sticker}", web::put().to(controllers::edit_sticker))
+                    .route("/{sticker}", web::delete().to(controllers::delete_sticker))
+                    .route(
+                        "/available",
+                        web::get().to(controllers::get_available_stickers),
+                    ),
+            )
+            // published, public posts are readable without an account
+            .service(
+                web::scope("/published")
+                    .route("", web::get().to(controllers::get_published_posts))
+                    .route("/{post}", web::get().to(controllers::get_published_post)),
+            )
+            // NOTE: this scope matches everything under /posts, so it has to be registered after
+            // the /spaces, /stickers and /published scopes or it will shadow them
+            .service(
+                web::scope("")
+                    .wrap(session)
+                    .wrap(jwt)
+                    .route("", web::get().to(controllers::get_posts))
+                    .route("", web::post().to(controllers::create_post))
+                    .route("/drafts", web::get().to(controllers::get_drafts))
+                    .route("/{post}/publish", web::post().to(controllers::publish_post))
+                    .route(
+                        "/{post}/unpublish",
+                        web::post().to(controllers::unpublish_post),
+                    )
+                    .route(
+                        "/{post}/schedule",
+                        web::put().to(controllers::schedule_post),
+                    )
+                    .route(
+                        "/{post}/schedule",
+                        web::delete().to(controllers::unschedule_post),
+                    )
+                    .route(
+                        "/{post}/revisions",
+                        web::get().to(controllers::get_post_revisions),
+                    )
+                    .route(
+                        "/{post}/revisions/{from}/diff/{to}",
+                        web::get().to(controllers::diff_post_revisions),
+                    )
+                    .route(
+                        "/{post}/revisions/{revision}/restore",
+                        web::post().to(controllers::restore_post_revision),
+                    )
+                    .route("/{post}/likes", web::get().to(controllers::get_post_likes))
+                    .route("/{post}/likes", web::put().to(controllers::like_post))
+                    .route("/{post}/likes", web::delete().to(controllers::unlike_post))
+                    .route(
+                        "/{post}/reactions",
+                        web::get().to(controllers::get_post_reactions),
+                    )
+                    .route(
+                        "/{post}/reactions/{sticker}",
+                        web::get().to(controllers::get_post_reactors),
+                    )
+                    .route(
+                        "/{post}/reactions/{sticker}",
+                        web::put().to(controllers::react_to_post),
+                    )
+                    .route(
+                        "/{post}/reactions/{sticker}",
+                        web::delete().to(controllers::delete_post_reaction),
+                    )
+                    .configure(comments::config)
+                    .route("/{post}", web::get().to(controllers::get_post))
+                    .route("/{post}", web::put().to(controllers::edit_post))
+                    .route("/{post}", web::delete().to(controllers::delete_post)),
+            ),
     );
 }
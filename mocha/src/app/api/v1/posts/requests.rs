@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::app::types::AssetVisibility;
@@ -7,3 +8,29 @@ pub struct EditStickerRequest {
     pub friendly_name: String,
     pub visibility: AssetVisibility,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePostRequest {
+    pub space_id: String,
+    pub title: String,
+    pub content: String,
+    pub image_uri: String,
+    pub private: bool,
+    pub tags: Vec<String>,
+    pub stickers: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditPostRequest {
+    pub title: String,
+    pub private: bool,
+    pub tags: Vec<String>,
+    pub stickers: Vec<String>,
+    pub content: String,
+    pub image_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchedulePostRequest {
+    pub publish_at: DateTime<Utc>,
+}
@@ -0,0 +1,40 @@
+use actix_web::{
+    web::{Data, Json, Query},
+    HttpResponse,
+};
+
+use crate::app::{
+    dto::{
+        pagination::{PaginationLimits, SearchPaginationOptions},
+        search::Search,
+    },
+    errors::AppError,
+    state::AppState,
+    storage::postgres,
+};
+
+use super::requests::SearchRequest;
+
+pub async fn search(
+    state: Data<AppState>,
+    query: Query<SearchRequest>,
+    pagination: Json<PaginationLimits<SearchPaginationOptions>>,
+) -> actix_web::Result<HttpResponse, AppError> {
+    let query = query.into_inner().q.trim().to_owned();
+    if query.is_empty() {
+        return Err(AppError::BadRequest);
+    }
+
+    let results = postgres::search::search(
+        &state.storage_layer.pg,
+        Search { query },
+        pagination.into_inner(),
+    )
+    .await
+    .map_err(|e| {
+        log::error!("{e}");
+        AppError::InternalServerError
+    })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "results": results })))
+}
@@ -0,0 +1,9 @@
+mod controllers;
+mod requests;
+
+use actix_web::web::{self, ServiceConfig};
+
+// search only ever returns published, public posts, so it doesn't need an account
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(web::scope("/search").route("", web::get().to(controllers::search)));
+}
@@ -1,47 +1,267 @@
 use crate::app::types::HashAlgorithm;
 use argon2::{self, Config};
+use derive_more::{Display, Error as DeriveError};
+use hmac::{Hmac, Mac};
+use pbkdf2::Pbkdf2;
 use rand::Rng;
-use std::error::Error;
+use scrypt::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Scrypt,
+};
+use sha2::Sha256;
+use std::{collections::HashMap, error::Error};
+
+/// Work factors new hashes are created with. Stored hashes made with anything else are upgraded
+/// the next time their owner signs in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashCosts {
+    /// Argon2 memory cost in KiB
+    pub argon2_memory_cost: u32,
+    /// Argon2 number of passes
+    pub argon2_time_cost: u32,
+    /// Bcrypt log2 rounds
+    pub bcrypt_cost: u32,
+    /// Scrypt log2 of the CPU/memory cost
+    pub scrypt_log_n: u8,
+    /// Scrypt block size
+    pub scrypt_r: u32,
+    /// Scrypt parallelism
+    pub scrypt_p: u32,
+    /// PBKDF2-SHA256 iterations
+    pub pbkdf2_rounds: u32,
+}
+
+impl Default for HashCosts {
+    fn default() -> Self {
+        let argon2_config = Config::default();
+        Self {
+            argon2_memory_cost: argon2_config.mem_cost,
+            argon2_time_cost: argon2_config.time_cost,
+            bcrypt_cost: 10,
+            scrypt_log_n: scrypt::Params::RECOMMENDED_LOG_N,
+            scrypt_r: scrypt::Params::RECOMMENDED_R,
+            scrypt_p: scrypt::Params::RECOMMENDED_P,
+            pbkdf2_rounds: pbkdf2::Params::RECOMMENDED_ROUNDS as u32,
+        }
+    }
+}
+
+/// Server side secrets that passwords are run through (HMAC-SHA256) before they're hashed, so a
+/// dump of the credentials table alone isn't enough to start cracking. Each credential records
+/// the id of the pepper it was made with, which lets old peppers be retired gradually.
+#[derive(Debug, Clone, Default)]
+pub struct Peppers {
+    /// Pepper new hashes are made with. `None` stores new hashes unpeppered.
+    pub current: Option<String>,
+    pub keys: HashMap<String, Vec<u8>>,
+}
+
+#[derive(Debug, Display, DeriveError)]
+pub enum CredentialError {
+    #[display(fmt = "credential was hashed with an unknown pepper")]
+    UnknownPepper,
+}
 
 pub struct CredentialManager {
     pub algorithm: HashAlgorithm,
+    pub costs: HashCosts,
+    pub peppers: Peppers,
 }
 
 impl CredentialManager {
-    pub fn new(algorithm: HashAlgorithm) -> Self {
-        Self { algorithm }
+    pub fn with_costs(algorithm: HashAlgorithm, costs: HashCosts) -> Self {
+        Self {
+            algorithm,
+            costs,
+            peppers: Peppers::default(),
+        }
+    }
+
+    pub fn with_peppers(self, peppers: Peppers) -> Self {
+        Self { peppers, ..self }
+    }
+
+    /// Id of the pepper `create_hash` uses, to be stored next to the hash
+    pub fn pepper_id(&self) -> Option<String> {
+        self.peppers.current.clone()
+    }
+
+    fn apply_pepper(
+        &self,
+        candidate: &[u8],
+        pepper_id: Option<&str>,
+    ) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let Some(pepper_id) = pepper_id else {
+            return Ok(candidate.to_vec());
+        };
+        let key = self
+            .peppers
+            .keys
+            .get(pepper_id)
+            .ok_or(CredentialError::UnknownPepper)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+        mac.update(candidate);
+        // hex keeps the result free of nul bytes and under bcrypt's 72 byte limit
+        Ok(mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>()
+            .into_bytes())
     }
 
     fn gen_random_bytes(&self) -> [u8; 16] {
         rand::thread_rng().gen::<[u8; 16]>()
     }
 
+    fn argon2_config(&self) -> Config<'static> {
+        Config {
+            mem_cost: self.costs.argon2_memory_cost,
+            time_cost: self.costs.argon2_time_cost,
+            ..Config::default()
+        }
+    }
+
     fn hash_argon2(&self, candidate: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
         let salt = self.gen_random_bytes();
-        let config = Config::default();
-        let hash = argon2::hash_encoded(candidate, &salt, &config)?;
+        let hash = argon2::hash_encoded(candidate, &salt, &self.argon2_config())?;
         Ok(hash)
     }
 
     fn hash_bcrypt(&self, candidate: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
         let salt = self.gen_random_bytes();
-        let hash_result = bcrypt::hash_with_salt(candidate, 10, salt)?;
+        let hash_result = bcrypt::hash_with_salt(candidate, self.costs.bcrypt_cost, salt)?;
         Ok(hash_result.to_string())
     }
 
+    fn scrypt_params(&self) -> Result<scrypt::Params, scrypt::errors::InvalidParams> {
+        scrypt::Params::new(
+            self.costs.scrypt_log_n,
+            self.costs.scrypt_r,
+            self.costs.scrypt_p,
+            scrypt::Params::RECOMMENDED_LEN,
+        )
+    }
+
+    fn pbkdf2_params(&self) -> pbkdf2::Params {
+        pbkdf2::Params {
+            rounds: self.costs.pbkdf2_rounds,
+            ..pbkdf2::Params::default()
+        }
+    }
+
+    fn hash_scrypt(&self, candidate: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let salt = SaltString::encode_b64(&self.gen_random_bytes())?;
+        let hash =
+            Scrypt.hash_password_customized(candidate, None, None, self.scrypt_params()?, &salt)?;
+        Ok(hash.to_string())
+    }
+
+    fn hash_pbkdf2(&self, candidate: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let salt = SaltString::encode_b64(&self.gen_random_bytes())?;
+        let hash = Pbkdf2.hash_password_customized(
+            candidate,
+            Some(pbkdf2::Algorithm::Pbkdf2Sha256.ident()),
+            None,
+            self.pbkdf2_params(),
+            &salt,
+        )?;
+        Ok(hash.to_string())
+    }
+
+    /// Hash a password with the current algorithm, work factors and pepper
     pub fn create_hash(&self, candidate: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let candidate = &self.apply_pepper(candidate, self.peppers.current.as_deref())?;
         match self.algorithm {
             HashAlgorithm::Argon2 => self.hash_argon2(candidate),
             HashAlgorithm::Bcrypt => self.hash_bcrypt(candidate),
+            HashAlgorithm::Scrypt => self.hash_scrypt(candidate),
+            HashAlgorithm::Pbkdf2 => self.hash_pbkdf2(candidate),
         }
     }
 
-    pub fn verify_hash(&self, candidate: &str, hash: &str) -> bool {
-        match self.algorithm {
+    /// Check a candidate password against a stored hash. `algorithm` and `pepper_id` are the ones
+    /// the hash was stored with, which aren't necessarily the ones new hashes are made with.
+    pub fn verify_hash(
+        &self,
+        candidate: &str,
+        hash: &str,
+        algorithm: &HashAlgorithm,
+        pepper_id: Option<&str>,
+    ) -> bool {
+        let candidate = match self.apply_pepper(candidate.as_bytes(), pepper_id) {
+            Ok(candidate) => candidate,
+            Err(e) => {
+                log::error!("{e}");
+                return false;
+            }
+        };
+        match algorithm {
+            HashAlgorithm::Argon2 => argon2::verify_encoded(hash, &candidate).unwrap_or(false),
+            HashAlgorithm::Bcrypt => bcrypt::verify(&candidate, hash).unwrap_or(false),
+            HashAlgorithm::Scrypt => PasswordHash::new(hash)
+                .and_then(|parsed| Scrypt.verify_password(&candidate, &parsed))
+                .is_ok(),
+            // imported hashes may use any of the digests the pbkdf2 crate understands
+            HashAlgorithm::Pbkdf2 => PasswordHash::new(hash)
+                .and_then(|parsed| Pbkdf2.verify_password(&candidate, &parsed))
+                .is_ok(),
+        }
+    }
+
+    /// Whether a stored hash was made with a different algorithm, work factors or pepper than the
+    /// current policy. Hashes that can't be parsed are treated as outdated.
+    pub fn needs_rehash(
+        &self,
+        hash: &str,
+        algorithm: &HashAlgorithm,
+        pepper_id: Option<&str>,
+    ) -> bool {
+        if *algorithm != self.algorithm || pepper_id != self.peppers.current.as_deref() {
+            return true;
+        }
+        match algorithm {
             HashAlgorithm::Argon2 => {
-                argon2::verify_encoded(hash, candidate.as_bytes()).unwrap_or(false)
+                let config = self.argon2_config();
+                let expected = format!(
+                    "m={},t={},p={}",
+                    config.mem_cost, config.time_cost, config.lanes
+                );
+                // $<variant>$v=<version>$m=<mem>,t=<time>,p=<lanes>$<salt>$<hash>
+                let parts: Vec<&str> = hash.split('$').collect();
+                parts.len() != 6
+                    || parts[1] != config.variant.as_lowercase_str()
+                    || parts[2] != format!("v={}", config.version.as_u32())
+                    || parts[3] != expected
             }
-            HashAlgorithm::Bcrypt => bcrypt::verify(candidate, hash).unwrap_or(false),
+            HashAlgorithm::Bcrypt => {
+                // $<version>$<cost>$<salt and hash>
+                let cost = hash.split('$').nth(2).and_then(|c| c.parse::<u32>().ok());
+                cost != Some(self.costs.bcrypt_cost)
+            }
+            HashAlgorithm::Scrypt => {
+                let params = PasswordHash::new(hash)
+                    .ok()
+                    .and_then(|parsed| scrypt::Params::try_from(&parsed).ok());
+                match (params, self.scrypt_params()) {
+                    (Some(stored), Ok(current)) => {
+                        stored.log_n() != current.log_n()
+                            || stored.r() != current.r()
+                            || stored.p() != current.p()
+                    }
+                    _ => true,
+                }
+            }
+            HashAlgorithm::Pbkdf2 => match PasswordHash::new(hash) {
+                Ok(parsed) => {
+                    parsed.algorithm != pbkdf2::Algorithm::Pbkdf2Sha256.ident()
+                        || pbkdf2::Params::try_from(&parsed)
+                            .map(|stored| stored != self.pbkdf2_params())
+                            .unwrap_or(true)
+                }
+                Err(_) => true,
+            },
         }
     }
 }
@@ -54,26 +274,158 @@ mod tests {
     pub fn test_credential_manager() {
         let plaintext = "jennysinha";
 
-        let mut manager = CredentialManager {
-            algorithm: HashAlgorithm::Argon2,
-        };
+        let mut manager =
+            CredentialManager::with_costs(HashAlgorithm::Argon2, HashCosts::default());
 
         let argon2_hash = manager.create_hash(plaintext.as_bytes()).unwrap();
 
-        let mut correct = manager.verify_hash(plaintext, &argon2_hash);
+        let mut correct =
+            manager.verify_hash(plaintext, &argon2_hash, &HashAlgorithm::Argon2, None);
         assert!(correct);
 
-        let mut incorrect = manager.verify_hash("incorrect_password", &argon2_hash);
+        let mut incorrect = manager.verify_hash(
+            "incorrect_password",
+            &argon2_hash,
+            &HashAlgorithm::Argon2,
+            None,
+        );
         assert!(!incorrect);
 
         manager.algorithm = HashAlgorithm::Bcrypt;
 
         let bcrypt_hash = manager.create_hash(plaintext.as_bytes()).unwrap();
 
-        correct = manager.verify_hash(plaintext, &bcrypt_hash);
+        correct = manager.verify_hash(plaintext, &bcrypt_hash, &HashAlgorithm::Bcrypt, None);
         assert!(correct);
 
-        incorrect = manager.verify_hash("incorrect_password", &bcrypt_hash);
+        incorrect = manager.verify_hash(
+            "incorrect_password",
+            &bcrypt_hash,
+            &HashAlgorithm::Bcrypt,
+            None,
+        );
         assert!(!incorrect);
+
+        // switching the configured algorithm doesn't lock out users hashed with the old one
+        correct = manager.verify_hash(plaintext, &argon2_hash, &HashAlgorithm::Argon2, None);
+        assert!(correct);
+    }
+
+    #[test]
+    pub fn test_needs_rehash() {
+        let manager = CredentialManager::with_costs(HashAlgorithm::Argon2, HashCosts::default());
+        let argon2_hash = manager.create_hash(b"jennysinha").unwrap();
+        assert!(!manager.needs_rehash(&argon2_hash, &HashAlgorithm::Argon2, None));
+
+        let stronger = CredentialManager::with_costs(
+            HashAlgorithm::Argon2,
+            HashCosts {
+                argon2_memory_cost: manager.costs.argon2_memory_cost * 2,
+                ..HashCosts::default()
+            },
+        );
+        assert!(stronger.needs_rehash(&argon2_hash, &HashAlgorithm::Argon2, None));
+        assert!(stronger.needs_rehash("garbage", &HashAlgorithm::Argon2, None));
+
+        let bcrypt = CredentialManager::with_costs(HashAlgorithm::Bcrypt, HashCosts::default());
+        assert!(bcrypt.needs_rehash(&argon2_hash, &HashAlgorithm::Argon2, None));
+
+        let bcrypt_hash = bcrypt.create_hash(b"jennysinha").unwrap();
+        assert!(!bcrypt.needs_rehash(&bcrypt_hash, &HashAlgorithm::Bcrypt, None));
+
+        let cheaper = CredentialManager::with_costs(
+            HashAlgorithm::Bcrypt,
+            HashCosts {
+                bcrypt_cost: 8,
+                ..HashCosts::default()
+            },
+        );
+        assert!(cheaper.needs_rehash(&bcrypt_hash, &HashAlgorithm::Bcrypt, None));
+    }
+
+    #[test]
+    pub fn test_phc_algorithms() {
+        // cheap work factors so the test doesn't crawl in debug builds
+        let costs = HashCosts {
+            scrypt_log_n: 10,
+            pbkdf2_rounds: 1000,
+            ..HashCosts::default()
+        };
+
+        for algorithm in [HashAlgorithm::Scrypt, HashAlgorithm::Pbkdf2] {
+            let manager = CredentialManager::with_costs(algorithm.clone(), costs.clone());
+            let hash = manager.create_hash(b"jennysinha").unwrap();
+            assert!(hash.starts_with(match algorithm {
+                HashAlgorithm::Scrypt => "$scrypt$",
+                _ => "$pbkdf2-sha256$",
+            }));
+
+            assert!(manager.verify_hash("jennysinha", &hash, &algorithm, None));
+            assert!(!manager.verify_hash("incorrect_password", &hash, &algorithm, None));
+            assert!(!manager.needs_rehash(&hash, &algorithm, None));
+
+            let stronger = CredentialManager::with_costs(
+                algorithm.clone(),
+                HashCosts {
+                    scrypt_log_n: 11,
+                    pbkdf2_rounds: 2000,
+                    ..costs.clone()
+                },
+            );
+            assert!(stronger.needs_rehash(&hash, &algorithm, None));
+        }
+
+        // an account imported from a system that used pbkdf2 with sha512
+        let manager = CredentialManager::with_costs(HashAlgorithm::Pbkdf2, costs);
+        let salt = SaltString::encode_b64(b"importedsaltdata").unwrap();
+        let imported = Pbkdf2
+            .hash_password_customized(
+                b"jennysinha",
+                Some(pbkdf2::Algorithm::Pbkdf2Sha512.ident()),
+                None,
+                pbkdf2::Params {
+                    rounds: 1000,
+                    output_length: 64,
+                },
+                &salt,
+            )
+            .unwrap()
+            .to_string();
+        assert!(manager.verify_hash("jennysinha", &imported, &HashAlgorithm::Pbkdf2, None));
+        assert!(manager.needs_rehash(&imported, &HashAlgorithm::Pbkdf2, None));
+    }
+
+    #[test]
+    pub fn test_pepper() {
+        let peppers = Peppers {
+            current: Some("v1".to_owned()),
+            keys: HashMap::from([("v1".to_owned(), b"pepper one".to_vec())]),
+        };
+        let manager = CredentialManager::with_costs(HashAlgorithm::Bcrypt, HashCosts::default())
+            .with_peppers(peppers.clone());
+        let hash = manager.create_hash(b"jennysinha").unwrap();
+        let alg = &HashAlgorithm::Bcrypt;
+
+        assert!(manager.verify_hash("jennysinha", &hash, alg, Some("v1")));
+        assert!(!manager.verify_hash("incorrect_password", &hash, alg, Some("v1")));
+        // the hash is of the peppered password, not the password itself
+        assert!(!manager.verify_hash("jennysinha", &hash, alg, None));
+        assert!(!manager.verify_hash("jennysinha", &hash, alg, Some("v0")));
+        assert!(!manager.needs_rehash(&hash, alg, Some("v1")));
+
+        // rotating keeps the old pepper around for verification and asks for a rehash
+        let mut rotated = peppers;
+        rotated.current = Some("v2".to_owned());
+        rotated.keys.insert("v2".to_owned(), b"pepper two".to_vec());
+        let manager = CredentialManager::with_costs(HashAlgorithm::Bcrypt, HashCosts::default())
+            .with_peppers(rotated);
+        assert!(manager.verify_hash("jennysinha", &hash, alg, Some("v1")));
+        assert!(manager.needs_rehash(&hash, alg, Some("v1")));
+
+        let unpeppered = CredentialManager::with_costs(HashAlgorithm::Bcrypt, HashCosts::default())
+            .create_hash(b"jennysinha")
+            .unwrap();
+        assert!(manager.verify_hash("jennysinha", &unpeppered, alg, None));
+        assert!(manager.needs_rehash(&unpeppered, alg, None));
     }
 }
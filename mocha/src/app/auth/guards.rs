@@ -9,12 +9,19 @@ use crate::app::state::AppState;
 
 use super::tokens::{self, Claims};
 
+/// Requires a valid session cookie. Clients that don't keep cookies can go without one as long as
+/// the jwt guard has already accepted their access token; scopes wrap the session guard first, so
+/// the jwt guard runs before it. A cookie that is present but invalid is always rejected.
 pub async fn session_guard(
     req: ServiceRequest,
     state: Data<AppState>,
 ) -> Result<ServiceRequest, (actix_web::Error, ServiceRequest)> {
-    let cookie_value = req
-        .cookie("mocha_session")
+    let cookie = req.cookie("mocha_session");
+    if cookie.is_none() && req.extensions().get::<Claims>().is_some() {
+        return Ok(req);
+    }
+
+    let cookie_value = cookie
         .map(|cookie| cookie.value().to_owned())
         .unwrap_or("".to_owned());
 
@@ -35,18 +42,32 @@ pub async fn session_guard(
     }
 }
 
+/// Requires a valid, unrevoked access token. If the denylist can't be reached the token is rejected
+/// rather than trusted.
 pub async fn jwt_guard(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (actix_web::error::Error, ServiceRequest)> {
-    let result = tokens::verify_rs256(credentials.token());
-    match result {
-        Ok(jwt) => {
+    let jwt = match tokens::verify_rs256(credentials.token()) {
+        Ok(jwt) => jwt,
+        Err(_) => return Err((ErrorUnauthorized("invalid token".to_owned()), req)),
+    };
+
+    let revoked = match req.app_data::<Data<AppState>>() {
+        Some(state) => tokens::is_access_token_revoked(&state.storage_layer, &jwt.claims).await,
+        None => Err("app state is not configured".into()),
+    };
+    match revoked {
+        Ok(false) => {
             let permissions: Vec<String> = jwt.claims.access.permissions.clone();
             req.attach(permissions);
             req.extensions_mut().insert::<Claims>(jwt.claims);
             Ok(req)
         }
-        Err(_) => Err((ErrorUnauthorized("invalid token".to_owned()), req)),
+        Ok(true) => Err((ErrorUnauthorized("invalid token".to_owned()), req)),
+        Err(e) => {
+            log::error!("{e}");
+            Err((ErrorUnauthorized("invalid token".to_owned()), req))
+        }
     }
 }
@@ -1,6 +1,9 @@
 pub mod credentials;
 pub mod guards;
+pub mod oidc;
 pub mod sessions;
 pub mod tokens;
+pub mod totp;
+pub mod webauthn;
 
 pub use credentials::CredentialManager;
@@ -0,0 +1,686 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use derive_more::{Display, Error as DeriveError};
+use jsonwebtoken::{
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use rand::Rng;
+use reqwest::{header::ACCEPT, redirect::Policy, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+pub static DEFAULT_OIDC_SCOPES: &str = "openid email profile";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+const METADATA_TTL: Duration = Duration::from_secs(60 * 60);
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+static CLOCK_SKEW: u64 = 60;
+static CODE_VERIFIER_LENGTH: usize = 32;
+static NONCE_LENGTH: usize = 32;
+
+#[derive(Debug, Display, DeriveError)]
+pub enum OidcError {
+    #[display(fmt = "unknown identity provider")]
+    UnknownProvider,
+    #[display(fmt = "error talking to the identity provider: {_0}")]
+    #[error(ignore)]
+    Provider(String),
+    #[display(fmt = "provider turned down the authorization code: {_0}")]
+    #[error(ignore)]
+    Rejected(String),
+    #[display(fmt = "provider metadata is for a different issuer")]
+    IssuerMismatch,
+    #[display(fmt = "invalid id token: {_0}")]
+    #[error(ignore)]
+    InvalidToken(String),
+    #[display(fmt = "id token is for a different sign in")]
+    Nonce,
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(e: reqwest::Error) -> Self {
+        OidcError::Provider(e.to_string())
+    }
+}
+
+/// A provider we know how to sign in with, as configured
+#[derive(Debug, Clone)]
+pub struct OidcProvider {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub scopes: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct Discovered {
+    metadata: ProviderMetadata,
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    aud: serde_json::Value,
+    azp: Option<String>,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<serde_json::Value>,
+    given_name: Option<String>,
+    family_name: Option<String>,
+    name: Option<String>,
+    preferred_username: Option<String>,
+    picture: Option<String>,
+}
+
+/// Who the provider says signed in, taken from a validated id token
+#[derive(Debug, Clone)]
+pub struct ExternalIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub name: Option<String>,
+    pub username: Option<String>,
+    pub picture: Option<String>,
+}
+
+/// Signs users in through OpenID Connect providers with the authorization code flow and PKCE
+pub struct OidcManager {
+    providers: HashMap<String, OidcProvider>,
+    redirect_uri: String,
+    client: reqwest::Client,
+    discovered: RwLock<HashMap<String, Arc<Discovered>>>,
+}
+
+impl OidcManager {
+    pub fn new(providers: HashMap<String, OidcProvider>, redirect_uri: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .redirect(Policy::none())
+            .build()
+            .expect("error building http client");
+        Self {
+            providers,
+            redirect_uri: redirect_uri.to_owned(),
+            client,
+            discovered: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Names of the configured providers, for the sign in page to offer
+    pub fn provider_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.providers.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    fn provider(&self, name: &str) -> Result<&OidcProvider, OidcError> {
+        self.providers.get(name).ok_or(OidcError::UnknownProvider)
+    }
+
+    async fn discover(
+        &self,
+        name: &str,
+        provider: &OidcProvider,
+        refresh: bool,
+    ) -> Result<Arc<Discovered>, OidcError> {
+        let cached = self
+            .discovered
+            .read()
+            .map_err(|_| OidcError::Provider("metadata cache is poisoned".to_owned()))?
+            .get(name)
+            .cloned();
+        if let Some(discovered) = cached {
+            let age = discovered.fetched_at.elapsed();
+            if age < MIN_REFRESH_INTERVAL || (!refresh && age < METADATA_TTL) {
+                return Ok(discovered);
+            }
+        }
+
+        let metadata: ProviderMetadata = self
+            .client
+            .get(format!(
+                "{}/.well-known/openid-configuration",
+                provider.issuer
+            ))
+            .header(ACCEPT, "application/json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if metadata.issuer.trim_end_matches('/') != provider.issuer {
+            return Err(OidcError::IssuerMismatch);
+        }
+        let keys: JwkSet = self
+            .client
+            .get(&metadata.jwks_uri)
+            .header(ACCEPT, "application/json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let discovered = Arc::new(Discovered {
+            metadata,
+            keys,
+            fetched_at: Instant::now(),
+        });
+        self.discovered
+            .write()
+            .map_err(|_| OidcError::Provider("metadata cache is poisoned".to_owned()))?
+            .insert(name.to_owned(), discovered.clone());
+        Ok(discovered)
+    }
+
+    /// Where to send the browser to sign in with a provider
+    pub async fn authorization_url(
+        &self,
+        name: &str,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<String, OidcError> {
+        let provider = self.provider(name)?;
+        let discovered = self.discover(name, provider, false).await?;
+        let url = Url::parse_with_params(
+            &discovered.metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &provider.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", &provider.scopes),
+                ("state", state),
+                ("nonce", nonce),
+                ("code_challenge", &code_challenge(code_verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| OidcError::Provider(e.to_string()))?;
+        Ok(url.to_string())
+    }
+
+    /// Trade the code a provider redirected back with for the identity it was issued for
+    pub async fn exchange_code(
+        &self,
+        name: &str,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, OidcError> {
+        let provider = self.provider(name)?;
+        let discovered = self.discover(name, provider, false).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_uri),
+            ("code_verifier", code_verifier),
+            ("client_id", &provider.client_id),
+        ];
+        if let Some(secret) = &provider.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let response = self
+            .client
+            .post(&discovered.metadata.token_endpoint)
+            .header(ACCEPT, "application/json")
+            .form(&form)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let reply = format!(
+                "token endpoint replied {status}: {}",
+                body.chars().take(200).collect::<String>()
+            );
+            // a 400 means the code, verifier or client didn't check out
+            return Err(match status.is_client_error() {
+                true => OidcError::Rejected(reply),
+                false => OidcError::Provider(reply),
+            });
+        }
+        let tokens: TokenResponse = response.json().await?;
+
+        self.validate_id_token(name, provider, &tokens.id_token, nonce)
+            .await
+    }
+
+    async fn validate_id_token(
+        &self,
+        name: &str,
+        provider: &OidcProvider,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<ExternalIdentity, OidcError> {
+        let invalid = |e: jsonwebtoken::errors::Error| OidcError::InvalidToken(e.to_string());
+        let header = jsonwebtoken::decode_header(id_token).map_err(invalid)?;
+        // only public key signatures
+        if !matches!(
+            header.alg,
+            Algorithm::RS256
+                | Algorithm::RS384
+                | Algorithm::RS512
+                | Algorithm::PS256
+                | Algorithm::PS384
+                | Algorithm::PS512
+                | Algorithm::ES256
+                | Algorithm::ES384
+        ) {
+            return Err(OidcError::InvalidToken(format!(
+                "unsupported algorithm {:?}",
+                header.alg
+            )));
+        }
+
+        let mut discovered = self.discover(name, provider, false).await?;
+        let jwk = match signing_key(&discovered.keys, header.kid.as_deref()) {
+            Some(jwk) => jwk.clone(),
+            None => {
+                // the provider may have rotated its keys since we last looked
+                discovered = self.discover(name, provider, true).await?;
+                signing_key(&discovered.keys, header.kid.as_deref())
+                    .ok_or(OidcError::InvalidToken("unknown signing key".to_owned()))?
+                    .clone()
+            }
+        };
+        let key = DecodingKey::from_jwk(&jwk).map_err(invalid)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&discovered.metadata.issuer]);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_required_spec_claims(&["exp", "iat", "iss", "aud", "sub"]);
+        validation.leeway = CLOCK_SKEW;
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(invalid)?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::Nonce);
+        }
+        // a token for several clients has to say it was handed to us
+        let audiences = claims.aud.as_array().map_or(1, Vec::len);
+        if audiences > 1 && claims.azp.as_deref() != Some(provider.client_id.as_str()) {
+            return Err(OidcError::InvalidToken(
+                "issued to a different client".to_owned(),
+            ));
+        }
+
+        let email_verified = matches!(&claims.email_verified, Some(serde_json::Value::Bool(true)))
+            || matches!(&claims.email_verified, Some(serde_json::Value::String(s)) if s == "true");
+        Ok(ExternalIdentity {
+            provider: name.to_owned(),
+            subject: claims.sub,
+            email: claims.email,
+            email_verified,
+            first_name: claims.given_name,
+            last_name: claims.family_name,
+            name: claims.name,
+            username: claims.preferred_username,
+            picture: claims.picture,
+        })
+    }
+}
+
+fn signing_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+}
+
+/// Generate a new PKCE code verifier (RFC 7636)
+pub fn new_code_verifier() -> String {
+    let mut verifier = vec![0u8; CODE_VERIFIER_LENGTH];
+    rand::thread_rng().fill(&mut verifier[..]);
+    URL_SAFE_NO_PAD.encode(verifier)
+}
+
+/// The S256 challenge sent to the provider in place of the verifier
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+pub fn new_nonce() -> String {
+    let mut nonce = vec![0u8; NONCE_LENGTH];
+    rand::thread_rng().fill(&mut nonce[..]);
+    URL_SAFE_NO_PAD.encode(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header::LOCATION, web, App, HttpResponse, HttpServer};
+    use jsonwebtoken::{EncodingKey, Header};
+    use openssl::rsa::Rsa;
+    use std::{net::TcpListener, sync::Mutex};
+
+    use crate::app::util;
+
+    use super::*;
+
+    static CLIENT_ID: &str = "mocha";
+    static CLIENT_SECRET: &str = "mocha-secret";
+    static REDIRECT_URI: &str = "http://localhost:8888/auth/oidc/callback";
+
+    struct Grant {
+        client_id: String,
+        redirect_uri: String,
+        nonce: String,
+        code_challenge: String,
+    }
+
+    struct MockProvider {
+        issuer: String,
+        kid: String,
+        key: EncodingKey,
+        jwk: serde_json::Value,
+        rogue_key: EncodingKey,
+        use_rogue_key: Mutex<bool>,
+        grants: Mutex<HashMap<String, Grant>>,
+        overrides: Mutex<serde_json::Value>,
+    }
+
+    fn rsa_key() -> (EncodingKey, Rsa<openssl::pkey::Private>) {
+        let rsa = Rsa::generate(2048).unwrap();
+        let key = EncodingKey::from_rsa_pem(&rsa.private_key_to_pem().unwrap()).unwrap();
+        (key, rsa)
+    }
+
+    async fn discovery(provider: web::Data<MockProvider>) -> HttpResponse {
+        HttpResponse::Ok().json(serde_json::json!({
+            "issuer": provider.issuer,
+            "authorization_endpoint": format!("{}/authorize", provider.issuer),
+            "token_endpoint": format!("{}/token", provider.issuer),
+            "jwks_uri": format!("{}/jwks", provider.issuer),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+            "code_challenge_methods_supported": ["S256"]
+        }))
+    }
+
+    async fn jwks(provider: web::Data<MockProvider>) -> HttpResponse {
+        HttpResponse::Ok().json(serde_json::json!({ "keys": [provider.jwk] }))
+    }
+
+    async fn authorize(
+        provider: web::Data<MockProvider>,
+        query: web::Query<HashMap<String, String>>,
+    ) -> HttpResponse {
+        let param = |k: &str| query.get(k).cloned().unwrap_or_default();
+        if param("response_type") != "code"
+            || param("code_challenge_method") != "S256"
+            || !param("scope").split(' ').any(|s| s == "openid")
+        {
+            return HttpResponse::BadRequest().finish();
+        }
+
+        let code = util::rng::random_string(24);
+        provider.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                client_id: param("client_id"),
+                redirect_uri: param("redirect_uri"),
+                nonce: param("nonce"),
+                code_challenge: param("code_challenge"),
+            },
+        );
+        let location = Url::parse_with_params(
+            &param("redirect_uri"),
+            &[("code", code.as_str()), ("state", &param("state"))],
+        )
+        .unwrap();
+        HttpResponse::Found()
+            .insert_header((LOCATION, location.to_string()))
+            .finish()
+    }
+
+    async fn token(
+        provider: web::Data<MockProvider>,
+        form: web::Form<HashMap<String, String>>,
+    ) -> HttpResponse {
+        let param = |k: &str| form.get(k).cloned().unwrap_or_default();
+        let invalid_grant =
+            || HttpResponse::BadRequest().json(serde_json::json!({"error": "invalid_grant"}));
+        let grant = match provider.grants.lock().unwrap().remove(&param("code")) {
+            Some(grant) => grant,
+            None => return invalid_grant(),
+        };
+        if param("grant_type") != "authorization_code"
+            || param("client_id") != grant.client_id
+            || param("client_secret") != CLIENT_SECRET
+            || param("redirect_uri") != grant.redirect_uri
+            || code_challenge(&param("code_verifier")) != grant.code_challenge
+        {
+            return invalid_grant();
+        }
+
+        let now = util::time::now();
+        let mut claims = serde_json::json!({
+            "iss": provider.issuer,
+            "sub": "248289761001",
+            "aud": grant.client_id,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": grant.nonce,
+            "email": "jenny@example.com",
+            "email_verified": true,
+            "given_name": "Jenny",
+            "family_name": "Sinha",
+            "preferred_username": "jenny"
+        });
+        if let Some(overrides) = provider.overrides.lock().unwrap().as_object() {
+            for (k, v) in overrides {
+                claims[k] = v.clone();
+            }
+        }
+
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(provider.kid.clone());
+        let key = match *provider.use_rogue_key.lock().unwrap() {
+            true => &provider.rogue_key,
+            false => &provider.key,
+        };
+        let id_token = jsonwebtoken::encode(&header, &claims, key).unwrap();
+        HttpResponse::Ok().json(serde_json::json!({
+            "access_token": util::rng::random_string(24),
+            "token_type": "Bearer",
+            "expires_in": 300,
+            "id_token": id_token
+        }))
+    }
+
+    async fn start_provider() -> web::Data<MockProvider> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let (key, rsa) = rsa_key();
+        let kid = "mock-1".to_owned();
+        let provider = web::Data::new(MockProvider {
+            issuer,
+            jwk: serde_json::json!({
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": kid,
+                "n": URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                "e": URL_SAFE_NO_PAD.encode(rsa.e().to_vec())
+            }),
+            kid,
+            key,
+            rogue_key: rsa_key().0,
+            use_rogue_key: Mutex::new(false),
+            grants: Mutex::new(HashMap::new()),
+            overrides: Mutex::new(serde_json::json!({})),
+        });
+
+        let data = provider.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(discovery),
+                )
+                .route("/jwks", web::get().to(jwks))
+                .route("/authorize", web::get().to(authorize))
+                .route("/token", web::post().to(token))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+        provider
+    }
+
+    fn manager(provider: &MockProvider) -> OidcManager {
+        let providers = HashMap::from([(
+            "mock".to_owned(),
+            OidcProvider {
+                issuer: provider.issuer.clone(),
+                client_id: CLIENT_ID.to_owned(),
+                client_secret: Some(CLIENT_SECRET.to_owned()),
+                scopes: DEFAULT_OIDC_SCOPES.to_owned(),
+            },
+        )]);
+        OidcManager::new(providers, REDIRECT_URI)
+    }
+
+    async fn authorize_in_browser(url: &str) -> (String, String) {
+        let browser = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .build()
+            .unwrap();
+        let response = browser.get(url).send().await.unwrap();
+        assert_eq!(response.status(), 302);
+        let location = Url::parse(response.headers()[LOCATION.as_str()].to_str().unwrap()).unwrap();
+        assert!(location.as_str().starts_with(REDIRECT_URI));
+        let query: HashMap<_, _> = location.query_pairs().into_owned().collect();
+        (query["code"].clone(), query["state"].clone())
+    }
+
+    async fn sign_in(manager: &OidcManager) -> Result<ExternalIdentity, OidcError> {
+        let (verifier, nonce) = (new_code_verifier(), new_nonce());
+        let url = manager
+            .authorization_url("mock", "some-state", &nonce, &verifier)
+            .await?;
+        let (code, state) = authorize_in_browser(&url).await;
+        assert_eq!(state, "some-state");
+        manager
+            .exchange_code("mock", &code, &verifier, &nonce)
+            .await
+    }
+
+    #[test]
+    fn test_pkce() {
+        // example from RFC 7636 appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+        assert_eq!(new_code_verifier().len(), 43);
+        assert_ne!(new_nonce(), new_nonce());
+    }
+
+    #[actix_web::test]
+    async fn test_oidc_sign_in() {
+        let provider = start_provider().await;
+        let manager = manager(&provider);
+        assert_eq!(manager.provider_names(), vec!["mock"]);
+
+        let identity = sign_in(&manager).await.expect("error signing in");
+        assert_eq!(identity.provider, "mock");
+        assert_eq!(identity.subject, "248289761001");
+        assert_eq!(identity.email.as_deref(), Some("jenny@example.com"));
+        assert!(identity.email_verified);
+        assert_eq!(identity.first_name.as_deref(), Some("Jenny"));
+
+        // some providers send email_verified as a string
+        *provider.overrides.lock().unwrap() = serde_json::json!({"email_verified": "false", "aud": [CLIENT_ID, "other"], "azp": CLIENT_ID});
+        let identity = sign_in(&manager).await.expect("error signing in");
+        assert!(!identity.email_verified);
+
+        assert!(matches!(
+            manager.authorization_url("nope", "s", "n", "v").await,
+            Err(OidcError::UnknownProvider)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_rejected_oidc_sign_ins() {
+        let provider = start_provider().await;
+        let manager = manager(&provider);
+
+        // a code is no good without the verifier from the trip that got it
+        let (verifier, nonce) = (new_code_verifier(), new_nonce());
+        let url = manager
+            .authorization_url("mock", "state", &nonce, &verifier)
+            .await
+            .unwrap();
+        let (code, _) = authorize_in_browser(&url).await;
+        assert!(matches!(
+            manager
+                .exchange_code("mock", &code, &new_code_verifier(), &nonce)
+                .await,
+            Err(OidcError::Rejected(_))
+        ));
+
+        // nor is an id token minted for another trip
+        let url = manager
+            .authorization_url("mock", "state", &nonce, &verifier)
+            .await
+            .unwrap();
+        let (code, _) = authorize_in_browser(&url).await;
+        assert!(matches!(
+            manager
+                .exchange_code("mock", &code, &verifier, &new_nonce())
+                .await,
+            Err(OidcError::Nonce)
+        ));
+
+        let now = util::time::now();
+        for overrides in [
+            serde_json::json!({"aud": "someone-else"}),
+            serde_json::json!({"aud": [CLIENT_ID, "someone-else"]}),
+            serde_json::json!({"iss": "https://accounts.example.com"}),
+            serde_json::json!({"exp": now - 3600, "iat": now - 3900}),
+            serde_json::json!({"sub": null}),
+        ] {
+            *provider.overrides.lock().unwrap() = overrides.clone();
+            assert!(
+                matches!(sign_in(&manager).await, Err(OidcError::InvalidToken(_))),
+                "accepted a token with {overrides}"
+            );
+        }
+        *provider.overrides.lock().unwrap() = serde_json::json!({});
+
+        *provider.use_rogue_key.lock().unwrap() = true;
+        assert!(matches!(
+            sign_in(&manager).await,
+            Err(OidcError::InvalidToken(_))
+        ));
+    }
+}
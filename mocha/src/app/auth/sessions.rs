@@ -1,15 +1,23 @@
 use crate::app::{
     config::StorageLayer,
-    dto::auth::{CreateSession, DeleteSession, GetSessionById},
+    dto::auth::{
+        CreateSession, DeleteExpiredSessions, DeleteSession, DeleteUserSessions, GetSessionById,
+        GetSessionsByUserId, RevokeSessionRefreshTokens, RevokeUserRefreshTokens, TouchSession,
+    },
     entities::auth::Session,
     storage::{postgres, redis},
 };
-use derive_more::Display;
+use chrono::{DateTime, Utc};
+use derive_more::{Display, Error as DeriveError};
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use serde_json::Value;
 use std::{env, time::SystemTime};
 use std::{error::Error, time::UNIX_EPOCH};
 
+/// How long a session can sit before a request counts as new activity. Keeps `updated_at` useful
+/// as a last seen time without writing to the session store on every request.
+const SESSION_TOUCH_INTERVAL: i64 = 60;
+
 #[derive(Debug, Display, PartialEq, Eq)]
 pub enum SessionInterface {
     #[display(fmt = "postgres")]
@@ -18,13 +26,34 @@ pub enum SessionInterface {
     Redis,
 }
 
+#[derive(Debug, Display, DeriveError)]
+pub enum SessionError {
+    #[display(fmt = "session expired")]
+    Expired,
+}
+
 pub struct SessionManager {
     pub interface: SessionInterface,
+    /// Seconds a session lives after it was started, however active it is
+    pub absolute_timeout: i64,
+    /// Seconds a session lives after its last request
+    pub idle_timeout: i64,
 }
 
 impl SessionManager {
-    pub fn new(interface: SessionInterface) -> Self {
-        Self { interface }
+    pub fn new(interface: SessionInterface, absolute_timeout: i64, idle_timeout: i64) -> Self {
+        Self {
+            interface,
+            absolute_timeout,
+            idle_timeout,
+        }
+    }
+
+    /// Seconds a session has left at `now`, whichever of the two timeouts comes first
+    fn remaining(&self, session: &Session, now: DateTime<Utc>) -> i64 {
+        let absolute = self.absolute_timeout - (now - session.created_at).num_seconds();
+        let idle = self.idle_timeout - (now - session.updated_at).num_seconds();
+        absolute.min(idle)
     }
 
     pub fn verify_session_signature(
@@ -57,17 +86,126 @@ impl SessionManager {
             id: session_id.to_owned(),
         };
 
+        let session = self.get_session(storage_layer, dto).await?;
+
+        let now = chrono::offset::Utc::now();
+        if self.remaining(&session, now) <= 0 {
+            self.end_session(
+                storage_layer,
+                DeleteSession {
+                    id: session.id.to_string(),
+                },
+            )
+            .await?;
+            return Err(SessionError::Expired)?;
+        }
+
+        if (now - session.updated_at).num_seconds() >= SESSION_TOUCH_INTERVAL {
+            let mut touched = session.clone();
+            touched.updated_at = now;
+            self.touch_session(
+                storage_layer,
+                TouchSession {
+                    id: session.id.to_string(),
+                    expires_in: self.remaining(&touched, now) as usize,
+                },
+            )
+            .await?;
+        }
+
+        Ok(session)
+    }
+
+    pub async fn get_session(
+        &self,
+        storage_layer: &StorageLayer,
+        data: GetSessionById,
+    ) -> Result<Session, Box<dyn Error + Send + Sync>> {
+        match self.interface {
+            SessionInterface::Postgres => {
+                Ok(postgres::auth::get_session(&storage_layer.pg, data).await?)
+            }
+            SessionInterface::Redis => {
+                let mut conn = storage_layer.redis.get().await?;
+                Ok(redis::auth::get_session(&mut conn, data).await?)
+            }
+        }
+    }
+
+    pub async fn touch_session(
+        &self,
+        storage_layer: &StorageLayer,
+        data: TouchSession,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.interface {
+            SessionInterface::Postgres => {
+                Ok(postgres::auth::touch_session(&storage_layer.pg, data).await?)
+            }
+            SessionInterface::Redis => {
+                let mut conn = storage_layer.redis.get().await?;
+                Ok(redis::auth::touch_session(&mut conn, data).await?)
+            }
+        }
+    }
+
+    /// Delete sessions that have timed out. Redis expires its keys by itself, so this only has
+    /// work to do for postgres.
+    pub async fn delete_expired_sessions(
+        &self,
+        storage_layer: &StorageLayer,
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        match self.interface {
+            SessionInterface::Postgres => Ok(postgres::auth::delete_expired_sessions(
+                &storage_layer.pg,
+                DeleteExpiredSessions {
+                    absolute_timeout: self.absolute_timeout,
+                    idle_timeout: self.idle_timeout,
+                },
+            )
+            .await?),
+            SessionInterface::Redis => Ok(0),
+        }
+    }
+
+    pub async fn get_user_sessions(
+        &self,
+        storage_layer: &StorageLayer,
+        data: GetSessionsByUserId,
+    ) -> Result<Vec<Session>, Box<dyn Error + Send + Sync>> {
         match self.interface {
             SessionInterface::Postgres => {
-                Ok(postgres::auth::get_session(&storage_layer.pg, dto).await?)
+                Ok(postgres::auth::get_user_sessions(&storage_layer.pg, data).await?)
             }
             SessionInterface::Redis => {
                 let mut conn = storage_layer.redis.get().await?;
-                Ok(redis::auth::get_session(&mut conn, dto).await?)
+                Ok(redis::auth::get_user_sessions(&mut conn, data).await?)
             }
         }
     }
 
+    /// End a user's sessions along with the refresh tokens issued with them
+    pub async fn end_user_sessions(
+        &self,
+        storage_layer: &StorageLayer,
+        data: DeleteUserSessions,
+    ) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        let revoke = RevokeUserRefreshTokens {
+            user_id: data.user_id.clone(),
+            except_session: data.except.clone(),
+        };
+        let ended = match self.interface {
+            SessionInterface::Postgres => {
+                postgres::auth::end_user_sessions(&storage_layer.pg, data).await?
+            }
+            SessionInterface::Redis => {
+                let mut conn = storage_layer.redis.get().await?;
+                redis::auth::end_user_sessions(&mut conn, data).await?
+            }
+        };
+        postgres::tokens::revoke_user_refresh_tokens(&storage_layer.pg, revoke).await?;
+        Ok(ended)
+    }
+
     pub fn create_signed_cookie(
         &self,
         session_id: &str,
@@ -104,28 +242,62 @@ impl SessionManager {
             }
             SessionInterface::Redis => {
                 let mut conn = storage_layer.redis.get().await?;
-                let id = redis::auth::start_session(&mut conn, data).await?;
+                let expires_in = self.absolute_timeout.min(self.idle_timeout) as usize;
+                let id = redis::auth::start_session(&mut conn, data, expires_in).await?;
                 Ok(id)
             }
         }
     }
 
+    /// End a session along with the refresh tokens issued with it
     pub async fn end_session(
         &self,
         storage_layer: &StorageLayer,
         data: DeleteSession,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let revoke = RevokeSessionRefreshTokens {
+            session_id: data.id.clone(),
+        };
         match self.interface {
             SessionInterface::Postgres => {
                 let pool = &storage_layer.pg;
                 postgres::auth::end_session(pool, data).await?;
-                Ok(())
             }
             SessionInterface::Redis => {
                 let mut conn = storage_layer.redis.get().await?;
                 redis::auth::end_session(&mut conn, data).await?;
-                Ok(())
             }
         }
+        postgres::tokens::revoke_session_refresh_tokens(&storage_layer.pg, revoke).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn test_remaining() {
+        let manager = SessionManager::new(SessionInterface::Postgres, 1000, 100);
+        let now = chrono::offset::Utc::now();
+        let session = |age: i64, idle: i64| Session {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            data: serde_json::json!({}),
+            created_at: now - Duration::seconds(age),
+            updated_at: now - Duration::seconds(idle),
+        };
+
+        assert_eq!(manager.remaining(&session(0, 0), now), 100);
+        assert_eq!(manager.remaining(&session(500, 30), now), 70);
+        // idle timeout has passed
+        assert!(manager.remaining(&session(500, 100), now) <= 0);
+        // absolute timeout caps a session that was just active
+        assert_eq!(manager.remaining(&session(950, 0), now), 50);
+        assert!(manager.remaining(&session(1000, 0), now) <= 0);
     }
 }
@@ -1,20 +1,36 @@
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::env;
 use std::error::Error;
 use uuid::Uuid;
 
+use crate::app::auth::sessions::SessionManager;
 use crate::app::config::StorageLayer;
-use crate::app::dto::auth::GetUserRbac;
+use crate::app::dto::auth::{
+    CheckAccessToken, CreateRefreshToken, DeleteUserSessions, GetUserRbac, InvalidateUserTokens,
+    RevokeAccessToken,
+};
 use crate::app::entities::auth::{UserAccess, UserRbac};
-use crate::app::storage::postgres;
+use crate::app::storage::{postgres, redis};
 use crate::app::util;
 
 pub static ISS: &str = "milkandmocha";
 pub static AUD: &str = "milkandmocha";
 pub static ACCESS_TOKEN_LIFETIME: usize = 60 * 5;
-// pub static REFRESH_TOKEN_LIFETIME: usize = 60 * 60 * 24 * 30 * 3;
-//
+pub static REFRESH_TOKEN_LIFETIME: usize = 60 * 60 * 24 * 30 * 3;
+static REFRESH_TOKEN_LENGTH: usize = 64;
+pub static PASSWORD_RESET_TOKEN_LIFETIME: usize = 60 * 30;
+static PASSWORD_RESET_TOKEN_LENGTH: usize = 48;
+pub static EMAIL_VERIFICATION_TOKEN_LIFETIME: usize = 60 * 60 * 24;
+pub static EMAIL_CHANGE_TOKEN_LIFETIME: usize = 60 * 60;
+static EMAIL_TOKEN_LENGTH: usize = 48;
+pub static LOGIN_CHALLENGE_LIFETIME: usize = 60 * 5;
+pub static LOGIN_CHALLENGE_MAX_ATTEMPTS: i32 = 5;
+static LOGIN_CHALLENGE_LENGTH: usize = 48;
+pub static OIDC_STATE_LIFETIME: usize = 60 * 10;
+static OIDC_STATE_LENGTH: usize = 48;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserAccessInfo {
     pub roles: Vec<String>,
@@ -100,6 +116,144 @@ impl Claims {
     }
 }
 
+/// Refresh, password reset, email and login challenge tokens are opaque random strings. Only their
+/// sha256 is ever stored, so a leaked table doesn't hand out working tokens.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Generate a new refresh token, returning it along with its hash
+pub fn new_refresh_token() -> (String, String) {
+    let token = util::rng::random_string(REFRESH_TOKEN_LENGTH);
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Generate a new password reset token, returning it along with its hash
+pub fn new_password_reset_token() -> (String, String) {
+    let token = util::rng::random_string(PASSWORD_RESET_TOKEN_LENGTH);
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Generate a new token for an email verification or email change link, returning it along with
+/// its hash
+pub fn new_email_token() -> (String, String) {
+    let token = util::rng::random_string(EMAIL_TOKEN_LENGTH);
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Generate a new token for a login waiting on its second factor, returning it along with its hash
+pub fn new_login_challenge() -> (String, String) {
+    let token = util::rng::random_string(LOGIN_CHALLENGE_LENGTH);
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Generate a new state for a trip to an identity provider, returning it along with its hash
+pub fn new_oidc_state() -> (String, String) {
+    let token = util::rng::random_string(OIDC_STATE_LENGTH);
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Start a new refresh token family for a user who just signed in. The family is revoked when
+/// `session_id` ends.
+pub async fn issue_refresh_token(
+    storage_layer: &StorageLayer,
+    sub: &str,
+    session_id: &str,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let (token, token_hash) = new_refresh_token();
+    postgres::tokens::create_refresh_token(
+        &storage_layer.pg,
+        CreateRefreshToken {
+            user_id: sub.to_owned(),
+            session_id: session_id.to_owned(),
+            token_hash,
+            expires_in: REFRESH_TOKEN_LIFETIME as i64,
+        },
+    )
+    .await?;
+    Ok(token)
+}
+
+/// Deny a single access token for the rest of its lifetime, e.g. the one presented on logout
+pub async fn revoke_access_token(
+    storage_layer: &StorageLayer,
+    claims: &Claims,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut conn = storage_layer.redis.get().await?;
+    redis::tokens::revoke_access_token(
+        &mut conn,
+        RevokeAccessToken {
+            jti: claims.jti.to_owned(),
+            expires_in: claims.exp.saturating_sub(util::time::now()),
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sign a user out everywhere: every session ends along with its refresh tokens, and access tokens
+/// issued before `issued_before` are rejected, so nothing is left to get a fresh access token with
+pub async fn revoke_user_tokens(
+    storage_layer: &StorageLayer,
+    session_manager: &SessionManager,
+    sub: &str,
+    issued_before: usize,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    session_manager
+        .end_user_sessions(
+            storage_layer,
+            DeleteUserSessions {
+                user_id: sub.to_owned(),
+                except: None,
+            },
+        )
+        .await?;
+
+    let mut conn = storage_layer.redis.get().await?;
+    redis::tokens::invalidate_user_tokens(
+        &mut conn,
+        InvalidateUserTokens {
+            user_id: sub.to_owned(),
+            issued_before,
+            expires_in: ACCESS_TOKEN_LIFETIME,
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// `iat` only has second precision. Revocations that expect the user to sign straight back in,
+/// like a password reset, cut off at the current second so the new tokens get through. Ones that
+/// shouldn't let anything through, like an admin's, cut off a second later.
+fn is_revoked(claims: &Claims, jti_revoked: bool, invalid_before: Option<usize>) -> bool {
+    jti_revoked || invalid_before.is_some_and(|before| claims.iat < before)
+}
+
+/// Check an already verified access token against the denylist
+pub async fn is_access_token_revoked(
+    storage_layer: &StorageLayer,
+    claims: &Claims,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let mut conn = storage_layer.redis.get().await?;
+    let (jti_revoked, invalid_before) = redis::tokens::get_access_token_revocation(
+        &mut conn,
+        &CheckAccessToken {
+            jti: claims.jti.to_owned(),
+            user_id: claims.sub.to_owned(),
+        },
+    )
+    .await?;
+    Ok(is_revoked(claims, jti_revoked, invalid_before))
+}
+
 pub fn verify_rs256(token: &str) -> Result<TokenData<Claims>, Box<dyn Error + Send + Sync>> {
     let public_key = env::var("RSA_PUBLIC_KEY")?;
     let decoded = jsonwebtoken::decode::<Claims>(
@@ -117,6 +271,13 @@ mod tests {
     use uuid::Uuid;
 
     use super::*;
+    use crate::app::{
+        auth::sessions::SessionInterface,
+        dto::{
+            auth::CreateSession,
+            users::{CreateUser, DeleteUser},
+        },
+    };
 
     use std::sync::Once;
 
@@ -165,4 +326,120 @@ mod tests {
         let _verified_claims = verify_rs256(&_signed).unwrap().claims;
         // println!("{:#?}", _verified_claims);
     }
+
+    #[test]
+    fn test_new_refresh_token() {
+        let (token, hash) = new_refresh_token();
+        assert_eq!(token.len(), REFRESH_TOKEN_LENGTH);
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, hash_token(&token));
+        assert_ne!(new_refresh_token().0, token);
+    }
+
+    #[test]
+    fn test_is_revoked() {
+        let claims = Claims {
+            sub: "sub".to_owned(),
+            iss: ISS.to_owned(),
+            aud: AUD.to_owned(),
+            jti: Uuid::new_v4().to_string(),
+            iat: 1000,
+            exp: 1000 + ACCESS_TOKEN_LIFETIME,
+            nbf: 1000,
+            access: UserAccessInfo {
+                roles: vec![],
+                permissions: vec![],
+            },
+        };
+
+        assert!(!is_revoked(&claims, false, None));
+        assert!(is_revoked(&claims, true, None));
+        assert!(!is_revoked(&claims, false, Some(999)));
+        // e.g. signing in right after resetting a password
+        assert!(!is_revoked(&claims, false, Some(1000)));
+        // e.g. an admin signing the user out in the same second
+        assert!(is_revoked(&claims, false, Some(1001)));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_user_tokens() {
+        util::test_util::init();
+        let storage_layer = StorageLayer::new()
+            .await
+            .expect("error creating storage layer");
+        let random_suffix = util::rng::random_string(6);
+
+        let user_id = postgres::users::create_user(
+            &storage_layer.pg,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating user");
+
+        for interface in [SessionInterface::Postgres, SessionInterface::Redis] {
+            let session_manager = SessionManager::new(interface, 60 * 60, 60 * 60);
+            let now = chrono::offset::Utc::now();
+            let session_id = session_manager
+                .start_session(
+                    &storage_layer,
+                    CreateSession {
+                        user_id: user_id.clone(),
+                        data: serde_json::json!({}),
+                        created_at: now,
+                        updated_at: now,
+                    },
+                )
+                .await
+                .expect("error starting session");
+            let cookie = session_manager
+                .create_signed_cookie(&session_id)
+                .expect("error signing cookie");
+            assert!(session_manager
+                .check_session(&storage_layer, &cookie)
+                .await
+                .is_ok());
+
+            let iat = util::time::now();
+            let claims = Claims {
+                sub: user_id.clone(),
+                iss: ISS.to_owned(),
+                aud: AUD.to_owned(),
+                jti: Uuid::new_v4().to_string(),
+                iat,
+                exp: iat + ACCESS_TOKEN_LIFETIME,
+                nbf: iat,
+                access: UserAccessInfo {
+                    roles: vec![],
+                    permissions: vec![],
+                },
+            };
+
+            revoke_user_tokens(&storage_layer, &session_manager, &user_id, iat + 1)
+                .await
+                .expect("error revoking tokens");
+
+            // the session cookie can't be traded for a new access token, and the one just
+            // issued is rejected
+            assert!(session_manager
+                .check_session(&storage_layer, &cookie)
+                .await
+                .is_err());
+            assert!(is_access_token_revoked(&storage_layer, &claims)
+                .await
+                .expect("error checking access token"));
+        }
+
+        postgres::users::delete_user(&storage_layer.pg, DeleteUser { id: user_id })
+            .await
+            .expect("error deleting user");
+    }
 }
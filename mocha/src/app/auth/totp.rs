@@ -0,0 +1,250 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use derive_more::{Display, Error as DeriveError};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha1::Sha1;
+
+use crate::app::util;
+
+pub static TOTP_ISSUER: &str = "Milk and Mocha";
+pub static TOTP_STEP: usize = 30;
+static TOTP_DIGITS: u32 = 6;
+static TOTP_SECRET_LENGTH: usize = 20;
+static TOTP_SKEW: i64 = 1;
+pub static MAX_FAILED_ATTEMPTS: i32 = 10;
+pub static LOCKOUT: i64 = 60 * 15;
+pub static RECOVERY_CODE_COUNT: usize = 10;
+static RECOVERY_CODE_LENGTH: usize = 16;
+static RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+static BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+static NONCE_LENGTH: usize = 24;
+
+#[derive(Debug, Display, DeriveError)]
+pub enum TotpError {
+    #[display(fmt = "error encrypting totp secret")]
+    Encrypt,
+    #[display(fmt = "error decrypting totp secret")]
+    Decrypt,
+}
+
+/// Generates, encrypts and checks TOTP secrets
+pub struct TotpManager {
+    key: [u8; 32],
+}
+
+impl TotpManager {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+
+    pub fn new_secret(&self) -> Vec<u8> {
+        let mut secret = vec![0u8; TOTP_SECRET_LENGTH];
+        rand::thread_rng().fill(&mut secret[..]);
+        secret
+    }
+
+    /// Encrypt a secret for storage, with the nonce prepended
+    pub fn encrypt_secret(&self, user_id: &str, secret: &[u8]) -> Result<Vec<u8>, TotpError> {
+        let cipher = XChaCha20Poly1305::new(&self.key.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: secret,
+                    aad: user_id.as_bytes(),
+                },
+            )
+            .map_err(|_| TotpError::Encrypt)?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    pub fn decrypt_secret(&self, user_id: &str, encrypted: &[u8]) -> Result<Vec<u8>, TotpError> {
+        if encrypted.len() < NONCE_LENGTH {
+            return Err(TotpError::Decrypt);
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+        let cipher = XChaCha20Poly1305::new(&self.key.into());
+        cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: user_id.as_bytes(),
+                },
+            )
+            .map_err(|_| TotpError::Decrypt)
+    }
+
+    /// The `otpauth://` URI authenticator apps read out of a QR code
+    pub fn provisioning_uri(&self, account: &str, secret: &[u8]) -> String {
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_STEP}",
+            encode_uri_component(TOTP_ISSUER),
+            encode_uri_component(account),
+            base32_encode(secret),
+            encode_uri_component(TOTP_ISSUER),
+        )
+    }
+
+    /// Check a code against a secret, returning the time step it belongs to
+    pub fn verify(&self, secret: &[u8], code: &str, last_used_step: Option<i64>) -> Option<i64> {
+        let code = code.trim();
+        if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let current = (util::time::now() / TOTP_STEP) as i64;
+        (current - TOTP_SKEW..=current + TOTP_SKEW)
+            .filter(|step| last_used_step.is_none_or(|last| *step > last))
+            .find(|step| hotp(secret, *step as u64) == code)
+    }
+}
+
+/// An HOTP (RFC 4226) code for the given counter
+fn hotp(secret: &[u8], counter: u64) -> String {
+    let mut mac =
+        <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("hmac takes keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+/// Unpadded RFC 4648 base32
+fn base32_encode(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+fn encode_uri_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Generate a fresh set of recovery codes, formatted the way they're shown to the user
+pub fn new_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..RECOVERY_CODE_LENGTH)
+                .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())])
+                .map(char::from)
+                .collect();
+            code.as_bytes()
+                .chunks(4)
+                .map(|chunk| std::str::from_utf8(chunk).unwrap())
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect()
+}
+
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hotp() {
+        // test vectors from RFC 6238, truncated to six digits
+        let secret = b"12345678901234567890";
+        assert_eq!(hotp(secret, 59 / 30), "287082");
+        assert_eq!(hotp(secret, 1111111109 / 30), "081804");
+        assert_eq!(hotp(secret, 1234567890 / 30), "005924");
+        assert_eq!(hotp(secret, 2000000000 / 30), "279037");
+    }
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32_encode(b""), "");
+        assert_eq!(base32_encode(b"f"), "MY");
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(
+            base32_encode(b"12345678901234567890"),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+        );
+    }
+
+    #[test]
+    fn test_totp() {
+        let manager = TotpManager::new([7; 32]);
+        let secret = manager.new_secret();
+
+        let encrypted = manager.encrypt_secret("jenny", &secret).unwrap();
+        assert_ne!(encrypted, secret);
+        assert_eq!(manager.decrypt_secret("jenny", &encrypted).unwrap(), secret);
+        // the ciphertext only opens for the user it was made for, and only with the same key
+        assert!(manager.decrypt_secret("anish", &encrypted).is_err());
+        assert!(TotpManager::new([8; 32])
+            .decrypt_secret("jenny", &encrypted)
+            .is_err());
+
+        let step = (util::time::now() / TOTP_STEP) as i64;
+        let code = hotp(&secret, step as u64);
+        assert_eq!(manager.verify(&secret, &code, None), Some(step));
+        assert_eq!(manager.verify(&secret, &code, Some(step - 1)), Some(step));
+        // a code can't be used twice
+        assert_eq!(manager.verify(&secret, &code, Some(step)), None);
+
+        let stale = hotp(&secret, (step - 5) as u64);
+        assert_eq!(manager.verify(&secret, &stale, None), None);
+        assert_eq!(manager.verify(&secret, "12345", None), None);
+
+        let uri = manager.provisioning_uri("jenny@example.com", b"12345678901234567890");
+        assert!(uri.starts_with(
+            "otpauth://totp/Milk%20and%20Mocha:jenny%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&"
+        ));
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = new_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes
+            .iter()
+            .all(|c| c.len() == 19 && c.matches('-').count() == 3));
+        assert_eq!(
+            normalize_recovery_code(" ABCD-efgh-1234-5678 "),
+            "abcdefgh12345678"
+        );
+    }
+}
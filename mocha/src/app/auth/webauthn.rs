@@ -0,0 +1,918 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use derive_more::{Display, Error as DeriveError};
+use openssl::{
+    bn::BigNum,
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{Id, PKey, Public},
+    rsa::Rsa,
+    sign::Verifier,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::app::{
+    dto::auth::{AuthenticationCredential, RegistrationCredential},
+    entities::auth::Passkey,
+};
+
+pub static PASSKEY_RP_NAME: &str = "Milk and Mocha";
+pub static PASSKEY_CHALLENGE_LIFETIME: usize = 60 * 5;
+static CHALLENGE_LENGTH: usize = 32;
+static MAX_CREDENTIAL_ID_LENGTH: usize = 1023;
+
+/// COSE algorithm identifiers for the keys we accept, in order of preference
+pub static COSE_ES256: i32 = -7;
+pub static COSE_EDDSA: i32 = -8;
+pub static COSE_RS256: i32 = -257;
+
+static FLAG_USER_PRESENT: u8 = 0x01;
+static FLAG_USER_VERIFIED: u8 = 0x04;
+static FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+#[derive(Debug, Display, DeriveError, PartialEq, Eq)]
+pub enum WebauthnError {
+    #[display(fmt = "malformed webauthn response")]
+    Encoding,
+    #[display(fmt = "client data is for a different ceremony")]
+    CeremonyType,
+    #[display(fmt = "client data comes from an unexpected origin")]
+    Origin,
+    #[display(fmt = "authenticator data is for a different relying party")]
+    RelyingParty,
+    #[display(fmt = "the authenticator didn't verify the user")]
+    UserVerification,
+    #[display(fmt = "unsupported credential public key")]
+    UnsupportedKey,
+    #[display(fmt = "credential doesn't match the one the authenticator reported")]
+    CredentialMismatch,
+    #[display(fmt = "invalid assertion signature")]
+    Signature,
+    #[display(fmt = "signature counter went backwards. the authenticator may have been cloned")]
+    SignCount,
+}
+
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "jen.passkey_ceremony")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Ceremony {
+    Registration,
+    Authentication,
+}
+
+impl Ceremony {
+    fn client_data_type(&self) -> &'static str {
+        match self {
+            Ceremony::Registration => "webauthn.create",
+            Ceremony::Authentication => "webauthn.get",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony_type: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+/// A credential that passed the registration checks, ready to be stored
+#[derive(Debug, Clone)]
+pub struct VerifiedRegistration {
+    pub challenge: String,
+    pub credential_id: String,
+    pub public_key: Vec<u8>,
+    pub algorithm: i32,
+    pub sign_count: u32,
+    pub aaguid: Uuid,
+}
+
+/// An assertion that passed the authentication checks
+#[derive(Debug, Clone)]
+pub struct VerifiedAssertion {
+    pub challenge: String,
+    pub sign_count: u32,
+}
+
+/// The relying party side of WebAuthn registration and authentication
+pub struct PasskeyManager {
+    pub rp_id: String,
+    pub origin: String,
+}
+
+impl PasskeyManager {
+    pub fn new(rp_id: &str, origin: &str) -> Self {
+        Self {
+            rp_id: rp_id.to_owned(),
+            origin: origin.to_owned(),
+        }
+    }
+
+    /// `PublicKeyCredentialCreationOptions` for `navigator.credentials.create()`
+    pub fn creation_options(
+        &self,
+        challenge: &str,
+        user_id: &Uuid,
+        name: &str,
+        display_name: &str,
+        exclude: &[String],
+    ) -> serde_json::Value {
+        let params: Vec<_> = [COSE_ES256, COSE_EDDSA, COSE_RS256]
+            .iter()
+            .map(|alg| serde_json::json!({ "type": "public-key", "alg": alg }))
+            .collect();
+        let exclude: Vec<_> = exclude
+            .iter()
+            .map(|id| serde_json::json!({ "type": "public-key", "id": id }))
+            .collect();
+        serde_json::json!({
+            "challenge": challenge,
+            "rp": { "id": self.rp_id, "name": PASSKEY_RP_NAME },
+            "user": {
+                "id": URL_SAFE_NO_PAD.encode(user_id.as_bytes()),
+                "name": name,
+                "displayName": display_name
+            },
+            "pubKeyCredParams": params,
+            "timeout": PASSKEY_CHALLENGE_LIFETIME * 1000,
+            "attestation": "none",
+            "authenticatorSelection": {
+                "residentKey": "required",
+                "requireResidentKey": true,
+                "userVerification": "required"
+            },
+            "excludeCredentials": exclude
+        })
+    }
+
+    /// `PublicKeyCredentialRequestOptions` for `navigator.credentials.get()`
+    pub fn request_options(&self, challenge: &str) -> serde_json::Value {
+        serde_json::json!({
+            "challenge": challenge,
+            "rpId": self.rp_id,
+            "timeout": PASSKEY_CHALLENGE_LIFETIME * 1000,
+            "userVerification": "required",
+            "allowCredentials": []
+        })
+    }
+
+    fn check_client_data(
+        &self,
+        client_data_json: &[u8],
+        ceremony: Ceremony,
+    ) -> Result<String, WebauthnError> {
+        let client_data: ClientData =
+            serde_json::from_slice(client_data_json).map_err(|_| WebauthnError::Encoding)?;
+        if client_data.ceremony_type != ceremony.client_data_type() {
+            return Err(WebauthnError::CeremonyType);
+        }
+        if client_data.origin != self.origin || client_data.cross_origin {
+            return Err(WebauthnError::Origin);
+        }
+        Ok(client_data.challenge)
+    }
+
+    fn check_authenticator_data(&self, auth_data: &AuthenticatorData) -> Result<(), WebauthnError> {
+        if auth_data.rp_id_hash != Sha256::digest(self.rp_id.as_bytes()).as_slice() {
+            return Err(WebauthnError::RelyingParty);
+        }
+        let verified = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+        if auth_data.flags & verified != verified {
+            return Err(WebauthnError::UserVerification);
+        }
+        Ok(())
+    }
+
+    /// Verify the response to a registration ceremony
+    pub fn verify_registration(
+        &self,
+        credential: &RegistrationCredential,
+    ) -> Result<VerifiedRegistration, WebauthnError> {
+        if credential.credential_type != "public-key" {
+            return Err(WebauthnError::Encoding);
+        }
+        let client_data_json = decode_base64url(&credential.response.client_data_json)?;
+        let challenge = self.check_client_data(&client_data_json, Ceremony::Registration)?;
+
+        let attestation_object = decode_base64url(&credential.response.attestation_object)?;
+        let (attestation, _) = cbor::decode(&attestation_object).ok_or(WebauthnError::Encoding)?;
+        let auth_data = attestation
+            .get_text("authData")
+            .and_then(|v| v.as_bytes())
+            .ok_or(WebauthnError::Encoding)?;
+        let auth_data = AuthenticatorData::parse(auth_data)?;
+        self.check_authenticator_data(&auth_data)?;
+
+        let attested = auth_data.attested.ok_or(WebauthnError::Encoding)?;
+        if attested.credential_id != decode_base64url(&credential.raw_id)?
+            || attested.credential_id != decode_base64url(&credential.id)?
+        {
+            return Err(WebauthnError::CredentialMismatch);
+        }
+        let (public_key, algorithm) = cose_public_key(&attested.public_key)?;
+
+        Ok(VerifiedRegistration {
+            challenge,
+            credential_id: URL_SAFE_NO_PAD.encode(&attested.credential_id),
+            public_key: public_key
+                .public_key_to_der()
+                .map_err(|_| WebauthnError::UnsupportedKey)?,
+            algorithm,
+            sign_count: auth_data.sign_count,
+            aaguid: attested.aaguid,
+        })
+    }
+
+    /// Verify the response to an authentication ceremony against the passkey it claims to be from
+    pub fn verify_authentication(
+        &self,
+        credential: &AuthenticationCredential,
+        passkey: &Passkey,
+    ) -> Result<VerifiedAssertion, WebauthnError> {
+        if credential.credential_type != "public-key" {
+            return Err(WebauthnError::Encoding);
+        }
+        if decode_base64url(&credential.raw_id)? != decode_base64url(&passkey.credential_id)? {
+            return Err(WebauthnError::CredentialMismatch);
+        }
+        // the user handle is the id we gave the authenticator when the passkey was made
+        if let Some(user_handle) = &credential.response.user_handle {
+            if decode_base64url(user_handle)? != passkey.user_id.as_bytes() {
+                return Err(WebauthnError::CredentialMismatch);
+            }
+        }
+
+        let client_data_json = decode_base64url(&credential.response.client_data_json)?;
+        let challenge = self.check_client_data(&client_data_json, Ceremony::Authentication)?;
+
+        let raw_auth_data = decode_base64url(&credential.response.authenticator_data)?;
+        let auth_data = AuthenticatorData::parse(&raw_auth_data)?;
+        self.check_authenticator_data(&auth_data)?;
+
+        let signed = [
+            raw_auth_data.as_slice(),
+            Sha256::digest(&client_data_json).as_slice(),
+        ]
+        .concat();
+        let signature = decode_base64url(&credential.response.signature)?;
+        if !verify_signature(&passkey.public_key, passkey.algorithm, &signed, &signature)? {
+            return Err(WebauthnError::Signature);
+        }
+
+        // authenticators that don't keep a counter always report zero
+        let sign_count = auth_data.sign_count;
+        if (sign_count != 0 || passkey.sign_count != 0) && (sign_count as i64) <= passkey.sign_count
+        {
+            return Err(WebauthnError::SignCount);
+        }
+
+        Ok(VerifiedAssertion {
+            challenge,
+            sign_count,
+        })
+    }
+}
+
+/// Generate a new random challenge, base64url encoded the way it comes back in the client data
+pub fn new_challenge() -> String {
+    let mut challenge = vec![0u8; CHALLENGE_LENGTH];
+    rand::thread_rng().fill(&mut challenge[..]);
+    URL_SAFE_NO_PAD.encode(challenge)
+}
+
+fn decode_base64url(value: &str) -> Result<Vec<u8>, WebauthnError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| WebauthnError::Encoding)
+}
+
+struct AttestedCredential {
+    aaguid: Uuid,
+    credential_id: Vec<u8>,
+    public_key: cbor::Value,
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    attested: Option<AttestedCredential>,
+}
+
+impl<'a> AuthenticatorData<'a> {
+    /// rpIdHash (32) | flags (1) | signCount (4) | attested credential data | extensions
+    fn parse(data: &'a [u8]) -> Result<Self, WebauthnError> {
+        if data.len() < 37 {
+            return Err(WebauthnError::Encoding);
+        }
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+        let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            // aaguid (16) | credentialIdLength (2) | credentialId | credentialPublicKey
+            let rest = &data[37..];
+            if rest.len() < 18 {
+                return Err(WebauthnError::Encoding);
+            }
+            let aaguid = Uuid::from_slice(&rest[..16]).map_err(|_| WebauthnError::Encoding)?;
+            let id_length = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+            if id_length > MAX_CREDENTIAL_ID_LENGTH {
+                return Err(WebauthnError::Encoding);
+            }
+            let credential_id = rest
+                .get(18..18 + id_length)
+                .ok_or(WebauthnError::Encoding)?
+                .to_vec();
+            let (public_key, _) =
+                cbor::decode(&rest[18 + id_length..]).ok_or(WebauthnError::Encoding)?;
+            Some(AttestedCredential {
+                aaguid,
+                credential_id,
+                public_key,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rp_id_hash: &data[..32],
+            flags,
+            sign_count,
+            attested,
+        })
+    }
+}
+
+fn cose_public_key(key: &cbor::Value) -> Result<(PKey<Public>, i32), WebauthnError> {
+    let int = |label: i64| key.get_int(label).and_then(|v| v.as_int());
+    let bytes = |label: i64| {
+        key.get_int(label)
+            .and_then(|v| v.as_bytes())
+            .ok_or(WebauthnError::UnsupportedKey)
+    };
+    let unsupported = |_| WebauthnError::UnsupportedKey;
+
+    let algorithm = int(3).ok_or(WebauthnError::UnsupportedKey)? as i32;
+    let public_key = match (int(1), algorithm) {
+        // EC2 on P-256
+        (Some(2), alg) if alg == COSE_ES256 => {
+            if int(-1) != Some(1) {
+                return Err(WebauthnError::UnsupportedKey);
+            }
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).map_err(unsupported)?;
+            let x = BigNum::from_slice(bytes(-2)?).map_err(unsupported)?;
+            let y = BigNum::from_slice(bytes(-3)?).map_err(unsupported)?;
+            let ec_key =
+                EcKey::from_public_key_affine_coordinates(&group, &x, &y).map_err(unsupported)?;
+            PKey::from_ec_key(ec_key).map_err(unsupported)?
+        }
+        // OKP on Ed25519
+        (Some(1), alg) if alg == COSE_EDDSA => {
+            if int(-1) != Some(6) {
+                return Err(WebauthnError::UnsupportedKey);
+            }
+            PKey::public_key_from_raw_bytes(bytes(-2)?, Id::ED25519).map_err(unsupported)?
+        }
+        (Some(3), alg) if alg == COSE_RS256 => {
+            let n = BigNum::from_slice(bytes(-1)?).map_err(unsupported)?;
+            let e = BigNum::from_slice(bytes(-2)?).map_err(unsupported)?;
+            let rsa = Rsa::from_public_components(n, e).map_err(unsupported)?;
+            PKey::from_rsa(rsa).map_err(unsupported)?
+        }
+        _ => return Err(WebauthnError::UnsupportedKey),
+    };
+    Ok((public_key, algorithm))
+}
+
+fn verify_signature(
+    public_key: &[u8],
+    algorithm: i32,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, WebauthnError> {
+    let key = PKey::public_key_from_der(public_key).map_err(|_| WebauthnError::UnsupportedKey)?;
+    let valid = if algorithm == COSE_EDDSA {
+        Verifier::new_without_digest(&key).and_then(|mut v| v.verify_oneshot(signature, data))
+    } else if algorithm == COSE_ES256 || algorithm == COSE_RS256 {
+        Verifier::new(MessageDigest::sha256(), &key).and_then(|mut v| {
+            v.update(data)?;
+            v.verify(signature)
+        })
+    } else {
+        return Err(WebauthnError::UnsupportedKey);
+    };
+    // openssl reports some malformed signatures as errors rather than as a failed check
+    Ok(valid.unwrap_or(false))
+}
+
+/// Just enough CBOR to read attestation objects and COSE keys
+mod cbor {
+    static MAX_DEPTH: usize = 16;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Value {
+        Integer(i64),
+        Bytes(Vec<u8>),
+        Text(String),
+        Array(Vec<Value>),
+        Map(Vec<(Value, Value)>),
+        Bool(bool),
+        Null,
+    }
+
+    impl Value {
+        pub fn get(&self, key: &Value) -> Option<&Value> {
+            match self {
+                Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+                _ => None,
+            }
+        }
+
+        pub fn get_int(&self, key: i64) -> Option<&Value> {
+            self.get(&Value::Integer(key))
+        }
+
+        pub fn get_text(&self, key: &str) -> Option<&Value> {
+            self.get(&Value::Text(key.to_owned()))
+        }
+
+        pub fn as_int(&self) -> Option<i64> {
+            match self {
+                Value::Integer(i) => Some(*i),
+                _ => None,
+            }
+        }
+
+        pub fn as_bytes(&self) -> Option<&[u8]> {
+            match self {
+                Value::Bytes(b) => Some(b),
+                _ => None,
+            }
+        }
+    }
+
+    pub fn decode(data: &[u8]) -> Option<(Value, usize)> {
+        let mut pos = 0;
+        let value = item(data, &mut pos, 0)?;
+        Some((value, pos))
+    }
+
+    fn take<'a>(data: &'a [u8], pos: &mut usize, len: u64) -> Option<&'a [u8]> {
+        let end = pos.checked_add(usize::try_from(len).ok()?)?;
+        let bytes = data.get(*pos..end)?;
+        *pos = end;
+        Some(bytes)
+    }
+
+    fn item(data: &[u8], pos: &mut usize, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let initial = *data.get(*pos)?;
+        *pos += 1;
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let argument = match info {
+            0..=23 => info as u64,
+            24..=27 => {
+                let bytes = take(data, pos, 1 << (info - 24))?;
+                bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64)
+            }
+            _ => return None,
+        };
+
+        match major {
+            0 => Some(Value::Integer(i64::try_from(argument).ok()?)),
+            1 => Some(Value::Integer(-1 - i64::try_from(argument).ok()?)),
+            2 => Some(Value::Bytes(take(data, pos, argument)?.to_vec())),
+            3 => Some(Value::Text(
+                String::from_utf8(take(data, pos, argument)?.to_vec()).ok()?,
+            )),
+            4 => {
+                let mut items = vec![];
+                for _ in 0..argument {
+                    items.push(item(data, pos, depth + 1)?);
+                }
+                Some(Value::Array(items))
+            }
+            5 => {
+                let mut entries = vec![];
+                for _ in 0..argument {
+                    let key = item(data, pos, depth + 1)?;
+                    let value = item(data, pos, depth + 1)?;
+                    entries.push((key, value));
+                }
+                Some(Value::Map(entries))
+            }
+            7 => match info {
+                20 => Some(Value::Bool(false)),
+                21 => Some(Value::Bool(true)),
+                22 => Some(Value::Null),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use openssl::{bn::BigNumContext, pkey::Private, sign::Signer};
+    use sqlx::types::chrono::Utc;
+
+    use crate::app::dto::auth::{AssertionResponse, AttestationResponse};
+
+    use super::{cbor::Value, *};
+
+    static RP_ID: &str = "localhost";
+    static ORIGIN: &str = "http://localhost:8888";
+
+    fn encode_head(major: u8, argument: u64, out: &mut Vec<u8>) {
+        let major = major << 5;
+        match argument {
+            0..=23 => out.push(major | argument as u8),
+            24..=0xff => out.extend([major | 24, argument as u8]),
+            0x100..=0xffff => {
+                out.push(major | 25);
+                out.extend((argument as u16).to_be_bytes());
+            }
+            0x10000..=0xffff_ffff => {
+                out.push(major | 26);
+                out.extend((argument as u32).to_be_bytes());
+            }
+            _ => {
+                out.push(major | 27);
+                out.extend(argument.to_be_bytes());
+            }
+        }
+    }
+
+    pub fn encode(value: &Value) -> Vec<u8> {
+        let mut out = vec![];
+        match value {
+            Value::Integer(i) if *i >= 0 => encode_head(0, *i as u64, &mut out),
+            Value::Integer(i) => encode_head(1, (-1 - *i) as u64, &mut out),
+            Value::Bytes(b) => {
+                encode_head(2, b.len() as u64, &mut out);
+                out.extend(b);
+            }
+            Value::Text(t) => {
+                encode_head(3, t.len() as u64, &mut out);
+                out.extend(t.as_bytes());
+            }
+            Value::Array(items) => {
+                encode_head(4, items.len() as u64, &mut out);
+                items.iter().for_each(|i| out.extend(encode(i)));
+            }
+            Value::Map(entries) => {
+                encode_head(5, entries.len() as u64, &mut out);
+                for (k, v) in entries {
+                    out.extend(encode(k));
+                    out.extend(encode(v));
+                }
+            }
+            Value::Bool(b) => out.push(0xf4 + *b as u8),
+            Value::Null => out.push(0xf6),
+        }
+        out
+    }
+
+    /// An in memory authenticator for driving both ceremonies in tests
+    pub struct SoftAuthenticator {
+        key: PKey<Private>,
+        algorithm: i32,
+        pub credential_id: Vec<u8>,
+        pub sign_count: u32,
+        pub user_handle: Vec<u8>,
+        pub flags: u8,
+    }
+
+    impl SoftAuthenticator {
+        pub fn es256(user_id: &Uuid) -> Self {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+            Self::with_key(key, COSE_ES256, user_id)
+        }
+
+        pub fn ed25519(user_id: &Uuid) -> Self {
+            Self::with_key(PKey::generate_ed25519().unwrap(), COSE_EDDSA, user_id)
+        }
+
+        fn with_key(key: PKey<Private>, algorithm: i32, user_id: &Uuid) -> Self {
+            let mut credential_id = vec![0u8; 16];
+            rand::thread_rng().fill(&mut credential_id[..]);
+            Self {
+                key,
+                algorithm,
+                credential_id,
+                sign_count: 0,
+                user_handle: user_id.as_bytes().to_vec(),
+                flags: FLAG_USER_PRESENT | FLAG_USER_VERIFIED,
+            }
+        }
+
+        fn cose_key(&self) -> Value {
+            let int = Value::Integer;
+            if self.algorithm == COSE_EDDSA {
+                return Value::Map(vec![
+                    (int(1), int(1)),
+                    (int(3), int(COSE_EDDSA as i64)),
+                    (int(-1), int(6)),
+                    (int(-2), Value::Bytes(self.key.raw_public_key().unwrap())),
+                ]);
+            }
+            let ec_key = self.key.ec_key().unwrap();
+            let (mut x, mut y) = (BigNum::new().unwrap(), BigNum::new().unwrap());
+            ec_key
+                .public_key()
+                .affine_coordinates(
+                    ec_key.group(),
+                    &mut x,
+                    &mut y,
+                    &mut BigNumContext::new().unwrap(),
+                )
+                .unwrap();
+            Value::Map(vec![
+                (int(1), int(2)),
+                (int(3), int(COSE_ES256 as i64)),
+                (int(-1), int(1)),
+                (int(-2), Value::Bytes(x.to_vec_padded(32).unwrap())),
+                (int(-3), Value::Bytes(y.to_vec_padded(32).unwrap())),
+            ])
+        }
+
+        fn client_data(ceremony: Ceremony, challenge: &str, origin: &str) -> Vec<u8> {
+            serde_json::to_vec(&serde_json::json!({
+                "type": ceremony.client_data_type(),
+                "challenge": challenge,
+                "origin": origin,
+                "crossOrigin": false
+            }))
+            .unwrap()
+        }
+
+        fn auth_data(&self, rp_id: &str, flags: u8) -> Vec<u8> {
+            [
+                Sha256::digest(rp_id.as_bytes()).as_slice(),
+                &[flags],
+                &self.sign_count.to_be_bytes(),
+            ]
+            .concat()
+        }
+
+        pub fn register(
+            &self,
+            challenge: &str,
+            origin: &str,
+            rp_id: &str,
+        ) -> RegistrationCredential {
+            let mut auth_data = self.auth_data(rp_id, self.flags | FLAG_ATTESTED_CREDENTIAL);
+            auth_data.extend([0u8; 16]);
+            auth_data.extend((self.credential_id.len() as u16).to_be_bytes());
+            auth_data.extend(&self.credential_id);
+            auth_data.extend(encode(&self.cose_key()));
+
+            let attestation = Value::Map(vec![
+                (
+                    Value::Text("fmt".to_owned()),
+                    Value::Text("none".to_owned()),
+                ),
+                (Value::Text("attStmt".to_owned()), Value::Map(vec![])),
+                (Value::Text("authData".to_owned()), Value::Bytes(auth_data)),
+            ]);
+            let id = URL_SAFE_NO_PAD.encode(&self.credential_id);
+            RegistrationCredential {
+                id: id.clone(),
+                raw_id: id,
+                response: AttestationResponse {
+                    client_data_json: URL_SAFE_NO_PAD.encode(Self::client_data(
+                        Ceremony::Registration,
+                        challenge,
+                        origin,
+                    )),
+                    attestation_object: URL_SAFE_NO_PAD.encode(encode(&attestation)),
+                },
+                credential_type: "public-key".to_owned(),
+            }
+        }
+
+        pub fn authenticate(
+            &mut self,
+            challenge: &str,
+            origin: &str,
+            rp_id: &str,
+        ) -> AuthenticationCredential {
+            self.sign_count += 1;
+            let auth_data = self.auth_data(rp_id, self.flags);
+            let client_data = Self::client_data(Ceremony::Authentication, challenge, origin);
+            let signed = [
+                auth_data.as_slice(),
+                Sha256::digest(&client_data).as_slice(),
+            ]
+            .concat();
+            let signature = if self.algorithm == COSE_EDDSA {
+                Signer::new_without_digest(&self.key)
+                    .unwrap()
+                    .sign_oneshot_to_vec(&signed)
+                    .unwrap()
+            } else {
+                let mut signer = Signer::new(MessageDigest::sha256(), &self.key).unwrap();
+                signer.update(&signed).unwrap();
+                signer.sign_to_vec().unwrap()
+            };
+
+            let id = URL_SAFE_NO_PAD.encode(&self.credential_id);
+            AuthenticationCredential {
+                id: id.clone(),
+                raw_id: id,
+                response: AssertionResponse {
+                    client_data_json: URL_SAFE_NO_PAD.encode(client_data),
+                    authenticator_data: URL_SAFE_NO_PAD.encode(auth_data),
+                    signature: URL_SAFE_NO_PAD.encode(signature),
+                    user_handle: Some(URL_SAFE_NO_PAD.encode(&self.user_handle)),
+                },
+                credential_type: "public-key".to_owned(),
+            }
+        }
+    }
+
+    fn stored(user_id: Uuid, registration: &VerifiedRegistration) -> Passkey {
+        Passkey {
+            id: Uuid::new_v4(),
+            user_id,
+            credential_id: registration.credential_id.clone(),
+            public_key: registration.public_key.clone(),
+            algorithm: registration.algorithm,
+            sign_count: registration.sign_count as i64,
+            aaguid: registration.aaguid,
+            name: "Passkey".to_owned(),
+            last_used_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_cbor() {
+        // examples from RFC 8949 appendix A
+        let decode = |hex: &str| {
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect::<Vec<_>>();
+            cbor::decode(&bytes)
+        };
+        assert_eq!(decode("1903e8"), Some((Value::Integer(1000), 3)));
+        assert_eq!(decode("3863"), Some((Value::Integer(-100), 2)));
+        assert_eq!(
+            decode("6449455446"),
+            Some((Value::Text("IETF".to_owned()), 5))
+        );
+        assert_eq!(
+            decode("a201020304"),
+            Some((
+                Value::Map(vec![
+                    (Value::Integer(1), Value::Integer(2)),
+                    (Value::Integer(3), Value::Integer(4))
+                ]),
+                5
+            ))
+        );
+        // only the first item is read
+        assert_eq!(decode("0102"), Some((Value::Integer(1), 1)));
+        // truncated, indefinite length and tagged items are rejected
+        assert_eq!(decode("1903"), None);
+        assert_eq!(decode("5f42010243030405ff"), None);
+        assert_eq!(decode("c11a514b67b0"), None);
+
+        let value = Value::Map(vec![
+            (Value::Text("a".to_owned()), Value::Bytes(vec![1; 300])),
+            (
+                Value::Integer(-300),
+                Value::Array(vec![Value::Bool(true), Value::Null]),
+            ),
+        ]);
+        let encoded = encode(&value);
+        assert_eq!(cbor::decode(&encoded), Some((value, encoded.len())));
+    }
+
+    #[test]
+    fn test_ceremonies() {
+        let manager = PasskeyManager::new(RP_ID, ORIGIN);
+        let user_id = Uuid::new_v4();
+
+        for mut authenticator in [
+            SoftAuthenticator::es256(&user_id),
+            SoftAuthenticator::ed25519(&user_id),
+        ] {
+            let challenge = new_challenge();
+            let registration = manager
+                .verify_registration(&authenticator.register(&challenge, ORIGIN, RP_ID))
+                .expect("error verifying registration");
+            assert_eq!(registration.challenge, challenge);
+            assert_eq!(
+                registration.credential_id,
+                URL_SAFE_NO_PAD.encode(&authenticator.credential_id)
+            );
+            let passkey = stored(user_id, &registration);
+
+            let challenge = new_challenge();
+            let assertion = manager
+                .verify_authentication(
+                    &authenticator.authenticate(&challenge, ORIGIN, RP_ID),
+                    &passkey,
+                )
+                .expect("error verifying assertion");
+            assert_eq!(assertion.challenge, challenge);
+            assert_eq!(assertion.sign_count, 1);
+        }
+    }
+
+    #[test]
+    fn test_rejected_ceremonies() {
+        let manager = PasskeyManager::new(RP_ID, ORIGIN);
+        let user_id = Uuid::new_v4();
+        let mut authenticator = SoftAuthenticator::es256(&user_id);
+        let challenge = new_challenge();
+
+        let phished = authenticator.register(&challenge, "https://mocha.example.com", RP_ID);
+        assert_eq!(
+            manager.verify_registration(&phished).unwrap_err(),
+            WebauthnError::Origin
+        );
+        let other_rp = authenticator.register(&challenge, ORIGIN, "example.com");
+        assert_eq!(
+            manager.verify_registration(&other_rp).unwrap_err(),
+            WebauthnError::RelyingParty
+        );
+        let mut swapped = authenticator.register(&challenge, ORIGIN, RP_ID);
+        swapped.raw_id = URL_SAFE_NO_PAD.encode(b"someone else");
+        assert_eq!(
+            manager.verify_registration(&swapped).unwrap_err(),
+            WebauthnError::CredentialMismatch
+        );
+
+        authenticator.flags = FLAG_USER_PRESENT;
+        let unverified = authenticator.register(&challenge, ORIGIN, RP_ID);
+        assert_eq!(
+            manager.verify_registration(&unverified).unwrap_err(),
+            WebauthnError::UserVerification
+        );
+        authenticator.flags = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+
+        let registration = manager
+            .verify_registration(&authenticator.register(&challenge, ORIGIN, RP_ID))
+            .unwrap();
+        let mut passkey = stored(user_id, &registration);
+
+        // a registration response can't be used to sign in
+        let mut wrong_ceremony = authenticator.authenticate(&challenge, ORIGIN, RP_ID);
+        wrong_ceremony.response.client_data_json = URL_SAFE_NO_PAD.encode(
+            SoftAuthenticator::client_data(Ceremony::Registration, &challenge, ORIGIN),
+        );
+        assert_eq!(
+            manager
+                .verify_authentication(&wrong_ceremony, &passkey)
+                .unwrap_err(),
+            WebauthnError::CeremonyType
+        );
+
+        let mut forged = authenticator.authenticate(&challenge, ORIGIN, RP_ID);
+        forged.response.client_data_json = URL_SAFE_NO_PAD.encode(SoftAuthenticator::client_data(
+            Ceremony::Authentication,
+            &new_challenge(),
+            ORIGIN,
+        ));
+        assert_eq!(
+            manager
+                .verify_authentication(&forged, &passkey)
+                .unwrap_err(),
+            WebauthnError::Signature
+        );
+
+        let mut impostor = authenticator.authenticate(&challenge, ORIGIN, RP_ID);
+        impostor.response.user_handle = Some(URL_SAFE_NO_PAD.encode(Uuid::new_v4().as_bytes()));
+        assert_eq!(
+            manager
+                .verify_authentication(&impostor, &passkey)
+                .unwrap_err(),
+            WebauthnError::CredentialMismatch
+        );
+
+        // the stored counter is ahead of the authenticator's, so this one is a copy
+        passkey.sign_count = 10;
+        let cloned = authenticator.authenticate(&challenge, ORIGIN, RP_ID);
+        assert_eq!(
+            manager
+                .verify_authentication(&cloned, &passkey)
+                .unwrap_err(),
+            WebauthnError::SignCount
+        );
+    }
+}
@@ -1,4 +1,8 @@
 use super::{
+    auth::{
+        credentials::{HashCosts, Peppers},
+        oidc::{OidcProvider, DEFAULT_OIDC_SCOPES},
+    },
     launch::LaunchMode,
     storage::{
         postgres,
@@ -6,9 +10,11 @@ use super::{
     },
     types::AssetBackend,
 };
-use std::env;
+use std::{collections::HashMap, env};
 
 use derive_more::{Display, Error};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use sqlx::{Pool, Postgres};
 
 pub struct Config {
@@ -16,6 +22,22 @@ pub struct Config {
     pub symmetric_secret: Vec<u8>,
     pub launch_mode: LaunchMode,
     pub asset_backend: AssetBackend,
+    pub site_url: String,
+    pub scheduler_interval: u64,
+    pub words_per_minute: u32,
+    pub image_read_seconds: u32,
+    pub sticker_read_seconds: u32,
+    pub comment_edit_window: i64,
+    pub spam_threshold: f64,
+    pub session_absolute_timeout: i64,
+    pub session_idle_timeout: i64,
+    pub hash_costs: HashCosts,
+    pub password_peppers: Peppers,
+    pub totp_key: [u8; 32],
+    pub webauthn_rp_id: String,
+    pub webauthn_origin: String,
+    pub oidc_providers: HashMap<String, OidcProvider>,
+    pub oidc_redirect_uri: String,
 }
 
 pub struct StorageLayer {
@@ -31,6 +53,10 @@ pub enum InitError {
     InitRedis,
     #[display(fmt = "error initializing sql connection pool")]
     InitPostgres,
+    #[display(fmt = "the current password pepper is not configured")]
+    Pepper,
+    #[display(fmt = "an identity provider is missing its issuer or client id")]
+    OidcProvider,
 }
 
 impl StorageLayer {
@@ -78,11 +104,215 @@ impl Config {
             _ => AssetBackend::Fs,
         };
 
+        // public address of the site, used for absolute links in feeds and the sitemap
+        let site_url = env::var("SITE_URL")
+            .unwrap_or("http://localhost:8888".to_owned())
+            .trim_end_matches('/')
+            .to_owned();
+
+        let scheduler_interval = env::var("SCHEDULER_INTERVAL")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .filter(|s| *s > 0)
+            .unwrap_or(30);
+
+        let words_per_minute = env::var("READING_WPM")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .filter(|wpm| *wpm > 0)
+            .unwrap_or(230);
+
+        let image_read_seconds = env::var("READING_IMAGE_SECONDS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(12);
+
+        let sticker_read_seconds = env::var("READING_STICKER_SECONDS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(3);
+
+        // how long after posting a comment can still be edited, in seconds
+        let comment_edit_window = env::var("COMMENT_EDIT_WINDOW")
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .unwrap_or(900);
+
+        // comments the spam filter scores at or above this probability are set aside as spam
+        let spam_threshold = env::var("SPAM_THRESHOLD")
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|p| (0.0..=1.0).contains(p))
+            .unwrap_or(0.9);
+
+        // sessions end this many seconds after sign in no matter how active they are
+        let session_absolute_timeout = env::var("SESSION_ABSOLUTE_TIMEOUT")
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|t| *t > 0)
+            .unwrap_or(60 * 60 * 24 * 30);
+
+        // sessions that go this many seconds without a request end early
+        let session_idle_timeout = env::var("SESSION_IDLE_TIMEOUT")
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|t| *t > 0)
+            .unwrap_or(60 * 60 * 24 * 7);
+
+        // work factors for new password hashes. raising them upgrades existing hashes as their
+        // owners sign in
+        let default_costs = HashCosts::default();
+        let hash_costs = HashCosts {
+            argon2_memory_cost: env::var("ARGON2_MEMORY_COST")
+                .ok()
+                .and_then(|s| s.parse::<u32>().ok())
+                .filter(|m| *m >= 8)
+                .unwrap_or(default_costs.argon2_memory_cost),
+            argon2_time_cost: env::var("ARGON2_TIME_COST")
+                .ok()
+                .and_then(|s| s.parse::<u32>().ok())
+                .filter(|t| *t > 0)
+                .unwrap_or(default_costs.argon2_time_cost),
+            bcrypt_cost: env::var("BCRYPT_COST")
+                .ok()
+                .and_then(|s| s.parse::<u32>().ok())
+                .filter(|c| (4..=31).contains(c))
+                .unwrap_or(default_costs.bcrypt_cost),
+            scrypt_log_n: env::var("SCRYPT_LOG_N")
+                .ok()
+                .and_then(|s| s.parse::<u8>().ok())
+                .filter(|n| (1..64).contains(n))
+                .unwrap_or(default_costs.scrypt_log_n),
+            scrypt_r: env::var("SCRYPT_R")
+                .ok()
+                .and_then(|s| s.parse::<u32>().ok())
+                .filter(|r| *r > 0)
+                .unwrap_or(default_costs.scrypt_r),
+            scrypt_p: env::var("SCRYPT_P")
+                .ok()
+                .and_then(|s| s.parse::<u32>().ok())
+                .filter(|p| *p > 0)
+                .unwrap_or(default_costs.scrypt_p),
+            pbkdf2_rounds: env::var("PBKDF2_ROUNDS")
+                .ok()
+                .and_then(|s| s.parse::<u32>().ok())
+                .filter(|i| *i > 0)
+                .unwrap_or(default_costs.pbkdf2_rounds),
+        };
+
+        // password peppers as a comma separated list of <key id>=<secret>. new hashes use the one
+        // named by PASSWORD_PEPPER_ID; the rest are kept so older hashes still verify until their
+        // owners sign in and get rehashed
+        let pepper_keys = env::var("PASSWORD_PEPPERS")
+            .unwrap_or("".to_owned())
+            .split(',')
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(id, secret)| (id.trim().to_owned(), secret.trim().as_bytes().to_vec()))
+            .filter(|(id, secret)| !id.is_empty() && !secret.is_empty())
+            .collect::<HashMap<_, _>>();
+        let current_pepper = env::var("PASSWORD_PEPPER_ID")
+            .ok()
+            .filter(|id| !id.is_empty());
+        if let Some(id) = &current_pepper {
+            if !pepper_keys.contains_key(id) {
+                return Err(InitError::Pepper);
+            }
+        }
+        let password_peppers = Peppers {
+            current: current_pepper,
+            keys: pepper_keys,
+        };
+
+        // TOTP secrets are encrypted with a key derived from TOTP_ENCRYPTION_KEY, or from the app
+        // secret when that isn't set. changing it makes every enrolled authenticator unreadable
+        let totp_secret = env::var("TOTP_ENCRYPTION_KEY")
+            .map(|s| s.into_bytes())
+            .unwrap_or(symmetric_secret.clone());
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&totp_secret).map_err(|_| InitError::Secret)?;
+        mac.update(b"mocha totp secrets");
+        let totp_key: [u8; 32] = mac.finalize().into_bytes().into();
+
+        // passkeys are bound to the origin the site is served from and to a domain it owns. both
+        // default to the site url; changing the domain orphans every registered passkey
+        let site_origin = match site_url.split_once("://") {
+            Some((scheme, rest)) => format!("{scheme}://{}", rest.split('/').next().unwrap_or("")),
+            None => site_url.clone(),
+        };
+        let webauthn_origin = env::var("WEBAUTHN_ORIGIN")
+            .map(|s| s.trim_end_matches('/').to_owned())
+            .unwrap_or(site_origin);
+        let webauthn_rp_id = env::var("WEBAUTHN_RP_ID").unwrap_or(
+            webauthn_origin
+                .split_once("://")
+                .map_or(webauthn_origin.as_str(), |(_, host)| host)
+                .split(':')
+                .next()
+                .unwrap_or("")
+                .to_owned(),
+        );
+
+        // social sign in providers as a comma separated list of names. each is configured with
+        // OIDC_<NAME>_ISSUER and OIDC_<NAME>_CLIENT_ID, and optionally OIDC_<NAME>_CLIENT_SECRET
+        // and OIDC_<NAME>_SCOPES. names show up in urls, so they're kept to letters, digits and -
+        let mut oidc_providers = HashMap::new();
+        for name in env::var("OIDC_PROVIDERS")
+            .unwrap_or("".to_owned())
+            .split(',')
+        {
+            let name = name.trim().to_lowercase();
+            if name.is_empty() {
+                continue;
+            }
+            if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(InitError::OidcProvider);
+            }
+            let var = |key: &str| {
+                env::var(format!(
+                    "OIDC_{}_{key}",
+                    name.to_uppercase().replace('-', "_")
+                ))
+                .ok()
+                .map(|s| s.trim().to_owned())
+                .filter(|s| !s.is_empty())
+            };
+            let provider = OidcProvider {
+                issuer: var("ISSUER")
+                    .ok_or(InitError::OidcProvider)?
+                    .trim_end_matches('/')
+                    .to_owned(),
+                client_id: var("CLIENT_ID").ok_or(InitError::OidcProvider)?,
+                client_secret: var("CLIENT_SECRET"),
+                scopes: var("SCOPES").unwrap_or(DEFAULT_OIDC_SCOPES.to_owned()),
+            };
+            oidc_providers.insert(name, provider);
+        }
+        // where providers send the browser back to. the page there posts the code and state on to
+        // the api
+        let oidc_redirect_uri =
+            env::var("OIDC_REDIRECT_URI").unwrap_or(format!("{site_url}/auth/oidc/callback"));
+
         Ok(Config {
             name: name.to_owned(),
             symmetric_secret,
             launch_mode,
             asset_backend,
+            site_url,
+            scheduler_interval,
+            words_per_minute,
+            image_read_seconds,
+            sticker_read_seconds,
+            comment_edit_window,
+            spam_threshold,
+            session_absolute_timeout,
+            session_idle_timeout,
+            hash_costs,
+            password_peppers,
+            totp_key,
+            webauthn_rp_id,
+            webauthn_origin,
+            oidc_providers,
+            oidc_redirect_uri,
         })
     }
 }
@@ -1,6 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use uuid::Uuid;
+
+use crate::app::{auth::webauthn::Ceremony, dto::users::CreateUser, types::HashAlgorithm};
 
 #[serde_with::skip_serializing_none]
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,6 +55,22 @@ pub struct DeleteSession {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeleteUserSessions {
     pub user_id: String,
+    /// A session to leave alone, usually the one making the request
+    pub except: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TouchSession {
+    pub id: String,
+    /// Seconds the session has left before it times out. Only used by stores that expire keys
+    /// on their own.
+    pub expires_in: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteExpiredSessions {
+    pub absolute_timeout: i64,
+    pub idle_timeout: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -128,3 +147,366 @@ pub struct AddRoleToUser {
 pub struct GetUserRbac {
     pub user_id: String,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateRefreshToken {
+    pub user_id: String,
+    /// The session started alongside the token
+    pub session_id: String,
+    pub token_hash: String,
+    /// Lifetime of the token, in seconds
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateRefreshToken {
+    pub token_hash: String,
+    pub new_token_hash: String,
+    /// Lifetime of the replacement token, in seconds
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeRefreshTokenFamily {
+    pub token_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeAccessToken {
+    pub jti: String,
+    /// How long the token would have stayed valid, in seconds. The revocation is forgotten after
+    /// that since the token has expired by then anyway.
+    pub expires_in: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvalidateUserTokens {
+    pub user_id: String,
+    /// Access tokens issued before this unix timestamp are rejected
+    pub issued_before: usize,
+    pub expires_in: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckAccessToken {
+    pub jti: String,
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeUserRefreshTokens {
+    pub user_id: String,
+    /// Leave the tokens issued with this session alone
+    pub except_session: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeSessionRefreshTokens {
+    pub session_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForgotPassword {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePasswordResetToken {
+    pub user_id: String,
+    pub token_hash: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResetPassword {
+    pub token_hash: String,
+    pub hashed_password: String,
+    pub algorithm: HashAlgorithm,
+    pub pepper_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailTokenRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateEmailVerificationToken {
+    pub user_id: String,
+    pub email: String,
+    pub token_hash: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyEmail {
+    pub token_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeEmailRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateEmailChange {
+    pub user_id: String,
+    pub new_email: String,
+    pub old_token_hash: String,
+    pub new_token_hash: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfirmEmailChange {
+    pub token_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+/// Either a code from the user's authenticator app or one of their recovery codes
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecondFactor {
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpLogin {
+    pub challenge: String,
+    #[serde(flatten)]
+    pub factor: SecondFactor,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTotp {
+    pub user_id: String,
+    pub secret: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetTotp {
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfirmTotp {
+    pub user_id: String,
+    pub step: i64,
+    pub recovery_code_hashes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UseTotpStep {
+    pub user_id: String,
+    pub step: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UseRecoveryCode {
+    pub user_id: String,
+    pub code_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteTotp {
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateLoginChallenge {
+    pub user_id: String,
+    pub token_hash: String,
+    pub data: Value,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttemptLoginChallenge {
+    pub token_hash: String,
+    pub max_attempts: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReserveTotpAttempt {
+    pub user_id: String,
+    pub max_failures: i32,
+    /// How long the authenticator stays locked, in seconds
+    pub lockout: i64,
+}
+
+/// `AuthenticatorAttestationResponse` as browsers serialize it with `toJSON()`. Binary fields are
+/// base64url.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+/// The `PublicKeyCredential` returned by `navigator.credentials.create()`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationCredential {
+    pub id: String,
+    #[serde(rename = "rawId")]
+    pub raw_id: String,
+    pub response: AttestationResponse,
+    #[serde(rename = "type")]
+    pub credential_type: String,
+}
+
+/// `AuthenticatorAssertionResponse` as browsers serialize it with `toJSON()`. Binary fields are
+/// base64url.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+/// The `PublicKeyCredential` returned by `navigator.credentials.get()`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthenticationCredential {
+    pub id: String,
+    #[serde(rename = "rawId")]
+    pub raw_id: String,
+    pub response: AssertionResponse,
+    #[serde(rename = "type")]
+    pub credential_type: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FinishPasskeyRegistration {
+    pub name: Option<String>,
+    pub credential: RegistrationCredential,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePasskeyChallenge {
+    pub user_id: Option<String>,
+    pub ceremony: Ceremony,
+    pub challenge: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsePasskeyChallenge {
+    pub challenge: String,
+    pub ceremony: Ceremony,
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePasskey {
+    pub user_id: String,
+    pub credential_id: String,
+    pub public_key: Vec<u8>,
+    pub algorithm: i32,
+    pub sign_count: i64,
+    pub aaguid: Uuid,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPasskeyByCredentialId {
+    pub credential_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserPasskeys {
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsePasskey {
+    pub id: String,
+    pub sign_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletePasskey {
+    pub id: String,
+    pub user_id: String,
+}
+
+/// What the browser brings back from the provider's redirect
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcCallback {
+    pub state: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateOidcState {
+    pub provider: String,
+    pub state_hash: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub user_id: Option<String>,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UseOidcState {
+    pub state_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateUserIdentity {
+    pub user_id: String,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserIdentity {
+    pub provider: String,
+    pub subject: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetUserIdentities {
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UseUserIdentity {
+    pub id: String,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteUserIdentity {
+    pub id: String,
+    pub user_id: String,
+}
+
+/// A new account for someone signing in with a provider for the first time
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateUserWithIdentity {
+    pub user: CreateUser,
+    pub provider: String,
+    pub subject: String,
+    /// Whether the provider vouches for the email, in which case it doesn't need verifying again
+    pub email_verified: bool,
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::types::CommentStatus;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateComment {
+    pub user_id: String,
+    pub post_id: String,
+    pub parent_id: Option<String>,
+    pub content: String,
+    /// Set when the spam filter is confident the comment is spam
+    pub spam: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetCommentById {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetThreads {
+    pub user_id: String,
+    pub post_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetThread {
+    pub user_id: String,
+    pub post_id: String,
+    pub thread_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditComment {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+    pub content: String,
+    /// How long after creation a comment may still be edited, in seconds
+    pub edit_window: i64,
+    /// Set when the spam filter is confident the new content is spam
+    pub spam: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteComment {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModerateComment {
+    pub moderator_id: String,
+    pub comment_id: String,
+    pub status: CommentStatus,
+}
@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetFeedItems {
+    pub space_id: Option<String>,
+    pub tag_id: Option<String>,
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LikePost {
+    pub user_id: String,
+    pub post_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnlikePost {
+    pub user_id: String,
+    pub post_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPostLikes {
+    pub user_id: String,
+    pub post_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LikeComment {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnlikeComment {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetCommentLikes {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+}
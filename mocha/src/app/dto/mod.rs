@@ -1,6 +1,12 @@
 pub mod auth;
+pub mod comments;
+pub mod feeds;
+pub mod likes;
 pub mod pagination;
 pub mod posts;
+pub mod reactions;
+pub mod search;
+pub mod sitemap;
 pub mod spaces;
 pub mod stickers;
 pub mod tags;
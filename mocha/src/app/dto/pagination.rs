@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::app::types::CommentStatus;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PaginationLimits<T> {
     pub offset: i64,
@@ -16,3 +18,30 @@ pub struct SpacePaginationOptions {
 pub struct TagPaginationOptions {
     pub asc: bool,
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PostPaginationOptions {
+    pub asc: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SearchPaginationOptions {
+    pub space_id: Option<String>,
+    pub tag_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommentPaginationOptions {
+    pub asc: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReactionPaginationOptions {
+    pub asc: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModerationPaginationOptions {
+    pub status: CommentStatus,
+    pub asc: bool,
+}
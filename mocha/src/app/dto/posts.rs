@@ -1,8 +1,16 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct GetPostById {
     pub post_id: String,
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPublishedPostById {
+    pub post_id: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -10,15 +18,25 @@ pub struct GetPostsByUser {
     pub user_id: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetAvailablePosts {
+    pub user_id: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreatePost {
     pub user_id: String,
+    pub space_id: String,
     pub title: String,
     pub content: String,
+    pub content_html: String,
+    pub toc: Value,
     pub image_uri: String,
     pub private: bool,
     pub tags: Vec<String>,
-    pub read_time: u32,
+    pub stickers: Vec<String>,
+    pub read_time: i32,
+    pub word_count: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,9 +46,13 @@ pub struct EditPost {
     pub title: String,
     pub private: bool,
     pub tags: Vec<String>,
+    pub stickers: Vec<String>,
     pub content: String,
+    pub content_html: String,
+    pub toc: Value,
     pub image_uri: String,
-    pub read_time: String,
+    pub read_time: i32,
+    pub word_count: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,3 +60,46 @@ pub struct DeletePost {
     pub user_id: String,
     pub post_id: String,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublishPost {
+    pub user_id: String,
+    pub post_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnpublishPost {
+    pub user_id: String,
+    pub post_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchedulePost {
+    pub user_id: String,
+    pub post_id: String,
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPostRevisions {
+    pub user_id: String,
+    pub post_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPostRevisionById {
+    pub user_id: String,
+    pub post_id: String,
+    pub revision_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestorePostRevision {
+    pub user_id: String,
+    pub post_id: String,
+    pub revision_id: String,
+    pub content_html: String,
+    pub toc: Value,
+    pub read_time: i32,
+    pub word_count: i32,
+}
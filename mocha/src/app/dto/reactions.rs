@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReactToPost {
+    pub user_id: String,
+    pub post_id: String,
+    pub sticker_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletePostReaction {
+    pub user_id: String,
+    pub post_id: String,
+    pub sticker_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPostReactions {
+    pub user_id: String,
+    pub post_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPostReactors {
+    pub user_id: String,
+    pub post_id: String,
+    pub sticker_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReactToComment {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+    pub sticker_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteCommentReaction {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+    pub sticker_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetCommentReactions {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetCommentReactors {
+    pub user_id: String,
+    pub post_id: String,
+    pub comment_id: String,
+    pub sticker_id: String,
+}
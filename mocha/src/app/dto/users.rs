@@ -22,6 +22,15 @@ pub struct CreateUser {
     pub image_uri: String,
     pub hashed_password: Option<String>,
     pub algorithm: Option<HashAlgorithm>,
+    pub pepper_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateUserCredentials {
+    pub user_id: String,
+    pub hashed_password: String,
+    pub algorithm: HashAlgorithm,
+    pub pepper_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,6 +12,32 @@ pub struct Session {
     pub updated_at: DateTime<Utc>,
 }
 
+/// A session as it's shown to its owner when they review their signed in devices
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ActiveSession {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// Whether this is the session the listing was requested from
+    pub current: bool,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+impl ActiveSession {
+    pub fn new(session: Session, current: bool) -> Self {
+        let field = |name: &str| session.data[name].as_str().map(|v| v.to_owned());
+        Self {
+            id: session.id,
+            user_agent: field("user_agent"),
+            ip: field("ip"),
+            current,
+            created_at: session.created_at,
+            last_seen_at: session.updated_at,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Permission {
     pub id: Uuid,
@@ -78,3 +104,96 @@ pub struct UserAccess {
     pub roles: Vec<Role>,
     pub permissions: Vec<Permission>,
 }
+
+/// What happened when a refresh token was presented
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RefreshOutcome {
+    /// The token was good and has been replaced by a new one in the same family
+    Rotated { user_id: Uuid },
+    /// The token had already been used. Its whole family has been revoked.
+    Reused,
+    /// The token doesn't exist, has expired or belongs to a revoked family
+    Invalid,
+}
+
+/// What happened when a token from an email change was presented
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmailChangeOutcome {
+    /// The token was good but the other address hasn't confirmed yet
+    Pending,
+    /// Both addresses have confirmed and the user's email has been changed
+    Completed { user_id: Uuid, email: String },
+    /// Both addresses confirmed, but someone else registered the new address in the meantime
+    Taken,
+    /// The token doesn't exist, has expired or belongs to a change that already went through
+    Invalid,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UserTotp {
+    pub user_id: Uuid,
+    /// Encrypted by the app. See `TotpManager`.
+    pub secret: Vec<u8>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoginChallenge {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub data: Value,
+    pub attempts: i32,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Passkey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// base64url, the way the browser reports it
+    pub credential_id: String,
+    /// DER encoded SubjectPublicKeyInfo
+    #[serde(skip_serializing)]
+    pub public_key: Vec<u8>,
+    /// COSE algorithm identifier
+    pub algorithm: i32,
+    pub sign_count: i64,
+    /// Identifies the make of authenticator, or all zeroes when it doesn't say
+    pub aaguid: Uuid,
+    pub name: String,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UserIdentity {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub provider: String,
+    /// The provider's id for the account, which never changes even if the email does
+    pub subject: String,
+    pub email: Option<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OidcState {
+    pub id: Uuid,
+    pub provider: String,
+    pub nonce: String,
+    #[serde(skip_serializing)]
+    pub code_verifier: String,
+    /// Set when a signed in user is linking an account rather than signing in with one
+    pub user_id: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
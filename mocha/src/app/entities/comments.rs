@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+use crate::app::types::CommentStatus;
+
+/// A comment or reply on a post. Deleted comments come back as tombstones with no author and no
+/// content so that the replies underneath them keep their place in the thread. Comments that
+/// haven't been approved are only ever shown to their author and to moderators.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: Uuid,
+    pub post_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub parent_id: Option<Uuid>,
+    pub thread_id: Option<Uuid>,
+    pub depth: i32,
+    pub content: String,
+    pub status: CommentStatus,
+    pub reply_count: i32,
+    pub like_count: i32,
+    pub liked: bool,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
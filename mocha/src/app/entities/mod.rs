@@ -1,5 +1,13 @@
 pub mod auth;
+pub mod comments;
+pub mod feeds;
+pub mod likes;
+pub mod posts;
+pub mod reactions;
+pub mod search;
+pub mod sitemap;
 pub mod spaces;
+pub mod spam;
 pub mod stickers;
 pub mod tags;
 pub mod users;
@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Uuid;
+
+use crate::app::types::AssetVisibility;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Post {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub space_id: Uuid,
+    pub image_uri: String,
+    pub title: String,
+    pub content: String,
+    pub content_html: String,
+    pub toc: Value,
+    pub read_time: i32,
+    pub word_count: i32,
+    pub visibility: AssetVisibility,
+    pub published: bool,
+    pub published_at: Option<DateTime<Utc>>,
+    pub publish_at: Option<DateTime<Utc>>,
+    pub tags: Vec<Uuid>,
+    pub stickers: Vec<Uuid>,
+    pub like_count: i32,
+    /// Whether the user asking for the post has liked it. Always false for anonymous readers.
+    pub liked: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostRevision {
+    pub id: Uuid,
+    pub post_id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub image_uri: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
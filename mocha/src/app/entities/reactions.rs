@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+use crate::app::types::AssetBackend;
+
+/// All the reactions made with one sticker, along with enough of the sticker to render it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reaction {
+    pub sticker_id: Uuid,
+    pub friendly_name: String,
+    pub file_path: String,
+    pub backend: AssetBackend,
+    pub count: i64,
+    /// Whether the user asking has reacted with this sticker
+    pub reacted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Reactor {
+    pub user_id: Uuid,
+    pub username: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub image_uri: String,
+    pub reacted_at: DateTime<Utc>,
+}
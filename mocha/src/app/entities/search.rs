@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum SearchResultKind {
+    Post,
+    Space,
+    Tag,
+}
+
+/// A single search hit. `snippet` is html-escaped text in which the matched terms are wrapped in
+/// `<mark>` tags, so it is safe to render as-is.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SearchResult {
+    pub kind: SearchResultKind,
+    pub id: Uuid,
+    pub space_id: Uuid,
+    pub title: String,
+    pub snippet: String,
+    pub rank: f32,
+}
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum SitemapEntryKind {
+    Post,
+    Space,
+    Tag,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SitemapEntry {
+    pub kind: SitemapEntryKind,
+    pub id: Uuid,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One file of a sitemap index along with the newest `lastmod` of the urls it contains
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SitemapPage {
+    pub page: i64,
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+/// What the spam filter learned from a comment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "jen.spam_label")]
+#[sqlx(rename_all = "lowercase")]
+pub enum SpamLabel {
+    Spam,
+    Ham,
+}
+
+/// How often a token has shown up in comments the spam filter was trained on
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TokenCounts {
+    pub token: String,
+    pub spam_count: i32,
+    pub ham_count: i32,
+}
+
+/// Everything the classifier needs to score one comment: the size of the training corpus and the
+/// counts for the comment's tokens that have been seen before
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpamStats {
+    pub spam_documents: i32,
+    pub ham_documents: i32,
+    pub tokens: Vec<TokenCounts>,
+}
@@ -14,6 +14,7 @@ pub struct User {
     pub email: String,
     pub username: String,
     pub image_uri: String,
+    pub email_verified_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -30,4 +31,5 @@ pub struct UserWithCredentials {
     pub updated_at: DateTime<Utc>,
     pub credential_hash: String,
     pub alg: HashAlgorithm,
+    pub pepper_id: Option<String>,
 }
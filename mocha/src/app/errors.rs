@@ -15,6 +15,8 @@ pub enum AppError {
     Forbidden,
     #[display(fmt = "not found")]
     NotFound,
+    #[display(fmt = "conflict")]
+    Conflict,
     #[display(fmt = "internal server error")]
     InternalServerError,
 }
@@ -26,6 +28,7 @@ impl actix_web::error::ResponseError for AppError {
             AppError::Unauthorized => StatusCode::UNAUTHORIZED,
             AppError::Forbidden => StatusCode::FORBIDDEN,
             AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Conflict => StatusCode::CONFLICT,
             AppError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -0,0 +1,214 @@
+use atom_syndication::{
+    CategoryBuilder as AtomCategoryBuilder, ContentBuilder, EntryBuilder, FeedBuilder, LinkBuilder,
+    PersonBuilder, Text,
+};
+use chrono::{DateTime, Utc};
+use rss::{CategoryBuilder as RssCategoryBuilder, ChannelBuilder, GuidBuilder, ItemBuilder};
+use uuid::Uuid;
+
+use super::entities::feeds::FeedItem;
+
+/// Number of posts included in every feed
+pub const FEED_LENGTH: i64 = 50;
+
+/// A post's space and tags both become categories, space first
+fn categories(item: &FeedItem) -> impl Iterator<Item = &String> {
+    std::iter::once(&item.space_name).chain(item.tags.iter())
+}
+
+pub fn post_url(site_url: &str, post_id: &Uuid) -> String {
+    format!("{site_url}/posts/{post_id}")
+}
+
+pub fn space_url(site_url: &str, space_id: &Uuid) -> String {
+    format!("{site_url}/spaces/{space_id}")
+}
+
+pub fn tag_url(site_url: &str, tag_id: &Uuid) -> String {
+    format!("{site_url}/tags/{tag_id}")
+}
+
+/// Everything needed to render a feed in either format. `link` points at the html page the feed
+/// mirrors and `self_url` at the feed itself, minus the `rss.xml`/`atom.xml` file name.
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub title: String,
+    pub description: String,
+    pub link: String,
+    pub self_url: String,
+    pub items: Vec<FeedItem>,
+}
+
+impl Feed {
+    /// The feed changes whenever one of its posts does. An empty feed reports the unix epoch so
+    /// that it still has a stable value for conditional requests.
+    pub fn updated(&self) -> DateTime<Utc> {
+        self.items
+            .iter()
+            .map(|item| item.updated_at)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+pub fn rss(feed: &Feed, site_url: &str) -> String {
+    let items: Vec<rss::Item> = feed
+        .items
+        .iter()
+        .map(|item| {
+            let link = post_url(site_url, &item.id);
+            ItemBuilder::default()
+                .title(item.title.clone())
+                .link(link.clone())
+                .guid(GuidBuilder::default().value(link).permalink(true).build())
+                .author(item.author.clone())
+                .description(item.content_html.clone())
+                .pub_date(item.published_at.to_rfc2822())
+                .categories(
+                    categories(item)
+                        .map(|tag| RssCategoryBuilder::default().name(tag.clone()).build())
+                        .collect::<Vec<_>>(),
+                )
+                .build()
+        })
+        .collect();
+
+    ChannelBuilder::default()
+        .title(feed.title.clone())
+        .link(feed.link.clone())
+        .description(feed.description.clone())
+        .last_build_date(feed.updated().to_rfc2822())
+        .items(items)
+        .build()
+        .to_string()
+}
+
+pub fn atom(feed: &Feed, site_url: &str) -> String {
+    let entries: Vec<atom_syndication::Entry> = feed
+        .items
+        .iter()
+        .map(|item| {
+            let link = post_url(site_url, &item.id);
+            EntryBuilder::default()
+                .title(Text::plain(item.title.clone()))
+                .id(link.clone())
+                .link(LinkBuilder::default().href(link).rel("alternate").build())
+                .author(PersonBuilder::default().name(item.author.clone()).build())
+                .published(Some(item.published_at.fixed_offset()))
+                .updated(item.updated_at.fixed_offset())
+                .content(Some(
+                    ContentBuilder::default()
+                        .value(Some(item.content_html.clone()))
+                        .content_type(Some("html".to_owned()))
+                        .build(),
+                ))
+                .categories(
+                    categories(item)
+                        .map(|tag| AtomCategoryBuilder::default().term(tag.clone()).build())
+                        .collect::<Vec<_>>(),
+                )
+                .build()
+        })
+        .collect();
+
+    let self_url = format!("{}/atom.xml", feed.self_url);
+    FeedBuilder::default()
+        .title(Text::plain(feed.title.clone()))
+        .subtitle(Some(Text::plain(feed.description.clone())))
+        .id(self_url.clone())
+        .updated(feed.updated().fixed_offset())
+        .links(vec![
+            LinkBuilder::default()
+                .href(feed.link.clone())
+                .rel("alternate")
+                .build(),
+            LinkBuilder::default().href(self_url).rel("self").build(),
+        ])
+        .entries(entries)
+        .build()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn feed() -> Feed {
+        let item = |title: &str, updated: i64| FeedItem {
+            id: Uuid::new_v4(),
+            title: title.to_owned(),
+            content_html: "<p>Sixteen heirs &amp; one fortune</p>".to_owned(),
+            author: "Jenny Sinha".to_owned(),
+            space_name: "Books".to_owned(),
+            tags: vec!["mystery".to_owned()],
+            published_at: Utc.timestamp_opt(1_694_000_000, 0).unwrap(),
+            updated_at: Utc.timestamp_opt(updated, 0).unwrap(),
+        };
+
+        Feed {
+            title: "Books".to_owned(),
+            description: "All about books".to_owned(),
+            link: "https://mocha.test/spaces/books".to_owned(),
+            self_url: "https://mocha.test/feeds/spaces/books".to_owned(),
+            items: vec![
+                item("The Westing Game", 1_694_100_000),
+                item("The Hobbit", 1_694_200_000),
+            ],
+        }
+    }
+
+    #[test]
+    pub fn test_updated() {
+        let mut feed = feed();
+        assert_eq!(feed.updated().timestamp(), 1_694_200_000);
+
+        feed.items.clear();
+        assert_eq!(feed.updated().timestamp(), 0);
+    }
+
+    #[test]
+    pub fn test_rss() {
+        let feed = feed();
+        let xml = rss(&feed, "https://mocha.test");
+
+        let channel = rss::Channel::read_from(xml.as_bytes()).expect("rss feed should parse");
+        assert_eq!(channel.title(), "Books");
+        assert_eq!(channel.items().len(), 2);
+        assert_eq!(
+            channel.items()[0].link(),
+            Some(post_url("https://mocha.test", &feed.items[0].id).as_str())
+        );
+        assert_eq!(
+            channel.items()[0].description(),
+            Some("<p>Sixteen heirs &amp; one fortune</p>")
+        );
+        assert_eq!(channel.items()[0].categories()[0].name(), "Books");
+        assert_eq!(channel.items()[0].categories()[1].name(), "mystery");
+        assert_eq!(
+            channel.last_build_date(),
+            Some(feed.updated().to_rfc2822().as_str())
+        );
+    }
+
+    #[test]
+    pub fn test_atom() {
+        let feed = feed();
+        let xml = atom(&feed, "https://mocha.test");
+
+        let parsed: atom_syndication::Feed = xml.parse().expect("atom feed should parse");
+        assert_eq!(parsed.title().as_str(), "Books");
+        assert_eq!(
+            parsed.id(),
+            "https://mocha.test/feeds/spaces/books/atom.xml"
+        );
+        assert_eq!(parsed.updated().timestamp(), 1_694_200_000);
+        assert_eq!(parsed.entries().len(), 2);
+        assert_eq!(parsed.entries()[1].updated().timestamp(), 1_694_200_000);
+        assert_eq!(
+            parsed.entries()[0].content().and_then(|c| c.value()),
+            Some("<p>Sixteen heirs &amp; one fortune</p>")
+        );
+    }
+}
@@ -0,0 +1,142 @@
+mod outbox;
+mod smtp;
+
+use async_trait::async_trait;
+use derive_more::{Display, Error as DeriveError};
+use std::{env, error::Error};
+
+pub use outbox::OutboxMailer;
+pub use smtp::{SmtpMailer, SmtpTls};
+
+/// A plain text email
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Display, DeriveError)]
+pub enum MailError {
+    #[display(fmt = "email headers can't contain line breaks")]
+    HeaderInjection,
+    #[display(fmt = "unknown mail transport")]
+    UnknownTransport,
+    #[display(fmt = "smtp credentials can only be sent over tls")]
+    InsecureCredentials,
+    #[display(fmt = "mail server replied {_0}")]
+    #[error(ignore)]
+    Rejected(String),
+}
+
+impl Email {
+    /// Render the message as it goes over the wire, with CRLF line endings
+    pub fn render(&self, from: &str) -> Result<String, MailError> {
+        if [from, &self.to, &self.subject]
+            .iter()
+            .any(|h| h.contains(['\r', '\n']))
+        {
+            return Err(MailError::HeaderInjection);
+        }
+
+        let domain = from
+            .rsplit_once('@')
+            .map(|(_, d)| d.trim_end_matches('>'))
+            .unwrap_or("localhost");
+        let body = self.body.replace("\r\n", "\n").replace('\n', "\r\n");
+
+        Ok(format!(
+            "From: {from}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{domain}>\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{body}\r\n",
+            self.to,
+            self.subject,
+            chrono::offset::Utc::now().to_rfc2822(),
+            uuid::Uuid::new_v4(),
+        ))
+    }
+}
+
+/// Something that can deliver email. Handlers only ever see this trait, so the transport is picked
+/// once at startup.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// Build the mailer selected by `MAIL_TRANSPORT`. Development defaults to writing messages to an
+/// outbox directory instead of sending them anywhere.
+pub fn from_env() -> Result<Box<dyn Mailer>, Box<dyn Error + Send + Sync>> {
+    let from = env::var("MAIL_FROM").unwrap_or("Milk and Mocha <noreply@localhost>".to_owned());
+
+    match env::var("MAIL_TRANSPORT")
+        .unwrap_or("outbox".to_owned())
+        .to_lowercase()
+        .as_str()
+    {
+        "smtp" => {
+            let host = env::var("SMTP_HOST")?;
+            let tls = match env::var("SMTP_TLS")
+                .unwrap_or("starttls".to_owned())
+                .to_lowercase()
+                .as_str()
+            {
+                "none" | "off" => SmtpTls::None,
+                "tls" | "implicit" => SmtpTls::Implicit,
+                _ => SmtpTls::StartTls,
+            };
+            let port = env::var("SMTP_PORT")
+                .ok()
+                .and_then(|p| p.parse::<u16>().ok())
+                .unwrap_or(match tls {
+                    SmtpTls::Implicit => 465,
+                    _ => 587,
+                });
+            let credentials = match (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+                (Ok(username), Ok(password)) => Some((username, password)),
+                _ => None,
+            };
+            if credentials.is_some() && tls == SmtpTls::None {
+                Err(MailError::InsecureCredentials)?;
+            }
+            Ok(Box::new(SmtpMailer {
+                host,
+                port,
+                tls,
+                credentials,
+                from,
+            }))
+        }
+        "outbox" | "file" => Ok(Box::new(OutboxMailer {
+            dir: env::var("MAIL_OUTBOX_DIR")
+                .unwrap_or("../outbox".to_owned())
+                .into(),
+            from,
+        })),
+        _ => Err(MailError::UnknownTransport)?,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let email = Email {
+            to: "jenny@example.com".to_owned(),
+            subject: "hello".to_owned(),
+            body: "line one\nline two".to_owned(),
+        };
+        let rendered = email.render("Mocha <noreply@example.com>").unwrap();
+        assert!(
+            rendered.starts_with("From: Mocha <noreply@example.com>\r\nTo: jenny@example.com\r\n")
+        );
+        assert!(rendered.contains("@example.com>\r\n"));
+        assert!(rendered.ends_with("\r\n\r\nline one\r\nline two\r\n"));
+
+        let injected = Email {
+            subject: "hello\r\nBcc: everyone@example.com".to_owned(),
+            ..email
+        };
+        assert!(injected.render("noreply@example.com").is_err());
+    }
+}
@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use std::{error::Error, path::PathBuf};
+
+use crate::app::util;
+
+use super::{Email, Mailer};
+
+/// Writes every message to its own `.eml` file instead of sending it. Meant for development and
+/// tests, where the files can be opened or read back.
+pub struct OutboxMailer {
+    pub dir: PathBuf,
+    pub from: String,
+}
+
+#[async_trait]
+impl Mailer for OutboxMailer {
+    async fn send(&self, email: Email) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let message = email.render(&self.from)?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let file_name = format!("{}-{}.eml", util::time::now(), util::rng::random_string(8));
+        let path = self.dir.join(file_name);
+        tokio::fs::write(&path, message).await?;
+        log::info!("wrote email to {}", path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_outbox() {
+        let dir = std::env::temp_dir().join(format!("outbox-{}", util::rng::random_string(8)));
+        let mailer = OutboxMailer {
+            dir: dir.clone(),
+            from: "noreply@example.com".to_owned(),
+        };
+
+        mailer
+            .send(Email {
+                to: "jenny@example.com".to_owned(),
+                subject: "hello".to_owned(),
+                body: "hi jenny".to_owned(),
+            })
+            .await
+            .expect("error sending email");
+
+        let mut entries = std::fs::read_dir(&dir).unwrap();
+        let written = std::fs::read_to_string(entries.next().unwrap().unwrap().path()).unwrap();
+        assert!(written.contains("To: jenny@example.com\r\n"));
+        assert!(written.ends_with("hi jenny\r\n"));
+        assert!(entries.next().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use openssl::ssl::{SslConnector, SslMethod};
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use super::{Email, MailError, Mailer};
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain text, never with credentials
+    None,
+    /// STARTTLS
+    StartTls,
+    /// TLS from the first byte
+    Implicit,
+}
+
+/// Sends mail through an SMTP relay
+pub struct SmtpMailer {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub credentials: Option<(String, String)>,
+    pub from: String,
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let message = email.render(&self.from)?;
+        let envelope = Envelope {
+            host: self.host.clone(),
+            port: self.port,
+            tls: self.tls,
+            credentials: self.credentials.clone(),
+            from: address(&self.from).to_owned(),
+            to: address(&email.to).to_owned(),
+            message,
+        };
+        tokio::task::spawn_blocking(move || envelope.deliver()).await?
+    }
+}
+
+/// The bare address out of `Name <address>`
+fn address(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+struct Envelope {
+    host: String,
+    port: u16,
+    tls: SmtpTls,
+    credentials: Option<(String, String)>,
+    from: String,
+    to: String,
+    message: String,
+}
+
+impl Envelope {
+    fn deliver(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.credentials.is_some() && self.tls == SmtpTls::None {
+            Err(MailError::InsecureCredentials)?;
+        }
+        let stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(SMTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(SMTP_TIMEOUT))?;
+
+        match self.tls {
+            SmtpTls::None => self.converse(BufReader::new(stream)),
+            SmtpTls::Implicit => {
+                let connector = SslConnector::builder(SslMethod::tls_client())?.build();
+                let stream = connector.connect(&self.host, stream)?;
+                self.converse(BufReader::new(stream))
+            }
+            SmtpTls::StartTls => {
+                let mut conn = BufReader::new(stream);
+                expect(&mut conn, 220)?;
+                command(&mut conn, "EHLO localhost", 250)?;
+                command(&mut conn, "STARTTLS", 220)?;
+
+                let connector = SslConnector::builder(SslMethod::tls_client())?.build();
+                let stream = connector.connect(&self.host, conn.into_inner())?;
+                let mut conn = BufReader::new(stream);
+                self.transaction(&mut conn)
+            }
+        }
+    }
+
+    fn converse<S: Read + Write>(
+        &self,
+        mut conn: BufReader<S>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        expect(&mut conn, 220)?;
+        self.transaction(&mut conn)
+    }
+
+    fn transaction<S: Read + Write>(
+        &self,
+        conn: &mut BufReader<S>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        command(conn, "EHLO localhost", 250)?;
+        if let Some((username, password)) = &self.credentials {
+            let token = STANDARD.encode(format!("\0{username}\0{password}"));
+            command(conn, &format!("AUTH PLAIN {token}"), 235)?;
+        }
+        command(conn, &format!("MAIL FROM:<{}>", self.from), 250)?;
+        command(conn, &format!("RCPT TO:<{}>", self.to), 250)?;
+        command(conn, "DATA", 354)?;
+
+        // lines starting with a dot get another one so they can't end the message early
+        let mut data = self
+            .message
+            .split("\r\n")
+            .map(|line| match line.starts_with('.') {
+                true => format!(".{line}"),
+                false => line.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("\r\n");
+        if !data.ends_with("\r\n") {
+            data.push_str("\r\n");
+        }
+        data.push_str(".\r\n");
+        conn.get_mut().write_all(data.as_bytes())?;
+        expect(conn, 250)?;
+
+        command(conn, "QUIT", 221)?;
+        Ok(())
+    }
+}
+
+fn command<S: Read + Write>(
+    conn: &mut BufReader<S>,
+    line: &str,
+    code: u16,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    conn.get_mut().write_all(format!("{line}\r\n").as_bytes())?;
+    conn.get_mut().flush()?;
+    expect(conn, code)
+}
+
+fn expect<S: Read + Write>(
+    conn: &mut BufReader<S>,
+    code: u16,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    loop {
+        let mut line = String::new();
+        if conn.read_line(&mut line)? == 0 {
+            Err(MailError::Rejected("nothing".to_owned()))?;
+        }
+        let line = line.trim_end();
+        if !line.starts_with(&code.to_string()) {
+            Err(MailError::Rejected(line.to_owned()))?;
+        }
+        // "250-" continues the reply, "250 " ends it
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn fake_server(listener: TcpListener) -> std::thread::JoinHandle<Vec<String>> {
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut conn = BufReader::new(stream);
+            let mut received = vec![];
+            let reply = |conn: &mut BufReader<TcpStream>, text: &str| {
+                conn.get_mut().write_all(text.as_bytes()).unwrap();
+            };
+
+            reply(&mut conn, "220 mail.example.com ready\r\n");
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if conn.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end_matches("\r\n").to_owned();
+                received.push(line.clone());
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        reply(&mut conn, "250 queued\r\n");
+                    }
+                    continue;
+                }
+                match line.split(' ').next().unwrap() {
+                    "EHLO" => reply(&mut conn, "250-mail.example.com\r\n250 AUTH PLAIN\r\n"),
+                    "AUTH" => reply(&mut conn, "235 ok\r\n"),
+                    "DATA" => {
+                        in_data = true;
+                        reply(&mut conn, "354 go ahead\r\n");
+                    }
+                    "QUIT" => {
+                        reply(&mut conn, "221 bye\r\n");
+                        break;
+                    }
+                    _ => reply(&mut conn, "250 ok\r\n"),
+                }
+            }
+            received
+        })
+    }
+
+    #[tokio::test]
+    async fn test_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mailer = SmtpMailer {
+            host: "127.0.0.1".to_owned(),
+            port,
+            tls: SmtpTls::None,
+            credentials: Some(("jenny".to_owned(), "secret".to_owned())),
+            from: "Mocha <noreply@example.com>".to_owned(),
+        };
+        let email = Email {
+            to: "jenny@example.com".to_owned(),
+            subject: "hello".to_owned(),
+            body: "hi jenny\n.hidden line".to_owned(),
+        };
+        // credentials never go out in plain text
+        assert!(mailer.send(email.clone()).await.is_err());
+
+        let server = fake_server(listener);
+        let mailer = SmtpMailer {
+            credentials: None,
+            ..mailer
+        };
+        mailer.send(email).await.expect("error sending email");
+
+        let received = server.join().unwrap();
+        assert_eq!(received[0], "EHLO localhost");
+        assert_eq!(received[1], "MAIL FROM:<noreply@example.com>");
+        assert_eq!(received[2], "RCPT TO:<jenny@example.com>");
+        assert!(received.contains(&"..hidden line".to_owned()));
+        assert_eq!(received.last().unwrap(), "QUIT");
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+use syntect::{
+    html::{ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+}
+
+/// Prefix for the css classes emitted by the syntax highlighter. The frontend ships a stylesheet
+/// for these (syntect can generate one from any sublime theme).
+const HIGHLIGHT_CLASS_PREFIX: &str = "hl-";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub title: String,
+    pub anchor: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderedContent {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+    pub word_count: usize,
+    pub image_count: usize,
+}
+
+/// Turn arbitrary text into something usable as an html id, e.g. "What's New?" -> "whats-new"
+fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_owned()
+}
+
+/// Claim `base` as an html id, or the first of `base-1`, `base-2`, ... that hasn't been used yet
+fn unique_anchor(used: &mut HashMap<String, usize>, base: &str) -> String {
+    let mut anchor = base.to_owned();
+    while used.contains_key(&anchor) {
+        let count = used.entry(base.to_owned()).or_insert(0);
+        *count += 1;
+        anchor = format!("{base}-{count}");
+    }
+    used.insert(anchor.clone(), 0);
+    anchor
+}
+
+/// The number and anchor of a footnote, handed out the first time its label turns up. Anchors
+/// share a namespace with headings.
+fn footnote<'a>(
+    footnotes: &'a mut HashMap<String, (usize, String)>,
+    used_anchors: &mut HashMap<String, usize>,
+    label: &str,
+) -> &'a (usize, String) {
+    let next = footnotes.len() + 1;
+    footnotes.entry(label.to_owned()).or_insert_with(|| {
+        let anchor = unique_anchor(used_anchors, &format!("fn-{}", slugify(label)));
+        (next, anchor)
+    })
+}
+
+fn highlight(code: &str, lang: &str) -> String {
+    let syntax = SYNTAX_SET
+        .find_syntax_by_token(lang)
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(
+        syntax,
+        &SYNTAX_SET,
+        ClassStyle::SpacedPrefixed {
+            prefix: HIGHLIGHT_CLASS_PREFIX,
+        },
+    );
+
+    let mut highlighted = true;
+    for line in LinesWithEndings::from(code) {
+        if generator
+            .parse_html_for_line_which_includes_newline(line)
+            .is_err()
+        {
+            highlighted = false;
+            break;
+        }
+    }
+
+    let body = if highlighted {
+        generator.finalize()
+    } else {
+        let mut escaped = String::new();
+        // writing into a string cannot fail
+        let _ = pulldown_cmark::escape::escape_html(&mut escaped, code);
+        escaped
+    };
+
+    let lang = slugify(lang);
+    format!("<pre class=\"highlight\"><code class=\"language-{lang}\">{body}</code></pre>\n")
+}
+
+fn sanitize(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_tag_attributes("h1", &["id"])
+        .add_tag_attributes("h2", &["id"])
+        .add_tag_attributes("h3", &["id"])
+        .add_tag_attributes("h4", &["id"])
+        .add_tag_attributes("h5", &["id"])
+        .add_tag_attributes("h6", &["id"])
+        .add_tag_attributes("a", &["class"])
+        .add_tag_attributes("div", &["id", "class"])
+        .add_tag_attributes("sup", &["class"])
+        .add_tag_attributes("pre", &["class"])
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("span", &["class"])
+        // task list checkboxes. whatever the input claimed to be, it leaves as a disabled checkbox
+        .add_tags(&["input"])
+        .add_tag_attributes("input", &["checked"])
+        .set_tag_attribute_value("input", "type", "checkbox")
+        .set_tag_attribute_value("input", "disabled", "")
+        .clean(html)
+        .to_string()
+}
+
+/// Render a markdown document to sanitized html and collect a table of contents from its
+/// headings. Every heading gets a unique anchor, fenced code blocks are highlighted with css
+/// classes, and footnotes are supported.
+pub fn render(markdown: &str) -> RenderedContent {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+
+    let events: Vec<Event> = Parser::new_ext(markdown, options).collect();
+
+    let mut word_count = 0;
+    let mut image_count = 0;
+    for event in events.iter() {
+        match event {
+            Event::Text(text) | Event::Code(text) => word_count += text.split_whitespace().count(),
+            Event::Start(Tag::Image(..)) => image_count += 1,
+            _ => (),
+        }
+    }
+
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+
+    let mut toc = Vec::<TocEntry>::new();
+    let mut used_anchors = HashMap::<String, usize>::new();
+    let mut footnotes = HashMap::<String, (usize, String)>::new();
+    let mut code_block: Option<(String, String)> = None;
+
+    let mut i = 0;
+    while i < events.len() {
+        match &events[i] {
+            Event::Start(Tag::Heading(level, _, _)) => {
+                // find the end of the heading so the anchor can be built from its text
+                let mut title = String::new();
+                let mut end = i + 1;
+                while end < events.len() {
+                    match &events[end] {
+                        Event::End(Tag::Heading(..)) => break,
+                        Event::Text(text) | Event::Code(text) => title.push_str(text),
+                        _ => (),
+                    }
+                    end += 1;
+                }
+
+                let mut anchor = slugify(&title);
+                if anchor.is_empty() {
+                    anchor = "section".to_owned();
+                }
+                let anchor = unique_anchor(&mut used_anchors, &anchor);
+
+                let level = *level as u8;
+                output.push(Event::Html(CowStr::from(format!(
+                    "<h{level} id=\"{anchor}\">"
+                ))));
+                output.extend(events[i + 1..end].iter().cloned());
+                output.push(Event::Html(CowStr::from(format!("</h{level}>\n"))));
+
+                toc.push(TocEntry {
+                    level,
+                    title,
+                    anchor,
+                });
+                i = end;
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let lang = match kind {
+                    CodeBlockKind::Fenced(lang) => {
+                        lang.split_whitespace().next().unwrap_or("").to_owned()
+                    }
+                    CodeBlockKind::Indented => "".to_owned(),
+                };
+                code_block = Some((lang, String::new()));
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, code)) = code_block.as_mut() {
+                    code.push_str(text);
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((lang, code)) = code_block.take() {
+                    output.push(Event::Html(CowStr::from(highlight(&code, &lang))));
+                }
+            }
+            Event::FootnoteReference(label) => {
+                let (number, anchor) = footnote(&mut footnotes, &mut used_anchors, label);
+                output.push(Event::Html(CowStr::from(format!(
+                    "<sup class=\"footnote-reference\"><a href=\"#{anchor}\">{number}</a></sup>"
+                ))));
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                let (number, anchor) = footnote(&mut footnotes, &mut used_anchors, label);
+                output.push(Event::Html(CowStr::from(format!(
+                    "<div class=\"footnote-definition\" id=\"{anchor}\"><sup class=\"footnote-definition-label\">{number}</sup>"
+                ))));
+            }
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                output.push(Event::Html(CowStr::from("</div>\n")));
+            }
+            event => output.push(event.clone()),
+        }
+        i += 1;
+    }
+
+    let mut unsafe_html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, output.into_iter());
+
+    RenderedContent {
+        html: sanitize(&unsafe_html),
+        toc,
+        word_count,
+        image_count,
+    }
+}
+
+/// Estimate how long a post takes to read in whole minutes, rounded up and never less than one.
+/// Images and stickers each add a fixed number of seconds on top of the time spent on the words.
+pub fn read_time(
+    word_count: usize,
+    image_count: usize,
+    sticker_count: usize,
+    words_per_minute: u32,
+    image_seconds: u32,
+    sticker_seconds: u32,
+) -> i32 {
+    let words_per_minute = words_per_minute.max(1) as f64;
+    let seconds = word_count as f64 / words_per_minute * 60.0
+        + (image_count as u64 * image_seconds as u64) as f64
+        + (sticker_count as u64 * sticker_seconds as u64) as f64;
+    ((seconds / 60.0).ceil() as i32).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_render() {
+        let markdown = r#"# The Westing Game
+
+Sixteen heirs, one fortune.[^1]
+
+## The Heirs
+
+```rust
+fn main() {
+    println!("hello jenny");
+}
+```
+
+## The Heirs
+
+<script>alert("boom")</script>
+
+[^1]: Ellen Raskin, 1978
+"#;
+
+        let rendered = render(markdown);
+
+        assert_eq!(
+            rendered.toc,
+            vec![
+                TocEntry {
+                    level: 1,
+                    title: "The Westing Game".to_owned(),
+                    anchor: "the-westing-game".to_owned(),
+                },
+                TocEntry {
+                    level: 2,
+                    title: "The Heirs".to_owned(),
+                    anchor: "the-heirs".to_owned(),
+                },
+                TocEntry {
+                    level: 2,
+                    title: "The Heirs".to_owned(),
+                    anchor: "the-heirs-1".to_owned(),
+                },
+            ]
+        );
+
+        assert!(rendered.html.contains("<h1 id=\"the-westing-game\">"));
+        assert!(rendered.html.contains("<h2 id=\"the-heirs-1\">"));
+        assert!(rendered.html.contains("<code class=\"language-rust\">"));
+        assert!(rendered.html.contains("class=\"hl-"));
+        assert!(rendered.html.contains("href=\"#fn-1\""));
+        assert!(rendered.html.contains("id=\"fn-1\""));
+        assert!(!rendered.html.contains("<script"));
+        assert_eq!(rendered.word_count, 20);
+        assert_eq!(rendered.image_count, 0);
+    }
+
+    #[test]
+    pub fn test_anchors() {
+        let markdown = "# Fn note\n\nSee below.[^note]\n\n# Fn note\n\n[^note]: A footnote\n";
+        let rendered = render(markdown);
+        let anchors: Vec<_> = rendered.toc.iter().map(|e| e.anchor.as_str()).collect();
+        assert_eq!(anchors, vec!["fn-note", "fn-note-2"]);
+        // the footnote is numbered and linked on first sight, before the second heading
+        assert!(rendered.html.contains("href=\"#fn-note-1\""));
+        assert!(rendered.html.contains("id=\"fn-note-1\""));
+    }
+
+    #[test]
+    pub fn test_task_lists() {
+        let rendered =
+            render("- [x] find the heirs\n- [ ] solve the puzzle\n\n<input type=\"text\">");
+        assert!(rendered.html.contains("checked"));
+        assert_eq!(rendered.html.matches("type=\"checkbox\"").count(), 3);
+        assert_eq!(rendered.html.matches("disabled").count(), 3);
+        assert!(!rendered.html.contains("type=\"text\""));
+    }
+
+    #[test]
+    pub fn test_read_time() {
+        let rendered = render("Sam Westing ![portrait](/westing.png) and ![hat](/hat.png)");
+        assert_eq!(rendered.word_count, 5);
+        assert_eq!(rendered.image_count, 2);
+
+        assert_eq!(read_time(0, 0, 0, 230, 12, 3), 1);
+        assert_eq!(read_time(230, 0, 0, 230, 12, 3), 1);
+        assert_eq!(read_time(231, 0, 0, 230, 12, 3), 2);
+        assert_eq!(read_time(460, 5, 0, 230, 12, 3), 3);
+        assert_eq!(read_time(460, 0, 20, 230, 12, 3), 3);
+        assert_eq!(read_time(100, 0, 0, 0, 12, 3), 100);
+    }
+
+    #[test]
+    pub fn test_slugify() {
+        assert_eq!(slugify("What's New?"), "whats-new");
+        assert_eq!(slugify("  rust -- and c++ "), "rust-and-c");
+        assert_eq!(slugify("???"), "");
+    }
+}
@@ -4,9 +4,15 @@ mod config;
 mod dto;
 pub mod entities;
 pub mod errors;
+mod feeds;
 mod launch;
+mod mail;
+mod markdown;
 mod pagination;
 pub mod routes;
+pub mod scheduler;
+mod sitemap;
+mod spam;
 pub mod state;
 mod storage;
 pub mod types;
@@ -4,3 +4,9 @@ use actix_web::web::ServiceConfig;
 pub fn config(cfg: &mut ServiceConfig) {
     cfg.configure(api::v1::config);
 }
+
+/// Routes served from the root of the site rather than under /api
+pub fn site_config(cfg: &mut ServiceConfig) {
+    cfg.configure(api::feeds::config)
+        .configure(api::sitemap::config);
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+use tokio::time::MissedTickBehavior;
+
+use crate::app::{state::AppState, storage::postgres};
+
+/// Background worker that runs alongside the http server. All of the state it acts on lives in
+/// postgres, so a restart simply picks up where the last run left off, and it is safe to run one
+/// worker per instance against the same database.
+pub async fn run(state: Data<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(state.config.scheduler_interval));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    log::info!(
+        "starting scheduler (interval: {}s)",
+        state.config.scheduler_interval
+    );
+
+    loop {
+        interval.tick().await;
+        publish_scheduled_posts(&state).await;
+        delete_expired_sessions(&state).await;
+        delete_expired_login_challenges(&state).await;
+        delete_expired_passkey_challenges(&state).await;
+        delete_expired_oidc_states(&state).await;
+    }
+}
+
+async fn publish_scheduled_posts(state: &AppState) {
+    match postgres::posts::publish_scheduled_posts(&state.storage_layer.pg).await {
+        Ok(published) => {
+            for post_id in published {
+                log::info!("published scheduled post {post_id}");
+            }
+        }
+        Err(e) => log::error!("error publishing scheduled posts: {e}"),
+    }
+}
+
+async fn delete_expired_sessions(state: &AppState) {
+    match state
+        .session_manager
+        .delete_expired_sessions(&state.storage_layer)
+        .await
+    {
+        Ok(0) => (),
+        Ok(deleted) => log::info!("deleted {deleted} expired sessions"),
+        Err(e) => log::error!("error deleting expired sessions: {e}"),
+    }
+}
+
+async fn delete_expired_login_challenges(state: &AppState) {
+    match postgres::totp::delete_expired_login_challenges(&state.storage_layer.pg).await {
+        Ok(0) => (),
+        Ok(deleted) => log::info!("deleted {deleted} expired login challenges"),
+        Err(e) => log::error!("error deleting expired login challenges: {e}"),
+    }
+}
+
+async fn delete_expired_passkey_challenges(state: &AppState) {
+    match postgres::passkeys::delete_expired_passkey_challenges(&state.storage_layer.pg).await {
+        Ok(0) => (),
+        Ok(deleted) => log::info!("deleted {deleted} expired passkey challenges"),
+        Err(e) => log::error!("error deleting expired passkey challenges: {e}"),
+    }
+}
+
+async fn delete_expired_oidc_states(state: &AppState) {
+    match postgres::identities::delete_expired_oidc_states(&state.storage_layer.pg).await {
+        Ok(0) => (),
+        Ok(deleted) => log::info!("deleted {deleted} expired oidc states"),
+        Err(e) => log::error!("error deleting expired oidc states: {e}"),
+    }
+}
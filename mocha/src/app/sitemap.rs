@@ -0,0 +1,144 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use super::{
+    entities::sitemap::{SitemapEntry, SitemapEntryKind, SitemapPage},
+    feeds::{post_url, space_url, tag_url},
+    launch::LaunchMode,
+};
+
+/// The sitemap protocol caps a single file at 50,000 urls. Anything bigger is split into pages
+/// that are listed by a sitemap index.
+pub const SITEMAP_PAGE_SIZE: i64 = 50_000;
+
+const SITEMAP_NAMESPACE: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn lastmod(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub fn page_url(site_url: &str, page: i64) -> String {
+    format!("{site_url}/sitemaps/{page}.xml")
+}
+
+pub fn urlset(entries: &[SitemapEntry], site_url: &str) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"{SITEMAP_NAMESPACE}\">\n"
+    );
+    for entry in entries {
+        let loc = match entry.kind {
+            SitemapEntryKind::Post => post_url(site_url, &entry.id),
+            SitemapEntryKind::Space => space_url(site_url, &entry.id),
+            SitemapEntryKind::Tag => tag_url(site_url, &entry.id),
+        };
+        xml.push_str(&format!(
+            "<url><loc>{}</loc><lastmod>{}</lastmod></url>\n",
+            escape(&loc),
+            lastmod(&entry.updated_at)
+        ));
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+pub fn index(pages: &[SitemapPage], site_url: &str) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<sitemapindex xmlns=\"{SITEMAP_NAMESPACE}\">\n"
+    );
+    for page in pages {
+        xml.push_str(&format!(
+            "<sitemap><loc>{}</loc><lastmod>{}</lastmod></sitemap>\n",
+            escape(&page_url(site_url, page.page)),
+            lastmod(&page.updated_at)
+        ));
+    }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+/// Only production may be indexed. Every other launch mode turns all crawlers away so that
+/// staging and test deployments never show up in search results.
+pub fn robots(launch_mode: &LaunchMode, site_url: &str) -> String {
+    match launch_mode {
+        LaunchMode::Production => {
+            format!("User-agent: *\nDisallow: /api/\n\nSitemap: {site_url}/sitemap.xml\n")
+        }
+        _ => "User-agent: *\nDisallow: /\n".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    pub fn test_urlset() {
+        let id = Uuid::new_v4();
+        let entries = vec![
+            SitemapEntry {
+                kind: SitemapEntryKind::Post,
+                id,
+                updated_at: Utc.timestamp_opt(1_694_000_000, 0).unwrap(),
+            },
+            SitemapEntry {
+                kind: SitemapEntryKind::Tag,
+                id,
+                updated_at: Utc.timestamp_opt(1_694_100_000, 0).unwrap(),
+            },
+        ];
+
+        let xml = urlset(&entries, "https://mocha.test");
+        assert!(xml.contains(&format!(
+            "<url><loc>https://mocha.test/posts/{id}</loc><lastmod>2023-09-06T11:33:20Z</lastmod></url>"
+        )));
+        assert!(xml.contains(&format!("<loc>https://mocha.test/tags/{id}</loc>")));
+        assert!(xml.ends_with("</urlset>\n"));
+    }
+
+    #[test]
+    pub fn test_index() {
+        let pages = vec![
+            SitemapPage {
+                page: 0,
+                updated_at: Utc.timestamp_opt(1_694_000_000, 0).unwrap(),
+            },
+            SitemapPage {
+                page: 1,
+                updated_at: Utc.timestamp_opt(1_694_100_000, 0).unwrap(),
+            },
+        ];
+
+        let xml = index(&pages, "https://mocha.test?a=1&b=2");
+        assert!(xml.contains("<sitemapindex"));
+        assert!(xml.contains("<loc>https://mocha.test?a=1&amp;b=2/sitemaps/1.xml</loc>"));
+        assert_eq!(xml.matches("<sitemap>").count(), 2);
+    }
+
+    #[test]
+    pub fn test_robots() {
+        let production = robots(&LaunchMode::Production, "https://mocha.test");
+        assert!(production.contains("Sitemap: https://mocha.test/sitemap.xml"));
+        assert!(!production.contains("Disallow: /\n"));
+
+        for mode in [
+            LaunchMode::Development,
+            LaunchMode::Testing,
+            LaunchMode::Staging,
+        ] {
+            assert_eq!(
+                robots(&mode, "https://mocha.test"),
+                "User-agent: *\nDisallow: /\n"
+            );
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeSet;
+
+use super::entities::spam::SpamStats;
+
+/// The filter stays quiet until moderators have labelled at least this many comments each way.
+/// Scores from a handful of examples are mostly noise.
+pub const MIN_DOCUMENTS: i32 = 5;
+
+const MIN_TOKEN_LENGTH: usize = 2;
+const MAX_TOKEN_LENGTH: usize = 32;
+const MAX_TOKENS: usize = 256;
+
+/// Split a comment into the set of distinct lowercase words it contains. Every token is counted
+/// once per comment no matter how often it is repeated.
+pub fn tokenize(content: &str) -> Vec<String> {
+    content
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| (MIN_TOKEN_LENGTH..=MAX_TOKEN_LENGTH).contains(&t.chars().count()))
+        .map(str::to_lowercase)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .take(MAX_TOKENS)
+        .collect()
+}
+
+/// Naive bayes estimate of the probability that a comment is spam, or `None` if the filter
+/// hasn't been trained enough to say. Token likelihoods are laplace smoothed and summed in log
+/// space. Tokens that were never seen carry no evidence either way and are left out of `stats`.
+pub fn spam_probability(stats: &SpamStats) -> Option<f64> {
+    if stats.spam_documents < MIN_DOCUMENTS || stats.ham_documents < MIN_DOCUMENTS {
+        return None;
+    }
+
+    let spam_documents = stats.spam_documents as f64;
+    let ham_documents = stats.ham_documents as f64;
+    let total = spam_documents + ham_documents;
+
+    let mut log_spam = (spam_documents / total).ln();
+    let mut log_ham = (ham_documents / total).ln();
+    for token in &stats.tokens {
+        log_spam += ((token.spam_count as f64 + 1.0) / (spam_documents + 2.0)).ln();
+        log_ham += ((token.ham_count as f64 + 1.0) / (ham_documents + 2.0)).ln();
+    }
+
+    Some(1.0 / (1.0 + (log_ham - log_spam).exp()))
+}
+
+pub fn is_spam(stats: &SpamStats, threshold: f64) -> bool {
+    spam_probability(stats).is_some_and(|p| p >= threshold)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::entities::spam::TokenCounts;
+
+    use super::*;
+
+    fn counts(token: &str, spam_count: i32, ham_count: i32) -> TokenCounts {
+        TokenCounts {
+            token: token.to_owned(),
+            spam_count,
+            ham_count,
+        }
+    }
+
+    #[test]
+    pub fn test_tokenize() {
+        let tokens = tokenize("Buy CHEAP pills at https://pills.example, cheap cheap! a");
+        assert_eq!(
+            tokens,
+            vec!["at", "buy", "cheap", "example", "https", "pills"]
+        );
+        assert!(tokenize("").is_empty());
+    }
+
+    #[test]
+    pub fn test_spam_probability() {
+        let untrained = SpamStats {
+            spam_documents: 1,
+            ham_documents: 40,
+            tokens: vec![counts("pills", 1, 0)],
+        };
+        assert_eq!(spam_probability(&untrained), None);
+        assert!(!is_spam(&untrained, 0.5));
+
+        let spammy = SpamStats {
+            spam_documents: 20,
+            ham_documents: 20,
+            tokens: vec![counts("cheap", 18, 1), counts("pills", 19, 0)],
+        };
+        let p = spam_probability(&spammy).expect("filter should be trained");
+        assert!(p > 0.99);
+        assert!(is_spam(&spammy, 0.9));
+
+        let hammy = SpamStats {
+            spam_documents: 20,
+            ham_documents: 20,
+            tokens: vec![counts("turtle", 0, 12), counts("heirs", 1, 9)],
+        };
+        assert!(spam_probability(&hammy).unwrap() < 0.05);
+
+        // nothing known about the comment, so only the priors are left
+        let unknown = SpamStats {
+            spam_documents: 10,
+            ham_documents: 30,
+            tokens: vec![],
+        };
+        assert!((spam_probability(&unknown).unwrap() - 0.25).abs() < 1e-9);
+    }
+}
@@ -1,9 +1,13 @@
 use super::{
     auth::{
+        oidc::OidcManager,
         sessions::{SessionInterface, SessionManager},
+        totp::TotpManager,
+        webauthn::PasskeyManager,
         CredentialManager,
     },
     config::{Config, StorageLayer},
+    mail::{self, Mailer},
     types::HashAlgorithm,
 };
 use std::env;
@@ -13,6 +17,10 @@ pub struct AppState {
     pub storage_layer: StorageLayer,
     pub credential_manager: CredentialManager,
     pub session_manager: SessionManager,
+    pub totp_manager: TotpManager,
+    pub passkey_manager: PasskeyManager,
+    pub oidc_manager: OidcManager,
+    pub mailer: Box<dyn Mailer>,
 }
 
 impl AppState {
@@ -29,6 +37,8 @@ impl AppState {
         {
             "argon2" => HashAlgorithm::Argon2,
             "bcrypt" => HashAlgorithm::Bcrypt,
+            "scrypt" => HashAlgorithm::Scrypt,
+            "pbkdf2" => HashAlgorithm::Pbkdf2,
             _ => HashAlgorithm::Argon2,
         };
 
@@ -42,14 +52,30 @@ impl AppState {
             _ => SessionInterface::Redis,
         };
 
-        let credential_manager = CredentialManager::new(hash_algorithm);
-        let session_manager = SessionManager::new(session_interface);
+        let credential_manager =
+            CredentialManager::with_costs(hash_algorithm, config.hash_costs.clone())
+                .with_peppers(config.password_peppers.clone());
+        let session_manager = SessionManager::new(
+            session_interface,
+            config.session_absolute_timeout,
+            config.session_idle_timeout,
+        );
+        let totp_manager = TotpManager::new(config.totp_key);
+        let passkey_manager = PasskeyManager::new(&config.webauthn_rp_id, &config.webauthn_origin);
+        let oidc_manager =
+            OidcManager::new(config.oidc_providers.clone(), &config.oidc_redirect_uri);
+
+        let mailer = mail::from_env().expect("error configuring mail transport");
 
         Self {
             config,
             storage_layer,
             credential_manager,
             session_manager,
+            totp_manager,
+            passkey_manager,
+            oidc_manager,
+            mailer,
         }
     }
 }
@@ -10,5 +10,11 @@ pub enum StorageError {
     PgEndSession,
     RedisGetSession,
     PgGetSession,
+    RedisTouchSession,
+    PgTouchSession,
+    RedisGetUserSessions,
+    PgGetUserSessions,
+    RedisEndUserSessions,
+    PgEndUserSessions,
     NotFound,
 }
@@ -6,8 +6,9 @@ use uuid::Uuid;
 use crate::app::{
     dto::auth::{
         AddRoleToUser, AttachInlinePermission, CreatePermission, CreateRole, CreateSession,
-        DeletePermission, DeleteRole, DeleteSession, EditPermission, EditRole, GetPermissionById,
-        GetRoleById, GetSessionById, GetUserRbac,
+        DeleteExpiredSessions, DeletePermission, DeleteRole, DeleteSession, DeleteUserSessions,
+        EditPermission, EditRole, GetPermissionById, GetRoleById, GetSessionById,
+        GetSessionsByUserId, GetUserRbac, TouchSession,
     },
     entities::auth::{Permission, Role, RoleWithPermissions, Session, UserAccess, UserRbac},
     storage::errors::StorageError,
@@ -68,6 +69,89 @@ pub async fn get_session<'a>(
     Ok(session)
 }
 
+/// Record activity on a session. The update trigger takes care of `updated_at`.
+pub async fn touch_session<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: TouchSession,
+) -> Result<(), StorageError> {
+    let session_id = Uuid::parse_str(&data.id).map_err(|_| {
+        log::error!("error converting string (session id) to uuid");
+        StorageError::PgTouchSession
+    })?;
+    sqlx::query("update jen.sessions set updated_at=current_timestamp where id=$1")
+        .bind(session_id)
+        .execute(executor)
+        .await
+        .map_err(|_| StorageError::PgTouchSession)?;
+    Ok(())
+}
+
+/// Delete sessions that are past either their absolute or idle timeout. Returns how many went.
+pub async fn delete_expired_sessions<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: DeleteExpiredSessions,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let sql = "delete from jen.sessions
+               where created_at <= current_timestamp - make_interval(secs => $1)
+                  or updated_at <= current_timestamp - make_interval(secs => $2)";
+    let rows = sqlx::query(sql)
+        .bind(data.absolute_timeout as f64)
+        .bind(data.idle_timeout as f64)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+/// Get every session a user has, most recently active first
+pub async fn get_user_sessions<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetSessionsByUserId,
+) -> Result<Vec<Session>, StorageError> {
+    let user_id = Uuid::parse_str(&data.user_id).map_err(|_| {
+        log::error!("error converting string (user id) to uuid");
+        StorageError::PgGetUserSessions
+    })?;
+    let sessions = sqlx::query_as!(
+        Session,
+        "select id, user_id, data, created_at, updated_at from jen.sessions where user_id=$1
+         order by updated_at desc",
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|_| StorageError::PgGetUserSessions)?;
+    Ok(sessions)
+}
+
+/// End all of a user's sessions, optionally keeping one of them. Returns how many were ended.
+pub async fn end_user_sessions<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: DeleteUserSessions,
+) -> Result<u64, StorageError> {
+    let user_id = Uuid::parse_str(&data.user_id).map_err(|_| {
+        log::error!("error converting string (user id) to uuid");
+        StorageError::PgEndUserSessions
+    })?;
+    let except = data
+        .except
+        .map(|id| Uuid::parse_str(&id))
+        .transpose()
+        .map_err(|_| {
+            log::error!("error converting string (session id) to uuid");
+            StorageError::PgEndUserSessions
+        })?;
+    let rows =
+        sqlx::query("delete from jen.sessions where user_id=$1 and ($2::uuid is null or id <> $2)")
+            .bind(user_id)
+            .bind(except)
+            .execute(executor)
+            .await
+            .map_err(|_| StorageError::PgEndUserSessions)?
+            .rows_affected();
+    Ok(rows)
+}
+
 pub async fn create_permission<'a>(
     executor: impl Executor<'a, Database = Postgres>,
     data: CreatePermission,
@@ -369,7 +453,10 @@ pub async fn get_user_rbac<'a>(
 #[cfg(test)]
 mod tests {
     use crate::app::{
-        auth::CredentialManager, dto::users::CreateUser, storage::postgres, types::HashAlgorithm,
+        auth::{credentials::HashCosts, CredentialManager},
+        dto::users::CreateUser,
+        storage::postgres,
+        types::HashAlgorithm,
         util,
     };
 
@@ -507,7 +594,7 @@ mod tests {
 
         let email = format!("jennycho35-{random_suffix}@gmail.com");
 
-        let manager = CredentialManager::new(HashAlgorithm::Argon2);
+        let manager = CredentialManager::with_costs(HashAlgorithm::Argon2, HashCosts::default());
         let hash = manager.create_hash(b"jennysinha").unwrap();
 
         let new_user = CreateUser {
@@ -518,6 +605,7 @@ mod tests {
             image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
             hashed_password: Some(hash.to_owned()),
             algorithm: Some(HashAlgorithm::Argon2),
+            pepper_id: None,
         };
 
         let new_user = postgres::users::create_user(&mut *txn, new_user)
@@ -602,4 +690,133 @@ mod tests {
 
         txn.rollback().await.unwrap();
     }
+
+    #[tokio::test]
+    pub async fn test_user_sessions() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.unwrap();
+        let mut txn = pool.begin().await.unwrap();
+
+        let random_suffix = util::rng::random_string(4);
+
+        let user_id = postgres::users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jennycho35-{random_suffix}@gmail.com"),
+                username: format!("jennysinha-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating new user");
+
+        let mut session_ids = vec![];
+        for device in ["laptop", "phone", "tablet"] {
+            let id = start_session(
+                &mut *txn,
+                CreateSession {
+                    user_id: user_id.clone(),
+                    data: serde_json::json!({ "user_agent": device }),
+                    created_at: chrono::offset::Utc::now(),
+                    updated_at: chrono::offset::Utc::now(),
+                },
+            )
+            .await
+            .expect("error starting session");
+            session_ids.push(id);
+        }
+
+        touch_session(
+            &mut *txn,
+            TouchSession {
+                id: session_ids[1].clone(),
+                expires_in: 60,
+            },
+        )
+        .await
+        .expect("error touching session");
+
+        let sessions = get_user_sessions(
+            &mut *txn,
+            GetSessionsByUserId {
+                user_id: user_id.clone(),
+            },
+        )
+        .await
+        .expect("error getting sessions");
+        assert_eq!(sessions.len(), 3);
+
+        end_session(
+            &mut *txn,
+            DeleteSession {
+                id: session_ids[2].clone(),
+            },
+        )
+        .await
+        .expect("error ending session");
+
+        let ended = end_user_sessions(
+            &mut *txn,
+            DeleteUserSessions {
+                user_id: user_id.clone(),
+                except: Some(session_ids[0].clone()),
+            },
+        )
+        .await
+        .expect("error ending sessions");
+        assert_eq!(ended, 1);
+
+        let sessions = get_user_sessions(
+            &mut *txn,
+            GetSessionsByUserId {
+                user_id: user_id.clone(),
+            },
+        )
+        .await
+        .expect("error getting sessions");
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id.to_string(), session_ids[0]);
+        assert_eq!(sessions[0].data["user_agent"], "laptop");
+
+        delete_expired_sessions(
+            &mut *txn,
+            DeleteExpiredSessions {
+                absolute_timeout: 60,
+                idle_timeout: 60,
+            },
+        )
+        .await
+        .expect("error deleting expired sessions");
+        let sessions = get_user_sessions(
+            &mut *txn,
+            GetSessionsByUserId {
+                user_id: user_id.clone(),
+            },
+        )
+        .await
+        .expect("error getting sessions");
+        assert_eq!(sessions.len(), 1);
+
+        // the transaction's clock doesn't move, so a zero timeout expires everything just made
+        delete_expired_sessions(
+            &mut *txn,
+            DeleteExpiredSessions {
+                absolute_timeout: 60,
+                idle_timeout: 0,
+            },
+        )
+        .await
+        .expect("error deleting expired sessions");
+        let sessions = get_user_sessions(&mut *txn, GetSessionsByUserId { user_id })
+            .await
+            .expect("error getting sessions");
+        assert!(sessions.is_empty());
+
+        txn.rollback().await.unwrap();
+    }
 }
@@ -0,0 +1,765 @@
+use sqlx::{postgres::PgRow, Acquire, Executor, FromRow, Postgres, QueryBuilder, Row};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::{
+        comments::{
+            CreateComment, DeleteComment, EditComment, GetCommentById, GetThread, GetThreads,
+            ModerateComment,
+        },
+        pagination::{CommentPaginationOptions, ModerationPaginationOptions, PaginationLimits},
+    },
+    entities::{comments::Comment, spam::SpamLabel},
+    pagination::PaginationContainer,
+    spam,
+    storage::postgres,
+    types::CommentStatus,
+};
+
+impl<'r> FromRow<'r, PgRow> for Comment {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Comment {
+            id: row.try_get("id")?,
+            post_id: row.try_get("post_id")?,
+            user_id: row.try_get("user_id")?,
+            parent_id: row.try_get("parent_id")?,
+            thread_id: row.try_get("thread_id")?,
+            depth: row.try_get("depth")?,
+            content: row.try_get("content")?,
+            // see the note on Post's FromRow about enums and their schema
+            status: row.try_get_unchecked("status")?,
+            reply_count: row.try_get("reply_count")?,
+            like_count: row.try_get("like_count")?,
+            liked: row.try_get("liked")?,
+            edited_at: row.try_get("edited_at")?,
+            deleted_at: row.try_get("deleted_at")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+
+/// Comments are only readable by people who can read the post they belong to, and until they're
+/// approved only by their author. Tombstones keep their position in the thread but lose their
+/// author and content.
+fn select_comments(user_id: Uuid) -> QueryBuilder<'static, Postgres> {
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "select c.id, c.post_id, case when c.deleted_at is null then c.user_id end as user_id,
+         c.parent_id, c.thread_id, c.depth, c.content, c.status, c.reply_count,
+         c.like_count, exists(select 1 from jen.comment_likes l where l.comment_id=c.id and
+         l.user_id=",
+    );
+    builder
+        .push_bind(user_id)
+        .push(
+            ") as liked, c.edited_at, c.deleted_at, c.created_at, c.updated_at
+             from jen.comments c join jen.posts p on p.id=c.post_id and (p.user_id=",
+        )
+        .push_bind(user_id)
+        .push(" or (p.published and p.visibility='public')) and (c.status='approved' or c.user_id=")
+        .push_bind(user_id)
+        .push(") where ");
+    builder
+}
+
+async fn paginate_comments<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    mut builder: QueryBuilder<'_, Postgres>,
+    pagination: PaginationLimits<CommentPaginationOptions>,
+) -> Result<PaginationContainer<Comment>, Box<dyn Error + Send + Sync>> {
+    builder.push(" order by c.created_at ");
+    if pagination.opts.asc {
+        builder.push("asc");
+    } else {
+        builder.push("desc");
+    }
+    builder
+        .push(", c.id offset ")
+        .push_bind(pagination.offset)
+        .push(" limit ")
+        .push_bind(pagination.limit + 1);
+
+    let comments = builder
+        .build_query_as::<Comment>()
+        .fetch_all(executor)
+        .await?;
+    Ok(PaginationContainer::new(comments, pagination.limit))
+}
+
+/// Create a comment or, when `parent_id` is set, a reply. Returns `None` if the post can't be
+/// commented on by this user or the parent doesn't exist on the same post, has been deleted or
+/// hasn't been approved.
+///
+/// A user's first comment is held for moderation. Once a moderator has approved one of their
+/// comments the rest go straight through, and authors never wait on their own posts. Comments the
+/// spam filter flagged are set aside as spam either way.
+pub async fn create_comment<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: CreateComment,
+) -> Result<Option<(String, CommentStatus)>, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let parent_id = data.parent_id.as_deref().map(Uuid::parse_str).transpose()?;
+
+    let sql = "insert into jen.comments (post_id, user_id, parent_id, thread_id, depth, content,
+                                        status)
+               select p.id, $2, parent.id, coalesce(parent.thread_id, parent.id),
+                      coalesce(parent.depth + 1, 0), $4,
+                      case when $5 then 'spam'::jen.comment_status
+                           when p.user_id=$2 or exists(select 1 from jen.comments prior
+                                                       where prior.user_id=$2
+                                                       and prior.status='approved')
+                           then 'approved'
+                           else 'pending' end
+               from jen.posts p
+               left join jen.comments parent on parent.id=$3 and parent.post_id=p.id
+                    and parent.deleted_at is null and parent.status='approved'
+               where p.id=$1 and p.published and (p.visibility='public' or p.user_id=$2)
+                     and ($3::uuid is null or parent.id is not null)
+               returning id, status";
+    let created = sqlx::query(sql)
+        .bind(post_id)
+        .bind(user_id)
+        .bind(parent_id)
+        .bind(data.content)
+        .bind(data.spam)
+        .fetch_optional(executor)
+        .await?;
+    let Some(row) = created else {
+        return Ok(None);
+    };
+
+    let id: Uuid = row.try_get("id")?;
+    Ok(Some((id.to_string(), row.try_get_unchecked("status")?)))
+}
+
+pub async fn get_comment<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetCommentById,
+) -> Result<Option<Comment>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let comment_id = Uuid::parse_str(&data.comment_id)?;
+
+    let mut builder = select_comments(user_id);
+    builder
+        .push("c.id=")
+        .push_bind(comment_id)
+        .push(" and c.post_id=")
+        .push_bind(post_id);
+
+    let comment = builder
+        .build_query_as::<Comment>()
+        .fetch_optional(executor)
+        .await?;
+    Ok(comment)
+}
+
+/// Top level comments on a post, each of which starts a thread
+pub async fn get_threads<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetThreads,
+    pagination: PaginationLimits<CommentPaginationOptions>,
+) -> Result<PaginationContainer<Comment>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+
+    let mut builder = select_comments(user_id);
+    builder
+        .push("c.post_id=")
+        .push_bind(post_id)
+        .push(" and c.parent_id is null");
+
+    paginate_comments(executor, builder, pagination).await
+}
+
+/// Every reply in a thread regardless of depth. Replies carry their `parent_id` and `depth` so
+/// the client can rebuild the tree.
+pub async fn get_thread<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetThread,
+    pagination: PaginationLimits<CommentPaginationOptions>,
+) -> Result<PaginationContainer<Comment>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let thread_id = Uuid::parse_str(&data.thread_id)?;
+
+    let mut builder = select_comments(user_id);
+    builder
+        .push("c.post_id=")
+        .push_bind(post_id)
+        .push(" and c.thread_id=")
+        .push_bind(thread_id);
+
+    paginate_comments(executor, builder, pagination).await
+}
+
+/// Edit a comment's content. Only the author can do this, only while the edit window is open and
+/// never once the comment has been deleted. Returns the comment's new status, or `None` if it
+/// couldn't be edited.
+///
+/// Edited comments go through the same trust rules as new ones. Rejected comments and comments set
+/// aside as spam stay that way. Whatever the spam filter learned from the old content is taken
+/// back.
+pub async fn edit_comment<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: EditComment,
+) -> Result<Option<CommentStatus>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let comment_id = Uuid::parse_str(&data.comment_id)?;
+
+    let mut txn = executor.begin().await?;
+
+    let sql = "select c.content, c.trained_as from jen.comments c
+               where c.id=$1 and c.post_id=$2 and c.user_id=$3 and c.deleted_at is null
+               and c.created_at > current_timestamp - make_interval(secs => $4)
+               for update";
+    let current = sqlx::query(sql)
+        .bind(comment_id)
+        .bind(post_id)
+        .bind(user_id)
+        .bind(data.edit_window as f64)
+        .fetch_optional(&mut *txn)
+        .await?;
+    let Some(current) = current else {
+        return Ok(None);
+    };
+    let content: String = current.try_get("content")?;
+    let trained_as: Option<SpamLabel> = current.try_get_unchecked("trained_as")?;
+
+    if let Some(previous) = trained_as {
+        let tokens = spam::tokenize(&content);
+        postgres::spam::train_spam_filter(&mut *txn, &tokens, previous, -1).await?;
+    }
+
+    let sql = "update jen.comments c set content=$1, edited_at=current_timestamp, trained_as=null,
+               status=case when c.status in ('rejected', 'spam') then c.status
+                           when $3 then 'spam'
+                           when p.user_id=c.user_id or exists(select 1 from jen.comments prior
+                                                              where prior.user_id=c.user_id
+                                                              and prior.status='approved')
+                           then 'approved'
+                           else 'pending' end
+               from jen.posts p
+               where c.id=$2 and p.id=c.post_id
+               returning c.status";
+    let status: CommentStatus = sqlx::query(sql)
+        .bind(data.content)
+        .bind(comment_id)
+        .bind(data.spam)
+        .fetch_one(&mut *txn)
+        .await?
+        .try_get_unchecked("status")?;
+
+    txn.commit().await?;
+    Ok(Some(status))
+}
+
+/// Soft delete a comment, leaving a tombstone behind so its replies stay attached to the thread
+pub async fn delete_comment<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: DeleteComment,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let comment_id = Uuid::parse_str(&data.comment_id)?;
+
+    let sql = "update jen.comments set content='', deleted_at=current_timestamp
+               where id=$1 and post_id=$2 and user_id=$3 and deleted_at is null";
+    let rows = sqlx::query(sql)
+        .bind(comment_id)
+        .bind(post_id)
+        .bind(user_id)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+/// Comments waiting on a moderator, or ones that have already been dealt with. Moderators see
+/// everything, including who wrote the comment.
+pub async fn get_moderation_queue<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    pagination: PaginationLimits<ModerationPaginationOptions>,
+) -> Result<PaginationContainer<Comment>, Box<dyn Error + Send + Sync>> {
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "select c.id, c.post_id, c.user_id, c.parent_id, c.thread_id, c.depth, c.content, c.status,
+         c.reply_count, c.like_count, false as liked, c.edited_at, c.deleted_at, c.created_at, c.updated_at
+         from jen.comments c where c.deleted_at is null and c.status=",
+    );
+    builder.push_bind(pagination.opts.status);
+
+    paginate_comments(
+        executor,
+        builder,
+        PaginationLimits {
+            offset: pagination.offset,
+            limit: pagination.limit,
+            opts: CommentPaginationOptions {
+                asc: pagination.opts.asc,
+            },
+        },
+    )
+    .await
+}
+
+/// Record a moderator's decision and teach the spam filter from it. Approved comments are learned
+/// as ham and spam as spam. If an earlier decision taught the filter something else, that lesson is
+/// taken back first.
+pub async fn moderate_comment<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: ModerateComment,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let moderator_id = Uuid::parse_str(&data.moderator_id)?;
+    let comment_id = Uuid::parse_str(&data.comment_id)?;
+
+    let mut txn = executor.begin().await?;
+
+    let sql = "select content, trained_as from jen.comments
+               where id=$1 and deleted_at is null for update";
+    let current = sqlx::query(sql)
+        .bind(comment_id)
+        .fetch_optional(&mut *txn)
+        .await?;
+    let Some(current) = current else {
+        return Ok(0);
+    };
+    let content: String = current.try_get("content")?;
+    let trained_as: Option<SpamLabel> = current.try_get_unchecked("trained_as")?;
+
+    let label = match data.status {
+        CommentStatus::Approved => Some(SpamLabel::Ham),
+        CommentStatus::Spam => Some(SpamLabel::Spam),
+        CommentStatus::Pending | CommentStatus::Rejected => None,
+    };
+    if label != trained_as {
+        let tokens = spam::tokenize(&content);
+        if let Some(previous) = trained_as {
+            postgres::spam::train_spam_filter(&mut *txn, &tokens, previous, -1).await?;
+        }
+        if let Some(label) = label {
+            postgres::spam::train_spam_filter(&mut *txn, &tokens, label, 1).await?;
+        }
+    }
+
+    let sql = "update jen.comments set status=$1, trained_as=$2, moderated_by=$3,
+               moderated_at=current_timestamp where id=$4";
+    let rows = sqlx::query(sql)
+        .bind(data.status)
+        .bind(label)
+        .bind(moderator_id)
+        .bind(comment_id)
+        .execute(&mut *txn)
+        .await?
+        .rows_affected();
+
+    txn.commit().await?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::{
+            posts::{CreatePost, PublishPost},
+            spaces::CreateSpace,
+            users::CreateUser,
+        },
+        storage::postgres::{self, posts, spaces, users},
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_comments() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let author = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating author");
+
+        let reader = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Anish".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("anish-{random_suffix}@gmail.com"),
+                username: format!("anish-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/anish".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating reader");
+
+        let space_id = spaces::create_space(
+            &mut *txn,
+            CreateSpace {
+                space_name: format!("Books {random_suffix}"),
+                bio: format!("All about books {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating space");
+
+        let post_id = posts::create_post(
+            &mut *txn,
+            CreatePost {
+                user_id: author.clone(),
+                space_id: space_id.clone(),
+                title: "The Westing Game".to_owned(),
+                content: "Sixteen heirs, one fortune.".to_owned(),
+                content_html: "".to_owned(),
+                toc: serde_json::json!([]),
+                image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+                private: false,
+                tags: vec![],
+                stickers: vec![],
+                read_time: 1,
+                word_count: 4,
+            },
+        )
+        .await
+        .expect("error creating post")
+        .expect("post is unexpectedly none");
+
+        let comment = |user_id: &str, parent_id: Option<&String>, content: &str| CreateComment {
+            user_id: user_id.to_owned(),
+            post_id: post_id.clone(),
+            parent_id: parent_id.cloned(),
+            content: content.to_owned(),
+            spam: false,
+        };
+
+        // drafts can't be commented on
+        let on_draft = create_comment(&mut *txn, comment(&reader, None, "First!"))
+            .await
+            .expect("error creating comment");
+        assert!(on_draft.is_none());
+
+        posts::publish_post(
+            &mut *txn,
+            PublishPost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error publishing post");
+
+        // a first comment is held until a moderator approves it
+        let (root, status) =
+            create_comment(&mut *txn, comment(&reader, None, "Who is the bomber?"))
+                .await
+                .expect("error creating comment")
+                .expect("comment should have been created");
+        assert_eq!(status, CommentStatus::Pending);
+
+        let get_root = |user_id: &str| GetCommentById {
+            user_id: user_id.to_owned(),
+            post_id: post_id.clone(),
+            comment_id: root.clone(),
+        };
+        let held = get_comment(&mut *txn, get_root(&author))
+            .await
+            .expect("error fetching comment");
+        assert!(held.is_none());
+        let own = get_comment(&mut *txn, get_root(&reader))
+            .await
+            .expect("error fetching comment");
+        assert_eq!(own.map(|c| c.status), Some(CommentStatus::Pending));
+
+        let early_reply = create_comment(&mut *txn, comment(&author, Some(&root), "Hmm."))
+            .await
+            .expect("error creating reply");
+        assert!(early_reply.is_none());
+
+        let before = postgres::spam::get_spam_stats(&mut *txn, &[])
+            .await
+            .expect("error fetching spam stats");
+        let moderate = |status: CommentStatus| ModerateComment {
+            moderator_id: author.clone(),
+            comment_id: root.clone(),
+            status,
+        };
+        for status in [CommentStatus::Spam, CommentStatus::Approved] {
+            let moderated = moderate_comment(&mut *txn, moderate(status))
+                .await
+                .expect("error moderating comment");
+            assert_eq!(moderated, 1);
+        }
+        // changing a decision takes back what the spam filter learned from the first one
+        let after = postgres::spam::get_spam_stats(&mut *txn, &[])
+            .await
+            .expect("error fetching spam stats");
+        assert_eq!(after.spam_documents, before.spam_documents);
+        assert_eq!(after.ham_documents, before.ham_documents + 1);
+
+        let (reply, status) =
+            create_comment(&mut *txn, comment(&author, Some(&root), "Turtle knows."))
+                .await
+                .expect("error creating reply")
+                .expect("reply should have been created");
+        assert_eq!(status, CommentStatus::Approved);
+        // once approved, a reader's comments go straight through
+        let (nested, status) =
+            create_comment(&mut *txn, comment(&reader, Some(&reply), "Of course."))
+                .await
+                .expect("error creating reply")
+                .expect("reply should have been created");
+        assert_eq!(status, CommentStatus::Approved);
+
+        let pagination = || PaginationLimits {
+            offset: 0,
+            limit: 10,
+            opts: CommentPaginationOptions { asc: true },
+        };
+
+        let threads = get_threads(
+            &mut *txn,
+            GetThreads {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+            },
+            pagination(),
+        )
+        .await
+        .expect("error fetching threads");
+        assert_eq!(threads.items.len(), 1);
+        assert_eq!(threads.items[0].id.to_string(), root);
+        assert_eq!(threads.items[0].reply_count, 1);
+
+        let thread = get_thread(
+            &mut *txn,
+            GetThread {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+                thread_id: root.clone(),
+            },
+            pagination(),
+        )
+        .await
+        .expect("error fetching thread");
+        assert_eq!(thread.items.len(), 2);
+        // everything in this test shares a timestamp, so look the nested reply up by id
+        let nested_reply = thread
+            .items
+            .iter()
+            .find(|c| c.id.to_string() == nested)
+            .expect("nested reply should be part of the thread");
+        assert_eq!(nested_reply.depth, 2);
+        assert_eq!(
+            nested_reply.parent_id.map(|id| id.to_string()),
+            Some(reply.clone())
+        );
+        assert_eq!(
+            nested_reply.thread_id.map(|id| id.to_string()),
+            Some(root.clone())
+        );
+
+        let edit = |user_id: &str, comment_id: &str, edit_window: i64| EditComment {
+            user_id: user_id.to_owned(),
+            post_id: post_id.clone(),
+            comment_id: comment_id.to_owned(),
+            content: "Turtle knows everything.".to_owned(),
+            edit_window,
+            spam: false,
+        };
+        assert!(edit_comment(&mut *txn, edit(&reader, &reply, 900))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(edit_comment(&mut *txn, edit(&author, &reply, 0))
+            .await
+            .unwrap()
+            .is_none());
+        // the post's author doesn't go back through moderation
+        assert_eq!(
+            edit_comment(&mut *txn, edit(&author, &reply, 900))
+                .await
+                .unwrap(),
+            Some(CommentStatus::Approved)
+        );
+
+        let deleted = delete_comment(
+            &mut *txn,
+            DeleteComment {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                comment_id: reply.clone(),
+            },
+        )
+        .await
+        .expect("error deleting comment");
+        assert_eq!(deleted, 1);
+
+        let tombstone = get_comment(
+            &mut *txn,
+            GetCommentById {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+                comment_id: reply.clone(),
+            },
+        )
+        .await
+        .expect("error fetching comment")
+        .expect("tombstones should still be returned");
+        assert!(tombstone.deleted_at.is_some());
+        assert!(tombstone.user_id.is_none());
+        assert!(tombstone.content.is_empty());
+        assert_eq!(tombstone.reply_count, 1);
+
+        // deleted comments can't be edited or replied to
+        assert!(edit_comment(&mut *txn, edit(&author, &reply, 900))
+            .await
+            .unwrap()
+            .is_none());
+        let orphan = create_comment(&mut *txn, comment(&reader, Some(&reply), "Hello?"))
+            .await
+            .expect("error creating reply");
+        assert!(orphan.is_none());
+
+        // an approved comment edited into spam is set aside, and what the filter learned from its
+        // old content is taken back
+        let edited = edit_comment(
+            &mut *txn,
+            EditComment {
+                content: "Cheap pills".to_owned(),
+                spam: true,
+                ..edit(&reader, &root, 900)
+            },
+        )
+        .await
+        .expect("error editing comment");
+        assert_eq!(edited, Some(CommentStatus::Spam));
+        let untrained = postgres::spam::get_spam_stats(&mut *txn, &[])
+            .await
+            .expect("error fetching spam stats");
+        assert_eq!(untrained.ham_documents, before.ham_documents);
+        let hidden = get_comment(&mut *txn, get_root(&author))
+            .await
+            .expect("error fetching comment");
+        assert!(hidden.is_none());
+
+        // editing it again doesn't get it out of the spam pile
+        let edited = edit_comment(&mut *txn, edit(&reader, &root, 900))
+            .await
+            .expect("error editing comment");
+        assert_eq!(edited, Some(CommentStatus::Spam));
+
+        // a clean edit from a commenter with an approved comment goes straight through
+        let edited = edit_comment(&mut *txn, edit(&reader, &nested, 900))
+            .await
+            .expect("error editing comment");
+        assert_eq!(edited, Some(CommentStatus::Approved));
+
+        // reply counts follow replies in and out of the approved state
+        let get_by_id = |comment_id: &str| GetCommentById {
+            user_id: reader.clone(),
+            post_id: post_id.clone(),
+            comment_id: comment_id.to_owned(),
+        };
+        let moderate_nested = |status: CommentStatus| ModerateComment {
+            moderator_id: author.clone(),
+            comment_id: nested.clone(),
+            status,
+        };
+        moderate_comment(&mut *txn, moderate_nested(CommentStatus::Rejected))
+            .await
+            .expect("error moderating comment");
+        let tombstone = get_comment(&mut *txn, get_by_id(&reply))
+            .await
+            .expect("error fetching comment")
+            .expect("tombstones should still be returned");
+        assert_eq!(tombstone.reply_count, 0);
+
+        // rejected comments stay rejected when edited
+        let edited = edit_comment(&mut *txn, edit(&reader, &nested, 900))
+            .await
+            .expect("error editing comment");
+        assert_eq!(edited, Some(CommentStatus::Rejected));
+
+        // with nothing approved left, the reader's comments wait on a moderator again
+        let (unvetted, status) = create_comment(&mut *txn, comment(&reader, None, "Sandy did it."))
+            .await
+            .expect("error creating comment")
+            .expect("comment should have been created");
+        assert_eq!(status, CommentStatus::Pending);
+        let edited = edit_comment(&mut *txn, edit(&reader, &unvetted, 900))
+            .await
+            .expect("error editing comment");
+        assert_eq!(edited, Some(CommentStatus::Pending));
+        let queue = get_moderation_queue(
+            &mut *txn,
+            PaginationLimits {
+                offset: 0,
+                limit: 100,
+                opts: ModerationPaginationOptions {
+                    status: CommentStatus::Pending,
+                    asc: false,
+                },
+            },
+        )
+        .await
+        .expect("error fetching moderation queue");
+        assert!(queue.items.iter().any(|c| c.id.to_string() == unvetted));
+
+        moderate_comment(&mut *txn, moderate_nested(CommentStatus::Approved))
+            .await
+            .expect("error moderating comment");
+        let tombstone = get_comment(&mut *txn, get_by_id(&reply))
+            .await
+            .expect("error fetching comment")
+            .expect("tombstones should still be returned");
+        assert_eq!(tombstone.reply_count, 1);
+
+        // flagged comments are set aside no matter who wrote them
+        let (flagged, status) = create_comment(
+            &mut *txn,
+            CreateComment {
+                spam: true,
+                ..comment(&reader, None, "Cheap pills")
+            },
+        )
+        .await
+        .expect("error creating comment")
+        .expect("comment should have been created");
+        assert_eq!(status, CommentStatus::Spam);
+        let hidden = get_comment(
+            &mut *txn,
+            GetCommentById {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                comment_id: flagged,
+            },
+        )
+        .await
+        .expect("error fetching comment");
+        assert!(hidden.is_none());
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
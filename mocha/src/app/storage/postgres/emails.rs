@@ -0,0 +1,409 @@
+use sqlx::{Acquire, Executor, Postgres, Transaction};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::auth::{ConfirmEmailChange, CreateEmailChange, CreateEmailVerificationToken, VerifyEmail},
+    entities::auth::EmailChangeOutcome,
+};
+
+/// Store the hash of a new verification token for the address it was mailed to. Tokens the user
+/// still had outstanding are used up, so only the most recently mailed link works.
+pub async fn create_email_verification_token<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: CreateEmailVerificationToken,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let mut txn: Transaction<'_, Postgres> = executor.begin().await?;
+
+    sqlx::query(
+        "update jen.email_verification_tokens set used_at=current_timestamp
+         where user_id=$1 and used_at is null",
+    )
+    .bind(user_id)
+    .execute(&mut *txn)
+    .await?;
+
+    let sql = "insert into jen.email_verification_tokens (user_id, email, token_hash, expires_at)
+               values ($1, $2, $3, current_timestamp + make_interval(secs => $4))
+               returning id";
+    let (id,): (Uuid,) = sqlx::query_as(sql)
+        .bind(user_id)
+        .bind(data.email)
+        .bind(data.token_hash)
+        .bind(data.expires_in as f64)
+        .fetch_one(&mut *txn)
+        .await?;
+
+    txn.commit().await?;
+    Ok(id.to_string())
+}
+
+/// Mark a user's email as verified and grant them the role that comes with it
+pub(super) async fn mark_verified(
+    txn: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    sqlx::query(
+        "update jen.users set email_verified_at=coalesce(email_verified_at, current_timestamp)
+         where id=$1",
+    )
+    .bind(user_id)
+    .execute(&mut **txn)
+    .await?;
+
+    sqlx::query(
+        "insert into jen.user_role_mappings (user_id, role_id)
+         values ($1, jen.get_role_id('mocha-verified')) on conflict do nothing",
+    )
+    .bind(user_id)
+    .execute(&mut **txn)
+    .await?;
+    Ok(())
+}
+
+/// Use up a verification token and mark its user's email as verified. Returns that user, or
+/// `None` if the token doesn't exist, has expired, was already used or was mailed to an address
+/// the user no longer has.
+pub async fn verify_email<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: VerifyEmail,
+) -> Result<Option<Uuid>, Box<dyn Error + Send + Sync>> {
+    let mut txn: Transaction<'_, Postgres> = executor.begin().await?;
+
+    let sql = "update jen.email_verification_tokens t set used_at=current_timestamp
+               from jen.users u
+               where t.token_hash=$1 and t.used_at is null and t.expires_at > current_timestamp
+               and u.id=t.user_id and u.email=t.email
+               returning t.user_id";
+    let user_id: Option<(Uuid,)> = sqlx::query_as(sql)
+        .bind(data.token_hash)
+        .fetch_optional(&mut *txn)
+        .await?;
+    let Some((user_id,)) = user_id else {
+        return Ok(None);
+    };
+
+    mark_verified(&mut txn, user_id).await?;
+
+    txn.commit().await?;
+    Ok(Some(user_id))
+}
+
+/// Start changing a user's email. Any change they had pending is dropped, so only the most
+/// recently mailed pair of links works.
+pub async fn create_email_change<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: CreateEmailChange,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let mut txn: Transaction<'_, Postgres> = executor.begin().await?;
+
+    sqlx::query("delete from jen.email_change_requests where user_id=$1 and completed_at is null")
+        .bind(user_id)
+        .execute(&mut *txn)
+        .await?;
+
+    let sql = "insert into jen.email_change_requests
+               (user_id, new_email, old_token_hash, new_token_hash, expires_at)
+               values ($1, $2, $3, $4, current_timestamp + make_interval(secs => $5))
+               returning id";
+    let (id,): (Uuid,) = sqlx::query_as(sql)
+        .bind(user_id)
+        .bind(data.new_email)
+        .bind(data.old_token_hash)
+        .bind(data.new_token_hash)
+        .bind(data.expires_in as f64)
+        .fetch_one(&mut *txn)
+        .await?;
+
+    txn.commit().await?;
+    Ok(id.to_string())
+}
+
+/// Confirm one side of an email change. The change is made as soon as both the old and the new
+/// address have confirmed, and the new address counts as verified from then on.
+pub async fn confirm_email_change<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: ConfirmEmailChange,
+) -> Result<EmailChangeOutcome, Box<dyn Error + Send + Sync>> {
+    let mut txn: Transaction<'_, Postgres> = executor.begin().await?;
+
+    let sql = "update jen.email_change_requests set
+                 old_confirmed_at = case when old_token_hash=$1
+                   then coalesce(old_confirmed_at, current_timestamp) else old_confirmed_at end,
+                 new_confirmed_at = case when new_token_hash=$1
+                   then coalesce(new_confirmed_at, current_timestamp) else new_confirmed_at end
+               where (old_token_hash=$1 or new_token_hash=$1) and completed_at is null
+               and expires_at > current_timestamp
+               returning id, user_id, new_email,
+                 old_confirmed_at is not null and new_confirmed_at is not null";
+    let confirmed: Option<(Uuid, Uuid, String, bool)> = sqlx::query_as(sql)
+        .bind(data.token_hash)
+        .fetch_optional(&mut *txn)
+        .await?;
+    let outcome = match confirmed {
+        None => EmailChangeOutcome::Invalid,
+        Some((_, _, _, false)) => EmailChangeOutcome::Pending,
+        Some((id, user_id, email, true)) => {
+            let changed = sqlx::query(
+                "update jen.users set email=$2 where id=$1
+                 and not exists (select 1 from jen.users where email=$2 and id<>$1)",
+            )
+            .bind(user_id)
+            .bind(&email)
+            .execute(&mut *txn)
+            .await?
+            .rows_affected();
+
+            if changed == 0 {
+                EmailChangeOutcome::Taken
+            } else {
+                sqlx::query(
+                    "update jen.email_change_requests set completed_at=current_timestamp
+                     where id=$1",
+                )
+                .bind(id)
+                .execute(&mut *txn)
+                .await?;
+                // links mailed to the old address mustn't verify anything anymore
+                sqlx::query(
+                    "update jen.email_verification_tokens set used_at=current_timestamp
+                     where user_id=$1 and used_at is null",
+                )
+                .bind(user_id)
+                .execute(&mut *txn)
+                .await?;
+                mark_verified(&mut txn, user_id).await?;
+                EmailChangeOutcome::Completed { user_id, email }
+            }
+        }
+    };
+
+    txn.commit().await?;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::{
+            auth::GetUserRbac,
+            users::{CreateUser, GetUserById},
+        },
+        storage::postgres::{self, auth, users},
+        util,
+    };
+
+    use super::*;
+
+    async fn can_comment(txn: &mut Transaction<'_, Postgres>, user_id: &str) -> bool {
+        let access = auth::get_user_access(
+            &mut **txn,
+            GetUserRbac {
+                user_id: user_id.to_owned(),
+            },
+        )
+        .await
+        .expect("error getting user access");
+        access
+            .permissions
+            .iter()
+            .any(|p| p.permission_name == "comments:create")
+    }
+
+    #[tokio::test]
+    pub async fn test_email_verification() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let email = format!("jenny-{random_suffix}@gmail.com");
+        let user_id = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: email.clone(),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating user");
+        assert!(!can_comment(&mut txn, &user_id).await);
+
+        let create = |token: &str, email: &str, expires_in: i64| CreateEmailVerificationToken {
+            user_id: user_id.clone(),
+            email: email.to_owned(),
+            token_hash: format!("{token}-{random_suffix}"),
+            expires_in,
+        };
+        let verify = |token: &str| VerifyEmail {
+            token_hash: format!("{token}-{random_suffix}"),
+        };
+
+        create_email_verification_token(&mut *txn, create("expired", &email, 0))
+            .await
+            .expect("error creating verification token");
+        let expired = verify_email(&mut *txn, verify("expired"))
+            .await
+            .expect("error verifying email");
+        assert!(expired.is_none());
+
+        // a token mailed to some other address doesn't verify the one the user has now
+        create_email_verification_token(&mut *txn, create("stale", "old@gmail.com", 60))
+            .await
+            .expect("error creating verification token");
+        let stale = verify_email(&mut *txn, verify("stale"))
+            .await
+            .expect("error verifying email");
+        assert!(stale.is_none());
+
+        create_email_verification_token(&mut *txn, create("good", &email, 60))
+            .await
+            .expect("error creating verification token");
+        let verified = verify_email(&mut *txn, verify("good"))
+            .await
+            .expect("error verifying email");
+        assert_eq!(verified.map(|u| u.to_string()), Some(user_id.clone()));
+
+        let reused = verify_email(&mut *txn, verify("good"))
+            .await
+            .expect("error verifying email");
+        assert!(reused.is_none());
+
+        let user = users::get_user_by_id(
+            &mut *txn,
+            GetUserById {
+                id: user_id.clone(),
+            },
+        )
+        .await
+        .expect("error getting user")
+        .expect("user doesn't exist");
+        assert!(user.email_verified_at.is_some());
+        assert!(can_comment(&mut txn, &user_id).await);
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+
+    #[tokio::test]
+    pub async fn test_email_change() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let create_user = |name: &str| CreateUser {
+            first_name: "Jenny".to_owned(),
+            last_name: "Sinha".to_owned(),
+            email: format!("{name}-{random_suffix}@gmail.com"),
+            username: format!("{name}-{random_suffix}"),
+            image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+            hashed_password: None,
+            algorithm: None,
+            pepper_id: None,
+        };
+        let user_id = users::create_user(&mut *txn, create_user("jenny"))
+            .await
+            .expect("error creating user");
+
+        let new_email = format!("jen-{random_suffix}@gmail.com");
+        let change = |name: &str, new_email: &str, expires_in: i64| CreateEmailChange {
+            user_id: user_id.clone(),
+            new_email: new_email.to_owned(),
+            old_token_hash: format!("{name}-old-{random_suffix}"),
+            new_token_hash: format!("{name}-new-{random_suffix}"),
+            expires_in,
+        };
+        let confirm = |token: &str| ConfirmEmailChange {
+            token_hash: format!("{token}-{random_suffix}"),
+        };
+
+        create_email_change(&mut *txn, change("first", &new_email, 60))
+            .await
+            .expect("error creating email change");
+        // starting over drops the first change
+        create_email_change(&mut *txn, change("second", &new_email, 60))
+            .await
+            .expect("error creating email change");
+        let dropped = confirm_email_change(&mut *txn, confirm("first-new"))
+            .await
+            .expect("error confirming email change");
+        assert_eq!(dropped, EmailChangeOutcome::Invalid);
+
+        // one side on its own isn't enough, no matter how often it confirms
+        for _ in 0..2 {
+            let pending = confirm_email_change(&mut *txn, confirm("second-new"))
+                .await
+                .expect("error confirming email change");
+            assert_eq!(pending, EmailChangeOutcome::Pending);
+        }
+
+        let completed = confirm_email_change(&mut *txn, confirm("second-old"))
+            .await
+            .expect("error confirming email change");
+        assert_eq!(
+            completed,
+            EmailChangeOutcome::Completed {
+                user_id: Uuid::parse_str(&user_id).unwrap(),
+                email: new_email.clone(),
+            }
+        );
+
+        let user = users::get_user_by_id(
+            &mut *txn,
+            GetUserById {
+                id: user_id.clone(),
+            },
+        )
+        .await
+        .expect("error getting user")
+        .expect("user doesn't exist");
+        assert_eq!(user.email, new_email);
+        assert!(user.email_verified_at.is_some());
+        assert!(can_comment(&mut txn, &user_id).await);
+
+        let replayed = confirm_email_change(&mut *txn, confirm("second-old"))
+            .await
+            .expect("error confirming email change");
+        assert_eq!(replayed, EmailChangeOutcome::Invalid);
+
+        // someone else takes the address before both sides have confirmed
+        let taken_email = format!("taken-{random_suffix}@gmail.com");
+        create_email_change(&mut *txn, change("taken", &taken_email, 60))
+            .await
+            .expect("error creating email change");
+        users::create_user(&mut *txn, create_user("taken"))
+            .await
+            .expect("error creating user");
+        confirm_email_change(&mut *txn, confirm("taken-old"))
+            .await
+            .expect("error confirming email change");
+        let taken = confirm_email_change(&mut *txn, confirm("taken-new"))
+            .await
+            .expect("error confirming email change");
+        assert_eq!(taken, EmailChangeOutcome::Taken);
+
+        create_email_change(&mut *txn, change("expired", "expired@gmail.com", 0))
+            .await
+            .expect("error creating email change");
+        let expired = confirm_email_change(&mut *txn, confirm("expired-old"))
+            .await
+            .expect("error confirming email change");
+        assert_eq!(expired, EmailChangeOutcome::Invalid);
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
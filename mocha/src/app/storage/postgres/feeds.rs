@@ -0,0 +1,202 @@
+use sqlx::{Executor, Postgres, QueryBuilder};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{dto::feeds::GetFeedItems, entities::feeds::FeedItem};
+
+/// Fetch the most recently published public posts for a feed, optionally narrowed down to a
+/// single space or tag
+pub async fn get_feed_items<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetFeedItems,
+    limit: i64,
+) -> Result<Vec<FeedItem>, Box<dyn Error + Send + Sync>> {
+    let space_id = data.space_id.as_deref().map(Uuid::parse_str).transpose()?;
+    let tag_id = data.tag_id.as_deref().map(Uuid::parse_str).transpose()?;
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "select p.id, p.title, p.content_html,
+         u.first_name || ' ' || u.last_name as author, s.space_name,
+         coalesce(array_agg(t.tag_name order by t.tag_name) filter (where t.id is not null),
+                  '{}') as tags,
+         coalesce(p.published_at, p.created_at) as published_at, p.updated_at
+         from jen.posts p
+         join jen.users u on u.id=p.user_id
+         join jen.spaces s on s.id=p.space_id
+         left join jen.post_tags pt on pt.post_id=p.id
+         left join jen.tags t on t.id=pt.tag_id
+         where p.published and p.visibility='public'",
+    );
+    if let Some(space_id) = space_id {
+        builder.push(" and p.space_id=").push_bind(space_id);
+    }
+    if let Some(tag_id) = tag_id {
+        builder
+            .push(" and exists (select 1 from jen.post_tags f where f.post_id=p.id and f.tag_id=")
+            .push_bind(tag_id)
+            .push(")");
+    }
+    builder
+        .push(" group by p.id, u.id, s.id order by published_at desc, p.id limit ")
+        .push_bind(limit);
+
+    let items = builder
+        .build_query_as::<FeedItem>()
+        .fetch_all(executor)
+        .await?;
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::{
+            posts::{CreatePost, PublishPost},
+            spaces::CreateSpace,
+            tags::CreateTag,
+            users::CreateUser,
+        },
+        storage::postgres::{self, posts, spaces, users},
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_get_feed_items() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let author = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating author");
+
+        let space_id = spaces::create_space(
+            &mut *txn,
+            CreateSpace {
+                space_name: format!("Books {random_suffix}"),
+                bio: format!("All about books {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating space");
+
+        let (tag_id, tag_name) = spaces::create_tag(
+            &mut *txn,
+            CreateTag {
+                space_id: space_id.clone(),
+                name: format!("mystery {random_suffix}"),
+                description: format!("Whodunits {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating tag");
+
+        let new_post = |title: &str, private: bool, tags: Vec<String>| CreatePost {
+            user_id: author.clone(),
+            space_id: space_id.clone(),
+            title: title.to_owned(),
+            content: "Sixteen heirs, one fortune.".to_owned(),
+            content_html: "<p>Sixteen heirs, one fortune.</p>".to_owned(),
+            toc: serde_json::json!([]),
+            image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+            private,
+            tags,
+            stickers: vec![],
+            read_time: 1,
+            word_count: 4,
+        };
+
+        let tagged = posts::create_post(
+            &mut *txn,
+            new_post("The Westing Game", false, vec![tag_id.clone()]),
+        )
+        .await
+        .expect("error creating post")
+        .expect("post is unexpectedly none");
+        let untagged = posts::create_post(&mut *txn, new_post("The Hobbit", false, vec![]))
+            .await
+            .expect("error creating post")
+            .expect("post is unexpectedly none");
+        let private = posts::create_post(&mut *txn, new_post("Diary", true, vec![]))
+            .await
+            .expect("error creating post")
+            .expect("post is unexpectedly none");
+        // a draft that never gets published
+        posts::create_post(&mut *txn, new_post("Draft", false, vec![]))
+            .await
+            .expect("error creating post")
+            .expect("post is unexpectedly none");
+
+        for post_id in [&tagged, &untagged, &private] {
+            posts::publish_post(
+                &mut *txn,
+                PublishPost {
+                    user_id: author.clone(),
+                    post_id: post_id.clone(),
+                },
+            )
+            .await
+            .expect("error publishing post");
+        }
+
+        let in_space = get_feed_items(
+            &mut *txn,
+            GetFeedItems {
+                space_id: Some(space_id.clone()),
+                tag_id: None,
+            },
+            10,
+        )
+        .await
+        .expect("error fetching feed items");
+        assert_eq!(in_space.len(), 2);
+        assert!(in_space.iter().all(|item| item.author == "Jenny Sinha"));
+        assert!(in_space.iter().all(|item| item.id.to_string() != private));
+
+        let with_tag = get_feed_items(
+            &mut *txn,
+            GetFeedItems {
+                space_id: None,
+                tag_id: Some(tag_id),
+            },
+            10,
+        )
+        .await
+        .expect("error fetching feed items");
+        assert_eq!(with_tag.len(), 1);
+        assert_eq!(with_tag[0].id.to_string(), tagged);
+        assert_eq!(with_tag[0].tags, vec![tag_name]);
+
+        let limited = get_feed_items(
+            &mut *txn,
+            GetFeedItems {
+                space_id: Some(space_id),
+                tag_id: None,
+            },
+            1,
+        )
+        .await
+        .expect("error fetching feed items");
+        assert_eq!(limited.len(), 1);
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
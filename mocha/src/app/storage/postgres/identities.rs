@@ -0,0 +1,363 @@
+use sqlx::{Acquire, Executor, Postgres, Transaction};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::auth::{
+        CreateOidcState, CreateUserIdentity, CreateUserWithIdentity, DeleteUserIdentity,
+        GetUserIdentities, GetUserIdentity, UseOidcState, UseUserIdentity,
+    },
+    entities::auth::{OidcState, UserIdentity},
+    storage::postgres::{emails, users},
+};
+
+pub async fn create_oidc_state<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: CreateOidcState,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let user_id = data.user_id.as_deref().map(Uuid::parse_str).transpose()?;
+    let sql = "insert into jen.oidc_states
+               (provider, state_hash, nonce, code_verifier, user_id, expires_at)
+               values ($1, $2, $3, $4, $5, current_timestamp + make_interval(secs => $6))
+               returning id";
+    let (id,): (Uuid,) = sqlx::query_as(sql)
+        .bind(data.provider)
+        .bind(data.state_hash)
+        .bind(data.nonce)
+        .bind(data.code_verifier)
+        .bind(user_id)
+        .bind(data.expires_in as f64)
+        .fetch_one(executor)
+        .await?;
+    Ok(id.to_string())
+}
+
+/// Use up the state a provider sent back. Returns `None` if it doesn't exist, has expired or was
+/// already used.
+pub async fn use_oidc_state<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: UseOidcState,
+) -> Result<Option<OidcState>, Box<dyn Error + Send + Sync>> {
+    let state = sqlx::query_as!(
+        OidcState,
+        "update jen.oidc_states set used_at=current_timestamp
+         where state_hash=$1 and used_at is null and expires_at > current_timestamp
+         returning id, provider, nonce, code_verifier, user_id, expires_at, created_at, updated_at",
+        data.state_hash
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(state)
+}
+
+/// Remove states that can no longer be used
+pub async fn delete_expired_oidc_states<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let rows = sqlx::query(
+        "delete from jen.oidc_states where expires_at <= current_timestamp or used_at is not null",
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+/// Link an external account to a user. Returns `None` if that account is already linked, to this
+/// user or anyone else.
+pub async fn create_user_identity<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: CreateUserIdentity,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "insert into jen.user_identities (user_id, provider, subject, email)
+               values ($1, $2, $3, $4) on conflict (provider, subject) do nothing
+               returning id";
+    let id: Option<(Uuid,)> = sqlx::query_as(sql)
+        .bind(user_id)
+        .bind(data.provider)
+        .bind(data.subject)
+        .bind(data.email)
+        .fetch_optional(executor)
+        .await?;
+    Ok(id.map(|(id,)| id.to_string()))
+}
+
+/// Create an account for someone signing in with a provider for the first time, already linked
+/// to the provider's account. Returns the new user's id.
+pub async fn create_user_with_identity<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: CreateUserWithIdentity,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut txn: Transaction<'_, Postgres> = executor.begin().await?;
+
+    let email = data.user.email.clone();
+    let user_id = users::create_user(&mut *txn, data.user).await?;
+    let linked = create_user_identity(
+        &mut *txn,
+        CreateUserIdentity {
+            user_id: user_id.clone(),
+            provider: data.provider,
+            subject: data.subject,
+            email: Some(email),
+        },
+    )
+    .await?;
+    if linked.is_none() {
+        Err("external account is already linked")?;
+    }
+    if data.email_verified {
+        emails::mark_verified(&mut txn, Uuid::parse_str(&user_id)?).await?;
+    }
+
+    txn.commit().await?;
+    Ok(user_id)
+}
+
+pub async fn get_user_identity<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetUserIdentity,
+) -> Result<Option<UserIdentity>, Box<dyn Error + Send + Sync>> {
+    let identity = sqlx::query_as!(
+        UserIdentity,
+        "select id, user_id, provider, subject, email, last_used_at, created_at, updated_at
+         from jen.user_identities where provider=$1 and subject=$2",
+        data.provider,
+        data.subject
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(identity)
+}
+
+pub async fn get_user_identities<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetUserIdentities,
+) -> Result<Vec<UserIdentity>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let identities = sqlx::query_as!(
+        UserIdentity,
+        "select id, user_id, provider, subject, email, last_used_at, created_at, updated_at
+         from jen.user_identities where user_id=$1 order by created_at",
+        user_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(identities)
+}
+
+/// Record a sign in through a linked account, along with the email the provider has for it now
+pub async fn use_user_identity<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: UseUserIdentity,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let id = Uuid::parse_str(&data.id)?;
+    sqlx::query(
+        "update jen.user_identities set last_used_at=current_timestamp, email=coalesce($2, email)
+         where id=$1",
+    )
+    .bind(id)
+    .bind(data.email)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Unlink an external account. Nothing is removed if it's the user's only way to sign in, i.e.
+/// they have no password, no passkeys and no other linked accounts.
+pub async fn delete_user_identity<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: DeleteUserIdentity,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let id = Uuid::parse_str(&data.id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "delete from jen.user_identities where id=$1 and user_id=$2
+               and (exists (select 1 from jen.user_credentials where user_id=$2)
+                    or exists (select 1 from jen.user_passkeys where user_id=$2)
+                    or exists (select 1 from jen.user_identities where user_id=$2 and id<>$1))";
+    let rows = sqlx::query(sql)
+        .bind(id)
+        .bind(user_id)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::users::{CreateUser, GetUserById},
+        storage::postgres,
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_user_identities() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let subject = |name: &str| format!("{name}-{random_suffix}");
+        let user_id = create_user_with_identity(
+            &mut *txn,
+            CreateUserWithIdentity {
+                user: CreateUser {
+                    first_name: "Jenny".to_owned(),
+                    last_name: "Sinha".to_owned(),
+                    email: format!("jenny-{random_suffix}@gmail.com"),
+                    username: format!("jenny-{random_suffix}"),
+                    image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                    hashed_password: None,
+                    algorithm: None,
+                    pepper_id: None,
+                },
+                provider: "google".to_owned(),
+                subject: subject("jenny"),
+                email_verified: true,
+            },
+        )
+        .await
+        .expect("error creating user");
+
+        // the provider already checked the email
+        let user = users::get_user_by_id(
+            &mut *txn,
+            GetUserById {
+                id: user_id.clone(),
+            },
+        )
+        .await
+        .unwrap()
+        .expect("user doesn't exist");
+        assert!(user.email_verified_at.is_some());
+
+        let identity = get_user_identity(
+            &mut *txn,
+            GetUserIdentity {
+                provider: "google".to_owned(),
+                subject: subject("jenny"),
+            },
+        )
+        .await
+        .unwrap()
+        .expect("identity doesn't exist");
+        assert_eq!(identity.user_id.to_string(), user_id);
+        assert_eq!(identity.email, Some(user.email.clone()));
+
+        use_user_identity(
+            &mut *txn,
+            UseUserIdentity {
+                id: identity.id.to_string(),
+                email: Some("jenny@sinha.dev".to_owned()),
+            },
+        )
+        .await
+        .expect("error using identity");
+
+        let link = |provider: &str, name: &str| CreateUserIdentity {
+            user_id: user_id.clone(),
+            provider: provider.to_owned(),
+            subject: subject(name),
+            email: None,
+        };
+        // an external account can only be linked once
+        assert!(create_user_identity(&mut *txn, link("google", "jenny"))
+            .await
+            .unwrap()
+            .is_none());
+        let gitlab_id = create_user_identity(&mut *txn, link("gitlab", "jenny"))
+            .await
+            .unwrap()
+            .expect("error linking identity");
+
+        let identities = get_user_identities(
+            &mut *txn,
+            GetUserIdentities {
+                user_id: user_id.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(identities.len(), 2);
+        assert_eq!(identities[0].email.as_deref(), Some("jenny@sinha.dev"));
+        assert!(identities[0].last_used_at.is_some());
+
+        let unlink = |id: &str| DeleteUserIdentity {
+            id: id.to_owned(),
+            user_id: user_id.clone(),
+        };
+        assert_eq!(
+            delete_user_identity(&mut *txn, unlink(&gitlab_id))
+                .await
+                .unwrap(),
+            1
+        );
+        // the last way in can't be removed
+        assert_eq!(
+            delete_user_identity(&mut *txn, unlink(&identity.id.to_string()))
+                .await
+                .unwrap(),
+            0
+        );
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+
+    #[tokio::test]
+    pub async fn test_oidc_states() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let create = |state: &str, expires_in: i64| CreateOidcState {
+            provider: "google".to_owned(),
+            state_hash: format!("{state}-{random_suffix}"),
+            nonce: "nonce".to_owned(),
+            code_verifier: "verifier".to_owned(),
+            user_id: None,
+            expires_in,
+        };
+        let use_state = |state: &str| UseOidcState {
+            state_hash: format!("{state}-{random_suffix}"),
+        };
+
+        create_oidc_state(&mut *txn, create("good", 60))
+            .await
+            .expect("error creating state");
+        let state = use_oidc_state(&mut *txn, use_state("good"))
+            .await
+            .unwrap()
+            .expect("state doesn't exist");
+        assert_eq!(state.provider, "google");
+        assert_eq!(state.code_verifier, "verifier");
+        assert!(state.user_id.is_none());
+        // states only work once
+        assert!(use_oidc_state(&mut *txn, use_state("good"))
+            .await
+            .unwrap()
+            .is_none());
+
+        create_oidc_state(&mut *txn, create("expired", 0))
+            .await
+            .expect("error creating state");
+        assert!(use_oidc_state(&mut *txn, use_state("expired"))
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(delete_expired_oidc_states(&mut *txn).await.unwrap() >= 2);
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
@@ -0,0 +1,395 @@
+use sqlx::{Acquire, Executor, Postgres};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::likes::{GetCommentLikes, GetPostLikes, LikeComment, LikePost, UnlikeComment, UnlikePost},
+    entities::likes::Likes,
+};
+
+/// Like a post. Liking twice is a no-op. Returns `None` if the post isn't published or can't be
+/// read by this user.
+pub async fn like_post<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: LikePost,
+) -> Result<Option<Likes>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+
+    let mut txn = executor.begin().await?;
+
+    let sql = "insert into jen.post_likes (post_id, user_id)
+               select p.id, $2 from jen.posts p
+               where p.id=$1 and p.published and (p.visibility='public' or p.user_id=$2)
+               on conflict do nothing";
+    sqlx::query(sql)
+        .bind(post_id)
+        .bind(user_id)
+        .execute(&mut *txn)
+        .await?;
+
+    let likes = select_post_likes(&mut *txn, post_id, user_id).await?;
+    txn.commit().await?;
+    Ok(likes)
+}
+
+/// Take back a like. Unliking a post that was never liked is a no-op.
+pub async fn unlike_post<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: UnlikePost,
+) -> Result<Option<Likes>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+
+    let mut txn = executor.begin().await?;
+
+    let sql = "delete from jen.post_likes where post_id=$1 and user_id=$2";
+    sqlx::query(sql)
+        .bind(post_id)
+        .bind(user_id)
+        .execute(&mut *txn)
+        .await?;
+
+    let likes = select_post_likes(&mut *txn, post_id, user_id).await?;
+    txn.commit().await?;
+    Ok(likes)
+}
+
+pub async fn get_post_likes<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetPostLikes,
+) -> Result<Option<Likes>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    select_post_likes(executor, post_id, user_id).await
+}
+
+async fn select_post_likes<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    post_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<Likes>, Box<dyn Error + Send + Sync>> {
+    let sql = "select p.like_count,
+               exists(select 1 from jen.post_likes l where l.post_id=p.id and l.user_id=$2) as liked
+               from jen.posts p
+               where p.id=$1 and (p.user_id=$2 or (p.published and p.visibility='public'))";
+    let likes = sqlx::query_as::<_, Likes>(sql)
+        .bind(post_id)
+        .bind(user_id)
+        .fetch_optional(executor)
+        .await?;
+    Ok(likes)
+}
+
+/// Like a comment. Liking twice is a no-op. Returns `None` if the comment has been deleted, hasn't
+/// been approved or its post can't be read by this user.
+pub async fn like_comment<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: LikeComment,
+) -> Result<Option<Likes>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let comment_id = Uuid::parse_str(&data.comment_id)?;
+
+    let mut txn = executor.begin().await?;
+
+    let sql = "insert into jen.comment_likes (comment_id, user_id)
+               select c.id, $3 from jen.comments c
+               join jen.posts p on p.id=c.post_id
+               where c.id=$1 and c.post_id=$2 and c.deleted_at is null and c.status='approved'
+                     and p.published and (p.visibility='public' or p.user_id=$3)
+               on conflict do nothing";
+    sqlx::query(sql)
+        .bind(comment_id)
+        .bind(post_id)
+        .bind(user_id)
+        .execute(&mut *txn)
+        .await?;
+
+    let likes = select_comment_likes(&mut *txn, comment_id, post_id, user_id).await?;
+    txn.commit().await?;
+    Ok(likes)
+}
+
+/// Take back a like on a comment. Unliking a comment that was never liked is a no-op.
+pub async fn unlike_comment<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: UnlikeComment,
+) -> Result<Option<Likes>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let comment_id = Uuid::parse_str(&data.comment_id)?;
+
+    let mut txn = executor.begin().await?;
+
+    let sql = "delete from jen.comment_likes where comment_id=$1 and user_id=$2";
+    sqlx::query(sql)
+        .bind(comment_id)
+        .bind(user_id)
+        .execute(&mut *txn)
+        .await?;
+
+    let likes = select_comment_likes(&mut *txn, comment_id, post_id, user_id).await?;
+    txn.commit().await?;
+    Ok(likes)
+}
+
+pub async fn get_comment_likes<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetCommentLikes,
+) -> Result<Option<Likes>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let comment_id = Uuid::parse_str(&data.comment_id)?;
+    select_comment_likes(executor, comment_id, post_id, user_id).await
+}
+
+async fn select_comment_likes<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    comment_id: Uuid,
+    post_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<Likes>, Box<dyn Error + Send + Sync>> {
+    let sql = "select c.like_count,
+               exists(select 1 from jen.comment_likes l where l.comment_id=c.id and l.user_id=$3)
+               as liked
+               from jen.comments c
+               join jen.posts p on p.id=c.post_id
+               where c.id=$1 and c.post_id=$2 and (c.status='approved' or c.user_id=$3)
+                     and (p.user_id=$3 or (p.published and p.visibility='public'))";
+    let likes = sqlx::query_as::<_, Likes>(sql)
+        .bind(comment_id)
+        .bind(post_id)
+        .bind(user_id)
+        .fetch_optional(executor)
+        .await?;
+    Ok(likes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::{
+            comments::CreateComment,
+            posts::{CreatePost, GetPostById, PublishPost},
+            spaces::CreateSpace,
+            users::CreateUser,
+        },
+        storage::postgres::{self, comments, posts, spaces, users},
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_likes() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let author = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating author");
+
+        let reader = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Anish".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("anish-{random_suffix}@gmail.com"),
+                username: format!("anish-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/anish".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating reader");
+
+        let space_id = spaces::create_space(
+            &mut *txn,
+            CreateSpace {
+                space_name: format!("Books {random_suffix}"),
+                bio: format!("All about books {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating space");
+
+        let post_id = posts::create_post(
+            &mut *txn,
+            CreatePost {
+                user_id: author.clone(),
+                space_id: space_id.clone(),
+                title: "The Westing Game".to_owned(),
+                content: "Sixteen heirs, one fortune.".to_owned(),
+                content_html: "".to_owned(),
+                toc: serde_json::json!([]),
+                image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+                private: false,
+                tags: vec![],
+                stickers: vec![],
+                read_time: 1,
+                word_count: 4,
+            },
+        )
+        .await
+        .expect("error creating post")
+        .expect("post is unexpectedly none");
+
+        let like = |user_id: &str| LikePost {
+            user_id: user_id.to_owned(),
+            post_id: post_id.clone(),
+        };
+
+        // drafts can't be liked
+        let on_draft = like_post(&mut *txn, like(&reader))
+            .await
+            .expect("error liking post");
+        assert!(on_draft.is_none());
+
+        posts::publish_post(
+            &mut *txn,
+            PublishPost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error publishing post");
+
+        // liking is idempotent
+        for _ in 0..2 {
+            let likes = like_post(&mut *txn, like(&reader))
+                .await
+                .expect("error liking post");
+            assert_eq!(
+                likes,
+                Some(Likes {
+                    like_count: 1,
+                    liked: true
+                })
+            );
+        }
+        let likes = like_post(&mut *txn, like(&author))
+            .await
+            .expect("error liking post");
+        assert_eq!(likes.map(|l| l.like_count), Some(2));
+
+        let after = posts::get_post_by_id(
+            &mut *txn,
+            GetPostById {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error fetching post")
+        .expect("post should exist");
+        assert_eq!(after.like_count, 2);
+        assert!(after.liked);
+
+        // and so is unliking
+        for _ in 0..2 {
+            let likes = unlike_post(
+                &mut *txn,
+                UnlikePost {
+                    user_id: reader.clone(),
+                    post_id: post_id.clone(),
+                },
+            )
+            .await
+            .expect("error unliking post");
+            assert_eq!(
+                likes,
+                Some(Likes {
+                    like_count: 1,
+                    liked: false
+                })
+            );
+        }
+
+        let comment_id = comments::create_comment(
+            &mut *txn,
+            CreateComment {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                parent_id: None,
+                content: "Turtle knows.".to_owned(),
+                spam: false,
+            },
+        )
+        .await
+        .expect("error creating comment")
+        .expect("comment should have been created")
+        .0;
+
+        let like_comment_as = |user_id: &str| LikeComment {
+            user_id: user_id.to_owned(),
+            post_id: post_id.clone(),
+            comment_id: comment_id.clone(),
+        };
+        like_comment(&mut *txn, like_comment_as(&reader))
+            .await
+            .expect("error liking comment");
+        let likes = like_comment(&mut *txn, like_comment_as(&reader))
+            .await
+            .expect("error liking comment");
+        assert_eq!(
+            likes,
+            Some(Likes {
+                like_count: 1,
+                liked: true
+            })
+        );
+
+        let author_view = get_comment_likes(
+            &mut *txn,
+            GetCommentLikes {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                comment_id: comment_id.clone(),
+            },
+        )
+        .await
+        .expect("error fetching comment likes");
+        assert_eq!(
+            author_view,
+            Some(Likes {
+                like_count: 1,
+                liked: false
+            })
+        );
+
+        let likes = unlike_comment(
+            &mut *txn,
+            UnlikeComment {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+                comment_id: comment_id.clone(),
+            },
+        )
+        .await
+        .expect("error unliking comment");
+        assert_eq!(likes.map(|l| l.like_count), Some(0));
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
@@ -3,8 +3,22 @@ use std::env;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 
 pub mod auth;
+pub mod comments;
+pub mod emails;
+pub mod feeds;
+pub mod identities;
+pub mod likes;
+pub mod passkeys;
+pub mod password_resets;
+pub mod posts;
+pub mod reactions;
+pub mod search;
+pub mod sitemap;
 pub mod spaces;
+pub mod spam;
 pub mod stickers;
+pub mod tokens;
+pub mod totp;
 pub mod users;
 
 pub async fn create_pool(max_connections: u32) -> Result<Pool<Postgres>, sqlx::Error> {
@@ -0,0 +1,364 @@
+use sqlx::{Executor, Postgres};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::auth::{
+        CreatePasskey, CreatePasskeyChallenge, DeletePasskey, GetPasskeyByCredentialId,
+        GetUserPasskeys, UsePasskey, UsePasskeyChallenge,
+    },
+    entities::auth::Passkey,
+};
+
+pub async fn create_passkey_challenge<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: CreatePasskeyChallenge,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let user_id = data.user_id.as_deref().map(Uuid::parse_str).transpose()?;
+    let sql = "insert into jen.passkey_challenges (user_id, ceremony, challenge, expires_at)
+               values ($1, $2, $3, current_timestamp + make_interval(secs => $4))
+               returning id";
+    let (id,): (Uuid,) = sqlx::query_as(sql)
+        .bind(user_id)
+        .bind(data.ceremony)
+        .bind(data.challenge)
+        .bind(data.expires_in as f64)
+        .fetch_one(executor)
+        .await?;
+    Ok(id.to_string())
+}
+
+/// Use up a challenge the browser answered. Returns `false` if it wasn't issued for this ceremony
+/// and user, has expired, or was already used.
+pub async fn use_passkey_challenge<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: UsePasskeyChallenge,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let user_id = data.user_id.as_deref().map(Uuid::parse_str).transpose()?;
+    let sql = "update jen.passkey_challenges set used_at=current_timestamp
+               where challenge=$1 and ceremony=$2 and user_id is not distinct from $3
+               and used_at is null and expires_at > current_timestamp";
+    let rows = sqlx::query(sql)
+        .bind(data.challenge)
+        .bind(data.ceremony)
+        .bind(user_id)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows > 0)
+}
+
+/// Remove challenges that can no longer be answered
+pub async fn delete_expired_passkey_challenges<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let rows = sqlx::query(
+        "delete from jen.passkey_challenges where expires_at <= current_timestamp or used_at is not null",
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+pub async fn create_passkey<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: CreatePasskey,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "insert into jen.user_passkeys
+               (user_id, credential_id, public_key, algorithm, sign_count, aaguid, name)
+               values ($1, $2, $3, $4, $5, $6, $7)
+               returning id";
+    let (id,): (Uuid,) = sqlx::query_as(sql)
+        .bind(user_id)
+        .bind(data.credential_id)
+        .bind(data.public_key)
+        .bind(data.algorithm)
+        .bind(data.sign_count)
+        .bind(data.aaguid)
+        .bind(data.name)
+        .fetch_one(executor)
+        .await?;
+    Ok(id.to_string())
+}
+
+pub async fn get_passkey_by_credential_id<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetPasskeyByCredentialId,
+) -> Result<Option<Passkey>, Box<dyn Error + Send + Sync>> {
+    let passkey = sqlx::query_as!(
+        Passkey,
+        "select id, user_id, credential_id, public_key, algorithm, sign_count, aaguid, name,
+         last_used_at, created_at, updated_at
+         from jen.user_passkeys where credential_id=$1",
+        data.credential_id
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(passkey)
+}
+
+pub async fn get_user_passkeys<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetUserPasskeys,
+) -> Result<Vec<Passkey>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let passkeys = sqlx::query_as!(
+        Passkey,
+        "select id, user_id, credential_id, public_key, algorithm, sign_count, aaguid, name,
+         last_used_at, created_at, updated_at
+         from jen.user_passkeys where user_id=$1 order by created_at",
+        user_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(passkeys)
+}
+
+/// Record a sign in with a passkey and its new signature counter. Returns `false` if the counter
+/// didn't move forward, which means a copy of the authenticator got there first. Authenticators
+/// that don't keep a counter always report zero, and are let through.
+pub async fn use_passkey<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: UsePasskey,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let id = Uuid::parse_str(&data.id)?;
+    let sql = "update jen.user_passkeys set sign_count=$2, last_used_at=current_timestamp
+               where id=$1 and (sign_count < $2 or ($2 = 0 and sign_count = 0))";
+    let rows = sqlx::query(sql)
+        .bind(id)
+        .bind(data.sign_count)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows > 0)
+}
+
+pub async fn delete_passkey<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: DeletePasskey,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let id = Uuid::parse_str(&data.id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let rows = sqlx::query("delete from jen.user_passkeys where id=$1 and user_id=$2")
+        .bind(id)
+        .bind(user_id)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        auth::webauthn::Ceremony,
+        dto::users::CreateUser,
+        storage::postgres::{self, users},
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_passkeys() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let user_id = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating user");
+        let credential_id = format!("credential-{random_suffix}");
+
+        let id = create_passkey(
+            &mut *txn,
+            CreatePasskey {
+                user_id: user_id.clone(),
+                credential_id: credential_id.clone(),
+                public_key: vec![1, 2, 3],
+                algorithm: -7,
+                sign_count: 0,
+                aaguid: Uuid::nil(),
+                name: "Phone".to_owned(),
+            },
+        )
+        .await
+        .expect("error creating passkey");
+
+        let passkey = get_passkey_by_credential_id(
+            &mut *txn,
+            GetPasskeyByCredentialId {
+                credential_id: credential_id.clone(),
+            },
+        )
+        .await
+        .unwrap()
+        .expect("passkey doesn't exist");
+        assert_eq!(passkey.id.to_string(), id);
+        assert_eq!(passkey.user_id.to_string(), user_id);
+        assert_eq!(passkey.public_key, vec![1, 2, 3]);
+        assert!(passkey.last_used_at.is_none());
+
+        let use_passkey_at = |sign_count: i64| UsePasskey {
+            id: id.clone(),
+            sign_count,
+        };
+        // counters start at zero and only go forward once they've started
+        assert!(use_passkey(&mut *txn, use_passkey_at(0)).await.unwrap());
+        assert!(use_passkey(&mut *txn, use_passkey_at(3)).await.unwrap());
+        assert!(!use_passkey(&mut *txn, use_passkey_at(3)).await.unwrap());
+        assert!(!use_passkey(&mut *txn, use_passkey_at(0)).await.unwrap());
+
+        let passkeys = get_user_passkeys(
+            &mut *txn,
+            GetUserPasskeys {
+                user_id: user_id.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(passkeys.len(), 1);
+        assert_eq!(passkeys[0].sign_count, 3);
+        assert!(passkeys[0].last_used_at.is_some());
+
+        // only the owner can remove a passkey
+        let delete = |user_id: &str| DeletePasskey {
+            id: id.clone(),
+            user_id: user_id.to_owned(),
+        };
+        assert_eq!(
+            delete_passkey(&mut *txn, delete(&Uuid::new_v4().to_string()))
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            delete_passkey(&mut *txn, delete(&user_id)).await.unwrap(),
+            1
+        );
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+
+    #[tokio::test]
+    pub async fn test_passkey_challenges() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let user_id = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating user");
+        let create =
+            |challenge: &str, ceremony: Ceremony, user_id: Option<&str>, expires_in: i64| {
+                CreatePasskeyChallenge {
+                    user_id: user_id.map(str::to_owned),
+                    ceremony,
+                    challenge: format!("{challenge}-{random_suffix}"),
+                    expires_in,
+                }
+            };
+        let use_challenge =
+            |challenge: &str, ceremony: Ceremony, user_id: Option<&str>| UsePasskeyChallenge {
+                challenge: format!("{challenge}-{random_suffix}"),
+                ceremony,
+                user_id: user_id.map(str::to_owned),
+            };
+
+        create_passkey_challenge(
+            &mut *txn,
+            create("register", Ceremony::Registration, Some(&user_id), 60),
+        )
+        .await
+        .expect("error creating challenge");
+        // registration challenges only work for the user they were issued to
+        assert!(!use_passkey_challenge(
+            &mut *txn,
+            use_challenge("register", Ceremony::Registration, None)
+        )
+        .await
+        .unwrap());
+        assert!(!use_passkey_challenge(
+            &mut *txn,
+            use_challenge("register", Ceremony::Authentication, Some(&user_id))
+        )
+        .await
+        .unwrap());
+        assert!(use_passkey_challenge(
+            &mut *txn,
+            use_challenge("register", Ceremony::Registration, Some(&user_id))
+        )
+        .await
+        .unwrap());
+        assert!(!use_passkey_challenge(
+            &mut *txn,
+            use_challenge("register", Ceremony::Registration, Some(&user_id))
+        )
+        .await
+        .unwrap());
+
+        create_passkey_challenge(
+            &mut *txn,
+            create("login", Ceremony::Authentication, None, 60),
+        )
+        .await
+        .expect("error creating challenge");
+        assert!(use_passkey_challenge(
+            &mut *txn,
+            use_challenge("login", Ceremony::Authentication, None)
+        )
+        .await
+        .unwrap());
+
+        create_passkey_challenge(
+            &mut *txn,
+            create("expired", Ceremony::Authentication, None, 0),
+        )
+        .await
+        .expect("error creating challenge");
+        assert!(!use_passkey_challenge(
+            &mut *txn,
+            use_challenge("expired", Ceremony::Authentication, None)
+        )
+        .await
+        .unwrap());
+
+        assert!(delete_expired_passkey_challenges(&mut *txn).await.unwrap() >= 3);
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
@@ -0,0 +1,178 @@
+use sqlx::{Acquire, Executor, Postgres, Transaction};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::dto::auth::{CreatePasswordResetToken, ResetPassword};
+
+/// Store the hash of a new reset token. Any reset tokens the user still had outstanding are used
+/// up, so only the most recently mailed link works.
+pub async fn create_password_reset_token<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: CreatePasswordResetToken,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let mut txn: Transaction<'_, Postgres> = executor.begin().await?;
+
+    sqlx::query(
+        "update jen.password_reset_tokens set used_at=current_timestamp
+         where user_id=$1 and used_at is null",
+    )
+    .bind(user_id)
+    .execute(&mut *txn)
+    .await?;
+
+    let sql = "insert into jen.password_reset_tokens (user_id, token_hash, expires_at)
+               values ($1, $2, current_timestamp + make_interval(secs => $3))
+               returning id";
+    let (id,): (Uuid,) = sqlx::query_as(sql)
+        .bind(user_id)
+        .bind(data.token_hash)
+        .bind(data.expires_in as f64)
+        .fetch_one(&mut *txn)
+        .await?;
+
+    txn.commit().await?;
+    Ok(id.to_string())
+}
+
+/// Use up a reset token and set the new password of the user it was issued to. Returns that
+/// user, or `None` if the token doesn't exist, has expired or was already used.
+pub async fn reset_password<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: ResetPassword,
+) -> Result<Option<Uuid>, Box<dyn Error + Send + Sync>> {
+    let mut txn: Transaction<'_, Postgres> = executor.begin().await?;
+
+    let sql = "update jen.password_reset_tokens set used_at=current_timestamp
+               where token_hash=$1 and used_at is null and expires_at > current_timestamp
+               returning user_id";
+    let user_id: Option<(Uuid,)> = sqlx::query_as(sql)
+        .bind(data.token_hash)
+        .fetch_optional(&mut *txn)
+        .await?;
+    let Some((user_id,)) = user_id else {
+        return Ok(None);
+    };
+
+    // accounts created without a password get their first credential here
+    let updated = sqlx::query(
+        "update jen.user_credentials set credential_hash=$2, alg=$3, pepper_id=$4
+         where user_id=$1",
+    )
+    .bind(user_id)
+    .bind(&data.hashed_password)
+    .bind(&data.algorithm)
+    .bind(&data.pepper_id)
+    .execute(&mut *txn)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        sqlx::query(
+            "insert into jen.user_credentials (user_id, credential_hash, alg, pepper_id)
+             values ($1, $2, $3, $4)",
+        )
+        .bind(user_id)
+        .bind(data.hashed_password)
+        .bind(data.algorithm)
+        .bind(data.pepper_id)
+        .execute(&mut *txn)
+        .await?;
+    }
+
+    txn.commit().await?;
+    Ok(Some(user_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        auth::{credentials::HashCosts, CredentialManager},
+        dto::users::{CreateUser, GetUserByEmail},
+        storage::postgres::{self, users},
+        types::HashAlgorithm,
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_password_reset() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let email = format!("jenny-{random_suffix}@gmail.com");
+        let user_id = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: email.clone(),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating user");
+
+        let manager = CredentialManager::with_costs(HashAlgorithm::Bcrypt, HashCosts::default());
+        let reset = |token: &str, password: &str| ResetPassword {
+            token_hash: format!("{token}-{random_suffix}"),
+            hashed_password: manager.create_hash(password.as_bytes()).unwrap(),
+            algorithm: HashAlgorithm::Bcrypt,
+            pepper_id: None,
+        };
+        let create = |token: &str, expires_in: i64| CreatePasswordResetToken {
+            user_id: user_id.clone(),
+            token_hash: format!("{token}-{random_suffix}"),
+            expires_in,
+        };
+
+        create_password_reset_token(&mut *txn, create("first", 60))
+            .await
+            .expect("error creating reset token");
+        // a newer token makes the first one useless
+        create_password_reset_token(&mut *txn, create("second", 60))
+            .await
+            .expect("error creating reset token");
+
+        let superseded = reset_password(&mut *txn, reset("first", "jennysinha"))
+            .await
+            .expect("error resetting password");
+        assert!(superseded.is_none());
+
+        let reset_for = reset_password(&mut *txn, reset("second", "jennysinha"))
+            .await
+            .expect("error resetting password");
+        assert_eq!(reset_for.map(|u| u.to_string()), Some(user_id.clone()));
+
+        // tokens are single use
+        let reused = reset_password(&mut *txn, reset("second", "hijacked"))
+            .await
+            .expect("error resetting password");
+        assert!(reused.is_none());
+
+        let user = users::get_user_with_credentials_by_email(&mut *txn, GetUserByEmail { email })
+            .await
+            .expect("error getting user")
+            .expect("user has no credentials");
+        assert!(manager.verify_hash("jennysinha", &user.credential_hash, &user.alg, None));
+
+        create_password_reset_token(&mut *txn, create("expired", 0))
+            .await
+            .expect("error creating reset token");
+        let expired = reset_password(&mut *txn, reset("expired", "jennysinha"))
+            .await
+            .expect("error resetting password");
+        assert!(expired.is_none());
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
@@ -0,0 +1,1003 @@
+use sqlx::{postgres::PgRow, Acquire, Executor, FromRow, Postgres, QueryBuilder, Row, Transaction};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::{
+        pagination::{PaginationLimits, PostPaginationOptions},
+        posts::{
+            CreatePost, DeletePost, EditPost, GetAvailablePosts, GetPostById, GetPostRevisionById,
+            GetPostRevisions, GetPostsByUser, GetPublishedPostById, PublishPost,
+            RestorePostRevision, SchedulePost, UnpublishPost,
+        },
+    },
+    entities::posts::{Post, PostRevision},
+    pagination::PaginationContainer,
+    types::AssetVisibility,
+};
+
+#[inline]
+fn visibility(private: bool) -> AssetVisibility {
+    if private {
+        AssetVisibility::Private
+    } else {
+        AssetVisibility::Public
+    }
+}
+
+fn parse_ids(ids: &[String]) -> Result<Vec<Uuid>, uuid::Error> {
+    ids.iter().map(|id| Uuid::parse_str(id)).collect()
+}
+
+/// Attach tags and stickers to a post. This is always called from inside a transaction so that a
+/// post is never left half-attached if one of the inserts fails. Only tags from the post's space,
+/// and only public stickers or the author's own, can be attached; returns false if any of the
+/// requested ones can't be, in which case the caller should roll back.
+async fn attach_tags_and_stickers(
+    txn: &mut Transaction<'_, Postgres>,
+    post_id: Uuid,
+    user_id: Uuid,
+    mut tags: Vec<Uuid>,
+    mut stickers: Vec<Uuid>,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    if !tags.is_empty() {
+        tags.sort_unstable();
+        tags.dedup();
+        let sql = "insert into jen.post_tags (post_id, tag_id)
+                   select p.id, t.id from jen.posts p join jen.tags t on t.space_id=p.space_id
+                   where p.id=$1 and t.id = any($2)";
+        let res = sqlx::query(sql)
+            .bind(post_id)
+            .bind(&tags)
+            .execute(&mut **txn)
+            .await?;
+        if res.rows_affected() != tags.len() as u64 {
+            return Ok(false);
+        }
+    }
+
+    if !stickers.is_empty() {
+        stickers.sort_unstable();
+        stickers.dedup();
+        let sql = "insert into jen.post_stickers (post_id, sticker_id)
+                   select $1, id from jen.stickers
+                   where id = any($2) and (visibility='public' or user_id=$3)";
+        let res = sqlx::query(sql)
+            .bind(post_id)
+            .bind(&stickers)
+            .bind(user_id)
+            .execute(&mut **txn)
+            .await?;
+        if res.rows_affected() != stickers.len() as u64 {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Copy the current title, content and image of a post into jen.post_revisions. Returns the number
+/// of rows copied, which is zero if the post does not exist or does not belong to the user.
+async fn snapshot_post(
+    txn: &mut Transaction<'_, Postgres>,
+    post_id: Uuid,
+    user_id: Uuid,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let sql = "insert into jen.post_revisions (post_id, title, content, image_uri)
+               select id, title, content, image_uri from jen.posts where id=$1 and user_id=$2";
+    let res = sqlx::query(sql)
+        .bind(post_id)
+        .bind(user_id)
+        .execute(&mut **txn)
+        .await?;
+    Ok(res.rows_affected())
+}
+
+pub async fn create_post<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: CreatePost,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let space_id = Uuid::parse_str(&data.space_id)?;
+    let tags = parse_ids(&data.tags)?;
+    let stickers = parse_ids(&data.stickers)?;
+
+    let mut txn = executor.begin().await?;
+
+    let sql = "insert into jen.posts (user_id, space_id, image_uri, title, content, content_html,
+               toc, read_time, word_count, visibility)
+               values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) returning id";
+    let (post_id,): (Uuid,) = sqlx::query_as(sql)
+        .bind(user_id)
+        .bind(space_id)
+        .bind(data.image_uri)
+        .bind(data.title)
+        .bind(data.content)
+        .bind(data.content_html)
+        .bind(data.toc)
+        .bind(data.read_time)
+        .bind(data.word_count)
+        .bind(visibility(data.private))
+        .fetch_one(&mut *txn)
+        .await?;
+
+    if !attach_tags_and_stickers(&mut txn, post_id, user_id, tags, stickers).await? {
+        return Ok(None);
+    }
+
+    txn.commit().await?;
+    Ok(Some(post_id.to_string()))
+}
+
+pub async fn get_post_by_id<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetPostById,
+) -> Result<Option<Post>, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let post = sqlx::query_as!(
+        Post,
+        r#"select id, user_id, space_id, image_uri, title, content, content_html, toc, read_time,
+           word_count, visibility as "visibility!: AssetVisibility", published, published_at, publish_at,
+           array(select tag_id from jen.post_tags where post_id=posts.id) as "tags!",
+           array(select sticker_id from jen.post_stickers where post_id=posts.id) as "stickers!",
+           like_count, exists(select 1 from jen.post_likes where post_id=posts.id and user_id=$2)
+           as "liked!", created_at, updated_at from jen.posts where id=$1 and (user_id=$2 or
+           (published and visibility='public'::jen.asset_visibility))"#,
+        post_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(post)
+}
+
+pub async fn get_published_post_by_id<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetPublishedPostById,
+) -> Result<Option<Post>, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let post = sqlx::query_as!(
+        Post,
+        r#"select id, user_id, space_id, image_uri, title, content, content_html, toc, read_time,
+           word_count, visibility as "visibility!: AssetVisibility", published, published_at, publish_at,
+           array(select tag_id from jen.post_tags where post_id=posts.id) as "tags!",
+           array(select sticker_id from jen.post_stickers where post_id=posts.id) as "stickers!",
+           like_count, false as "liked!", created_at, updated_at from jen.posts where id=$1 and
+           published and
+           visibility='public'::jen.asset_visibility"#,
+        post_id
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(post)
+}
+
+impl<'r> FromRow<'r, PgRow> for Post {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Post {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            space_id: row.try_get("space_id")?,
+            image_uri: row.try_get("image_uri")?,
+            title: row.try_get("title")?,
+            content: row.try_get("content")?,
+            content_html: row.try_get("content_html")?,
+            toc: row.try_get("toc")?,
+            read_time: row.try_get("read_time")?,
+            word_count: row.try_get("word_count")?,
+            // the type check is skipped here because postgres reports the enum without its schema
+            // (asset_visibility) while the rust type is declared as jen.asset_visibility
+            visibility: row.try_get_unchecked("visibility")?,
+            published: row.try_get("published")?,
+            published_at: row.try_get("published_at")?,
+            publish_at: row.try_get("publish_at")?,
+            tags: row.try_get("tags")?,
+            stickers: row.try_get("stickers")?,
+            like_count: row.try_get("like_count")?,
+            liked: row.try_get("liked")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+
+/// Start a listing query over jen.posts. Callers push their own filter right after the `where`
+/// and then hand the builder to [`paginate_posts`]. `viewer` is the user whose likes are reported
+/// in `liked`, if anyone is signed in.
+fn select_posts<'a>(viewer: Option<Uuid>) -> QueryBuilder<'a, Postgres> {
+    let mut query_builder = QueryBuilder::new(
+        "select id, user_id, space_id, image_uri, title, content, content_html, toc, read_time,
+         word_count, visibility, published, published_at, publish_at,
+         array(select tag_id from jen.post_tags where post_id=posts.id) as tags,
+         array(select sticker_id from jen.post_stickers where post_id=posts.id) as stickers,
+         like_count, ",
+    );
+    match viewer {
+        Some(user_id) => query_builder
+            .push("exists(select 1 from jen.post_likes where post_id=posts.id and user_id=")
+            .push_bind(user_id)
+            .push(") as liked"),
+        None => query_builder.push("false as liked"),
+    };
+    query_builder.push(", created_at, updated_at from jen.posts where ");
+    query_builder
+}
+
+async fn paginate_posts<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    mut query_builder: QueryBuilder<'_, Postgres>,
+    order_by: &str,
+    pagination: PaginationLimits<PostPaginationOptions>,
+) -> Result<PaginationContainer<Post>, Box<dyn Error + Send + Sync>> {
+    query_builder.push(" order by ").push(order_by);
+
+    if pagination.opts.asc {
+        query_builder.push(" asc ");
+    } else {
+        query_builder.push(" desc ");
+    };
+
+    let sql = query_builder
+        .push(" limit ")
+        .push_bind(pagination.limit + 1)
+        .push(" offset ")
+        .push_bind(pagination.offset)
+        .build_query_as::<Post>();
+
+    let limit = pagination.limit;
+    let posts = sql.fetch_all(executor).await?;
+
+    Ok(PaginationContainer::new(posts, limit))
+}
+
+pub async fn get_posts<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetAvailablePosts,
+    pagination: PaginationLimits<PostPaginationOptions>,
+) -> Result<PaginationContainer<Post>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let mut query_builder = select_posts(Some(user_id));
+    query_builder
+        .push("(user_id=")
+        .push_bind(user_id)
+        .push(" or (published and visibility='public'::jen.asset_visibility))");
+
+    paginate_posts(executor, query_builder, "created_at", pagination).await
+}
+
+pub async fn get_published_posts<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    pagination: PaginationLimits<PostPaginationOptions>,
+) -> Result<PaginationContainer<Post>, Box<dyn Error + Send + Sync>> {
+    let mut query_builder = select_posts(None);
+    query_builder.push("published and visibility='public'::jen.asset_visibility");
+
+    paginate_posts(executor, query_builder, "published_at", pagination).await
+}
+
+pub async fn get_drafts<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetPostsByUser,
+    pagination: PaginationLimits<PostPaginationOptions>,
+) -> Result<PaginationContainer<Post>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let mut query_builder = select_posts(Some(user_id));
+    query_builder
+        .push("not published and user_id=")
+        .push_bind(user_id);
+
+    paginate_posts(executor, query_builder, "updated_at", pagination).await
+}
+
+pub async fn edit_post<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: EditPost,
+) -> Result<Option<u64>, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let tags = parse_ids(&data.tags)?;
+    let stickers = parse_ids(&data.stickers)?;
+
+    let mut txn = executor.begin().await?;
+
+    snapshot_post(&mut txn, post_id, user_id).await?;
+
+    let sql = "update jen.posts set title=$1, content=$2, content_html=$3, toc=$4, image_uri=$5,
+               read_time=$6, word_count=$7, visibility=$8 where id=$9 and user_id=$10";
+    let res = sqlx::query(sql)
+        .bind(data.title)
+        .bind(data.content)
+        .bind(data.content_html)
+        .bind(data.toc)
+        .bind(data.image_uri)
+        .bind(data.read_time)
+        .bind(data.word_count)
+        .bind(visibility(data.private))
+        .bind(post_id)
+        .bind(user_id)
+        .execute(&mut *txn)
+        .await?;
+
+    // only replace the attachments (and keep the snapshot) if the post actually belongs to the
+    // user. otherwise roll back (implicitly, by dropping the transaction) and let the caller handle
+    // the missing post
+    if res.rows_affected() != 1 {
+        return Ok(Some(res.rows_affected()));
+    }
+
+    sqlx::query("delete from jen.post_tags where post_id=$1")
+        .bind(post_id)
+        .execute(&mut *txn)
+        .await?;
+    sqlx::query("delete from jen.post_stickers where post_id=$1")
+        .bind(post_id)
+        .execute(&mut *txn)
+        .await?;
+
+    if !attach_tags_and_stickers(&mut txn, post_id, user_id, tags, stickers).await? {
+        return Ok(None);
+    }
+
+    txn.commit().await?;
+    Ok(Some(res.rows_affected()))
+}
+
+pub async fn delete_post<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: DeletePost,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "delete from jen.posts where id=$1 and user_id=$2";
+    let res = sqlx::query(sql)
+        .bind(post_id)
+        .bind(user_id)
+        .execute(executor)
+        .await?;
+    Ok(res.rows_affected())
+}
+
+pub async fn publish_post<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: PublishPost,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "update jen.posts set published=true, published_at=current_timestamp, publish_at=null
+               where id=$1 and user_id=$2 and not published";
+    let res = sqlx::query(sql)
+        .bind(post_id)
+        .bind(user_id)
+        .execute(executor)
+        .await?;
+    Ok(res.rows_affected())
+}
+
+pub async fn unpublish_post<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: UnpublishPost,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "update jen.posts set published=false, published_at=null
+               where id=$1 and user_id=$2 and published";
+    let res = sqlx::query(sql)
+        .bind(post_id)
+        .bind(user_id)
+        .execute(executor)
+        .await?;
+    Ok(res.rows_affected())
+}
+
+pub async fn schedule_post<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: SchedulePost,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "update jen.posts set publish_at=$1 where id=$2 and user_id=$3 and not published";
+    let res = sqlx::query(sql)
+        .bind(data.publish_at)
+        .bind(post_id)
+        .bind(user_id)
+        .execute(executor)
+        .await?;
+    Ok(res.rows_affected())
+}
+
+/// Publish every draft whose `publish_at` has passed and return the ids of the posts that were
+/// published. Rows that are already locked by another instance running the same query are skipped
+/// instead of waited on, and a post that was published in the meantime no longer matches the
+/// filter, so no post is ever published twice.
+pub async fn publish_scheduled_posts<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+    let sql = "update jen.posts set published=true, published_at=publish_at, publish_at=null
+               where id in (select id from jen.posts where not published and
+               publish_at <= current_timestamp for update skip locked) returning id";
+    let rows: Vec<(Uuid,)> = sqlx::query_as(sql).fetch_all(executor).await?;
+    Ok(rows.into_iter().map(|(id,)| id.to_string()).collect())
+}
+
+pub async fn get_post_revisions<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetPostRevisions,
+) -> Result<Vec<PostRevision>, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let revisions = sqlx::query_as!(
+        PostRevision,
+        r#"select r.id, r.post_id, r.title, r.content, r.image_uri, r.created_at, r.updated_at
+           from jen.post_revisions r join jen.posts p on p.id=r.post_id and p.id=$1 and
+           p.user_id=$2 order by r.created_at desc"#,
+        post_id,
+        user_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(revisions)
+}
+
+pub async fn get_post_revision<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetPostRevisionById,
+) -> Result<Option<PostRevision>, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let revision_id = Uuid::parse_str(&data.revision_id)?;
+    let revision = sqlx::query_as!(
+        PostRevision,
+        r#"select r.id, r.post_id, r.title, r.content, r.image_uri, r.created_at, r.updated_at
+           from jen.post_revisions r join jen.posts p on p.id=r.post_id and p.id=$1 and
+           p.user_id=$2 and r.id=$3"#,
+        post_id,
+        user_id,
+        revision_id
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(revision)
+}
+
+/// Restore a post to an older revision. The state being replaced is itself saved as a revision
+/// first, so a restore can always be undone.
+pub async fn restore_post_revision<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: RestorePostRevision,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let post_id = Uuid::parse_str(&data.post_id)?;
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let revision_id = Uuid::parse_str(&data.revision_id)?;
+
+    let mut txn = executor.begin().await?;
+
+    if snapshot_post(&mut txn, post_id, user_id).await? != 1 {
+        return Ok(0);
+    }
+
+    let sql = "update jen.posts set title=r.title, content=r.content, content_html=$4, toc=$5,
+               read_time=$6, word_count=$7, image_uri=r.image_uri from jen.post_revisions r
+               where posts.id=$1 and posts.user_id=$2 and r.id=$3 and r.post_id=posts.id";
+    let res = sqlx::query(sql)
+        .bind(post_id)
+        .bind(user_id)
+        .bind(revision_id)
+        .bind(data.content_html)
+        .bind(data.toc)
+        .bind(data.read_time)
+        .bind(data.word_count)
+        .execute(&mut *txn)
+        .await?;
+
+    if res.rows_affected() != 1 {
+        return Ok(res.rows_affected());
+    }
+
+    txn.commit().await?;
+    Ok(res.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::{
+            spaces::CreateSpace,
+            stickers::{CreateSticker, CreateStickers},
+            tags::CreateTag,
+            users::{CreateUser, DeleteUser},
+        },
+        storage::postgres::{self, spaces, stickers, users},
+        types::AssetBackend,
+        util,
+    };
+
+    use chrono::Utc;
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_posts() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let author = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating author");
+
+        let reader = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Anish".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("anish-{random_suffix}@gmail.com"),
+                username: format!("anish-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/anish".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating reader");
+
+        let space_id = spaces::create_space(
+            &mut *txn,
+            CreateSpace {
+                space_name: format!("Books {random_suffix}"),
+                bio: format!("All about books {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating space");
+
+        let (tag_id, _) = spaces::create_tag(
+            &mut *txn,
+            CreateTag {
+                space_id: space_id.clone(),
+                name: format!("mystery {random_suffix}"),
+                description: format!("Whodunits {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating tag");
+
+        let other_space_id = spaces::create_space(
+            &mut *txn,
+            CreateSpace {
+                space_name: format!("Films {random_suffix}"),
+                bio: format!("All about films {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating space");
+
+        let (other_tag_id, _) = spaces::create_tag(
+            &mut *txn,
+            CreateTag {
+                space_id: other_space_id,
+                name: format!("noir {random_suffix}"),
+                description: format!("Shadows {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating tag");
+
+        let sticker = |visibility: AssetVisibility, name: &str| CreateSticker {
+            visibility,
+            friendly_name: name.to_owned(),
+            file_path: format!("stickers/{name}-{random_suffix}.png"),
+            backend: AssetBackend::Fs,
+        };
+        let own = stickers::create_stickers(
+            &mut *txn,
+            CreateStickers {
+                user_id: author.clone(),
+                stickers: vec![sticker(AssetVisibility::Private, "magnifier")],
+            },
+        )
+        .await
+        .expect("error creating stickers");
+        let foreign = stickers::create_stickers(
+            &mut *txn,
+            CreateStickers {
+                user_id: reader.clone(),
+                stickers: vec![
+                    sticker(AssetVisibility::Public, "turtle"),
+                    sticker(AssetVisibility::Private, "bomb"),
+                ],
+            },
+        )
+        .await
+        .expect("error creating stickers");
+        let (own_private, public, foreign_private) =
+            (own[0].clone(), foreign[0].clone(), foreign[1].clone());
+
+        let new_post = |tags: Vec<String>, stickers: Vec<String>| CreatePost {
+            user_id: author.clone(),
+            space_id: space_id.clone(),
+            title: "The Westing Game".to_owned(),
+            content: "Sixteen heirs, one fortune.".to_owned(),
+            content_html: "".to_owned(),
+            toc: serde_json::json!([]),
+            image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+            private: true,
+            tags,
+            stickers,
+            read_time: 1,
+            word_count: 4,
+        };
+
+        // someone else's private sticker and stickers that don't exist can't be attached
+        let rejected = create_post(&mut *txn, new_post(vec![], vec![foreign_private.clone()]))
+            .await
+            .expect("error creating post");
+        assert!(rejected.is_none());
+        let rejected = create_post(
+            &mut *txn,
+            new_post(vec![], vec![Uuid::new_v4().to_string()]),
+        )
+        .await
+        .expect("error creating post");
+        assert!(rejected.is_none());
+        // nor can tags from another space
+        let rejected = create_post(&mut *txn, new_post(vec![other_tag_id], vec![]))
+            .await
+            .expect("error creating post");
+        assert!(rejected.is_none());
+
+        let post_id = create_post(
+            &mut *txn,
+            CreatePost {
+                user_id: author.clone(),
+                space_id: space_id.clone(),
+                title: "The Westing Game".to_owned(),
+                content: "Sixteen heirs, one fortune.".to_owned(),
+                content_html: "".to_owned(),
+                toc: serde_json::json!([]),
+                image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+                private: true,
+                tags: vec![tag_id.clone(), tag_id.clone()],
+                stickers: vec![],
+                read_time: 1,
+                word_count: 4,
+            },
+        )
+        .await
+        .expect("error creating post")
+        .expect("post is unexpectedly none");
+
+        let post = get_post_by_id(
+            &mut *txn,
+            GetPostById {
+                post_id: post_id.clone(),
+                user_id: author.clone(),
+            },
+        )
+        .await
+        .expect("error fetching post")
+        .expect("post is unexpectedly none");
+
+        // the repeated tag is only attached once
+        assert_eq!(post.tags.len(), 1);
+        assert_eq!(post.tags[0].to_string(), tag_id);
+
+        // private posts must not be visible to anyone but their author
+        let hidden = get_post_by_id(
+            &mut *txn,
+            GetPostById {
+                post_id: post_id.clone(),
+                user_id: reader.clone(),
+            },
+        )
+        .await
+        .expect("error fetching post");
+        assert!(hidden.is_none());
+
+        let not_edited = edit_post(
+            &mut *txn,
+            EditPost {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+                title: "Hijacked".to_owned(),
+                private: false,
+                tags: vec![],
+                stickers: vec![],
+                content: "".to_owned(),
+                content_html: "".to_owned(),
+                toc: serde_json::json!([]),
+                image_uri: "".to_owned(),
+                read_time: 1,
+                word_count: 0,
+            },
+        )
+        .await
+        .expect("error editing post");
+        assert_eq!(not_edited, Some(0));
+
+        let bad_stickers = edit_post(
+            &mut *txn,
+            EditPost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                title: "The Westing Game".to_owned(),
+                private: false,
+                tags: vec![],
+                stickers: vec![public.clone(), foreign_private],
+                content: "Sixteen heirs, one fortune.".to_owned(),
+                content_html: "".to_owned(),
+                toc: serde_json::json!([]),
+                image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+                read_time: 1,
+                word_count: 4,
+            },
+        )
+        .await
+        .expect("error editing post");
+        assert!(bad_stickers.is_none());
+
+        let edited = edit_post(
+            &mut *txn,
+            EditPost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                title: "The Westing Game".to_owned(),
+                private: false,
+                tags: vec![],
+                stickers: vec![public, own_private],
+                content: "Sixteen heirs, one fortune, and a bomber.".to_owned(),
+                content_html: "".to_owned(),
+                toc: serde_json::json!([]),
+                image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+                read_time: 1,
+                word_count: 7,
+            },
+        )
+        .await
+        .expect("error editing post");
+        assert_eq!(edited, Some(1));
+
+        let revisions = get_post_revisions(
+            &mut *txn,
+            GetPostRevisions {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error fetching revisions");
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].content, "Sixteen heirs, one fortune.");
+
+        // the failed edit by the reader must not have left a revision behind, and the reader
+        // cannot see the author's revisions
+        let foreign = get_post_revisions(
+            &mut *txn,
+            GetPostRevisions {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error fetching revisions");
+        assert!(foreign.is_empty());
+
+        let restored = restore_post_revision(
+            &mut *txn,
+            RestorePostRevision {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                revision_id: revisions[0].id.to_string(),
+                content_html: "".to_owned(),
+                toc: serde_json::json!([]),
+                read_time: 2,
+                word_count: 4,
+            },
+        )
+        .await
+        .expect("error restoring revision");
+        assert_eq!(restored, 1);
+
+        let post = get_post_by_id(
+            &mut *txn,
+            GetPostById {
+                post_id: post_id.clone(),
+                user_id: author.clone(),
+            },
+        )
+        .await
+        .expect("error fetching post")
+        .expect("post is unexpectedly none");
+        assert_eq!(post.content, "Sixteen heirs, one fortune.");
+        assert_eq!(post.stickers.len(), 2);
+
+        let revisions = get_post_revisions(
+            &mut *txn,
+            GetPostRevisions {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error fetching revisions");
+        assert_eq!(revisions.len(), 2);
+
+        // public drafts are still only visible to their author
+        let hidden = get_post_by_id(
+            &mut *txn,
+            GetPostById {
+                post_id: post_id.clone(),
+                user_id: reader.clone(),
+            },
+        )
+        .await
+        .expect("error fetching post");
+        assert!(hidden.is_none());
+
+        let drafts = get_drafts(
+            &mut *txn,
+            GetPostsByUser {
+                user_id: author.clone(),
+            },
+            PaginationLimits {
+                offset: 0,
+                limit: 10,
+                opts: PostPaginationOptions { asc: false },
+            },
+        )
+        .await
+        .expect("error fetching drafts");
+        assert_eq!(drafts.items.len(), 1);
+
+        let mut scheduled = schedule_post(
+            &mut *txn,
+            SchedulePost {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+                publish_at: Some(Utc::now()),
+            },
+        )
+        .await
+        .expect("error scheduling post");
+        assert_eq!(scheduled, 0);
+
+        scheduled = schedule_post(
+            &mut *txn,
+            SchedulePost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                publish_at: Some(Utc::now() - chrono::Duration::minutes(1)),
+            },
+        )
+        .await
+        .expect("error scheduling post");
+        assert_eq!(scheduled, 1);
+
+        let due = publish_scheduled_posts(&mut *txn)
+            .await
+            .expect("error publishing scheduled posts");
+        assert!(due.contains(&post_id));
+
+        // a second pass (or a second instance) must not publish the same post again
+        let due = publish_scheduled_posts(&mut *txn)
+            .await
+            .expect("error publishing scheduled posts");
+        assert!(!due.contains(&post_id));
+
+        let unpublished = unpublish_post(
+            &mut *txn,
+            UnpublishPost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error unpublishing post");
+        assert_eq!(unpublished, 1);
+
+        let published = publish_post(
+            &mut *txn,
+            PublishPost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error publishing post");
+        assert_eq!(published, 1);
+
+        let anonymous = get_published_post_by_id(
+            &mut *txn,
+            GetPublishedPostById {
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error fetching published post")
+        .expect("published post is unexpectedly none");
+        assert!(anonymous.published_at.is_some());
+
+        let posts = get_posts(
+            &mut *txn,
+            GetAvailablePosts {
+                user_id: reader.clone(),
+            },
+            PaginationLimits {
+                offset: 0,
+                limit: 10,
+                opts: PostPaginationOptions { asc: false },
+            },
+        )
+        .await
+        .expect("error fetching posts");
+
+        let visible = posts
+            .items
+            .iter()
+            .find(|p| p.id.to_string() == post_id)
+            .expect("published post should be visible to readers");
+        assert!(visible.tags.is_empty());
+        assert_eq!(visible.read_time, 2);
+        assert_eq!(visible.word_count, 4);
+
+        let unpublished = unpublish_post(
+            &mut *txn,
+            UnpublishPost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error unpublishing post");
+        assert_eq!(unpublished, 1);
+
+        let published_posts = get_published_posts(
+            &mut *txn,
+            PaginationLimits {
+                offset: 0,
+                limit: 10,
+                opts: PostPaginationOptions { asc: false },
+            },
+        )
+        .await
+        .expect("error fetching published posts");
+        assert!(published_posts
+            .items
+            .iter()
+            .all(|p| p.id.to_string() != post_id));
+
+        let deleted = delete_post(
+            &mut *txn,
+            DeletePost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error deleting post");
+        assert_eq!(deleted, 1);
+
+        users::delete_user(&mut *txn, DeleteUser { id: author })
+            .await
+            .expect("error deleting author");
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
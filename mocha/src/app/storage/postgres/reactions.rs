@@ -0,0 +1,640 @@
+use sqlx::{postgres::PgRow, Acquire, Executor, FromRow, Postgres, QueryBuilder, Row};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::{
+        pagination::{PaginationLimits, ReactionPaginationOptions},
+        reactions::{
+            DeleteCommentReaction, DeletePostReaction, GetCommentReactions, GetCommentReactors,
+            GetPostReactions, GetPostReactors, ReactToComment, ReactToPost,
+        },
+    },
+    entities::reactions::{Reaction, Reactor},
+    pagination::PaginationContainer,
+};
+
+/// What a reaction is attached to. Posts and comments keep their reactions in separate tables but
+/// are otherwise handled the same way.
+enum Target {
+    Post { post_id: Uuid },
+    Comment { post_id: Uuid, comment_id: Uuid },
+}
+
+impl Target {
+    fn table(&self) -> &'static str {
+        match self {
+            Target::Post { .. } => "jen.post_reactions",
+            Target::Comment { .. } => "jen.comment_reactions",
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            Target::Post { .. } => "post_id",
+            Target::Comment { .. } => "comment_id",
+        }
+    }
+
+    fn id(&self) -> Uuid {
+        match self {
+            Target::Post { post_id } => *post_id,
+            Target::Comment { comment_id, .. } => *comment_id,
+        }
+    }
+
+    /// Push a subquery selecting the target's id if `user_id` can see it. Reacting is stricter
+    /// than reading: the post has to be published and a comment has to be approved and not
+    /// deleted.
+    fn push_visible(&self, builder: &mut QueryBuilder<'_, Postgres>, user_id: Uuid, react: bool) {
+        match self {
+            Target::Post { post_id } => {
+                builder
+                    .push("select p.id from jen.posts p where p.id=")
+                    .push_bind(*post_id);
+            }
+            Target::Comment {
+                post_id,
+                comment_id,
+            } => {
+                builder
+                    .push("select c.id from jen.comments c join jen.posts p on p.id=c.post_id where c.id=")
+                    .push_bind(*comment_id)
+                    .push(" and c.post_id=")
+                    .push_bind(*post_id);
+                if react {
+                    builder.push(" and c.deleted_at is null and c.status='approved'");
+                } else {
+                    builder
+                        .push(" and (c.status='approved' or c.user_id=")
+                        .push_bind(user_id)
+                        .push(")");
+                }
+            }
+        }
+        if react {
+            builder
+                .push(" and p.published and (p.visibility='public' or p.user_id=")
+                .push_bind(user_id)
+                .push(")");
+        } else {
+            builder
+                .push(" and (p.user_id=")
+                .push_bind(user_id)
+                .push(" or (p.published and p.visibility='public'))");
+        }
+    }
+}
+
+/// The same rule as `get_available_stickers`: public stickers and the user's own private ones
+fn push_sticker_available(builder: &mut QueryBuilder<'_, Postgres>, user_id: Uuid) {
+    builder
+        .push("(s.visibility='public'::jen.asset_visibility or s.user_id=")
+        .push_bind(user_id)
+        .push(")");
+}
+
+impl<'r> FromRow<'r, PgRow> for Reaction {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Reaction {
+            sticker_id: row.try_get("sticker_id")?,
+            friendly_name: row.try_get("friendly_name")?,
+            file_path: row.try_get("file_path")?,
+            // see the note on Post's FromRow about enums and their schema
+            backend: row.try_get_unchecked("backend")?,
+            count: row.try_get("count")?,
+            reacted: row.try_get("reacted")?,
+        })
+    }
+}
+
+/// Returns `false` if the target or the sticker isn't available to the user. Reacting twice with
+/// the same sticker is a no-op.
+async fn react<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    target: Target,
+    user_id: Uuid,
+    sticker_id: Uuid,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("with target as (");
+    target.push_visible(&mut builder, user_id, true);
+    builder
+        .push("), sticker as (select s.id from jen.stickers s where s.id=")
+        .push_bind(sticker_id)
+        .push(" and ");
+    push_sticker_available(&mut builder, user_id);
+    builder.push(format!(
+        "), inserted as (insert into {} ({}, user_id, sticker_id) select target.id, ",
+        target.table(),
+        target.column()
+    ));
+    builder.push_bind(user_id).push(
+        ", sticker.id from target, sticker on conflict do nothing)
+             select exists(select 1 from target) and exists(select 1 from sticker)",
+    );
+
+    let (allowed,): (bool,) = builder.build_query_as().fetch_one(executor).await?;
+    Ok(allowed)
+}
+
+async fn delete_reaction<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    target: Target,
+    user_id: Uuid,
+    sticker_id: Uuid,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+        "delete from {} where {}=",
+        target.table(),
+        target.column()
+    ));
+    builder
+        .push_bind(target.id())
+        .push(" and user_id=")
+        .push_bind(user_id)
+        .push(" and sticker_id=")
+        .push_bind(sticker_id);
+
+    let res = builder.build().execute(executor).await?;
+    Ok(res.rows_affected())
+}
+
+/// Per-sticker counts for a post or comment. Reactions made with a sticker the user can't see are
+/// left out entirely so private stickers never show up for anyone but their owner.
+async fn get_reactions<'a>(
+    executor: impl Acquire<'a, Database = Postgres>,
+    target: Target,
+    user_id: Uuid,
+) -> Result<Option<Vec<Reaction>>, Box<dyn Error + Send + Sync>> {
+    let mut conn = executor.acquire().await?;
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("select exists(");
+    target.push_visible(&mut builder, user_id, false);
+    builder.push(")");
+    let (visible,): (bool,) = builder.build_query_as().fetch_one(&mut *conn).await?;
+    if !visible {
+        return Ok(None);
+    }
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "select s.id as sticker_id, s.friendly_name, a.file_path, a.backend,
+         count(*) as count, bool_or(r.user_id=",
+    );
+    builder
+        .push_bind(user_id)
+        .push(format!(
+            ") as reacted from {} r
+             join jen.stickers s on s.id=r.sticker_id
+             join jen.assets a on a.id=s.asset_id
+             where r.{}=",
+            target.table(),
+            target.column()
+        ))
+        .push_bind(target.id())
+        .push(" and ");
+    push_sticker_available(&mut builder, user_id);
+    builder.push(" group by s.id, a.id order by count desc, s.id");
+
+    let reactions = builder
+        .build_query_as::<Reaction>()
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(Some(reactions))
+}
+
+/// Everyone who reacted to a post or comment with one sticker. Returns `None` if the user can't
+/// see the target or the sticker.
+async fn get_reactors<'a>(
+    executor: impl Acquire<'a, Database = Postgres>,
+    target: Target,
+    user_id: Uuid,
+    sticker_id: Uuid,
+    pagination: PaginationLimits<ReactionPaginationOptions>,
+) -> Result<Option<PaginationContainer<Reactor>>, Box<dyn Error + Send + Sync>> {
+    let mut conn = executor.acquire().await?;
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("select exists(");
+    target.push_visible(&mut builder, user_id, false);
+    builder
+        .push(") and exists(select 1 from jen.stickers s where s.id=")
+        .push_bind(sticker_id)
+        .push(" and ");
+    push_sticker_available(&mut builder, user_id);
+    builder.push(")");
+    let (visible,): (bool,) = builder.build_query_as().fetch_one(&mut *conn).await?;
+    if !visible {
+        return Ok(None);
+    }
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+        "select u.id as user_id, u.username, u.first_name, u.last_name, u.image_uri,
+         r.created_at as reacted_at
+         from {} r join jen.users u on u.id=r.user_id
+         where r.{}=",
+        target.table(),
+        target.column()
+    ));
+    builder
+        .push_bind(target.id())
+        .push(" and r.sticker_id=")
+        .push_bind(sticker_id)
+        .push(" order by r.created_at ");
+    if pagination.opts.asc {
+        builder.push("asc");
+    } else {
+        builder.push("desc");
+    }
+    builder
+        .push(", u.id offset ")
+        .push_bind(pagination.offset)
+        .push(" limit ")
+        .push_bind(pagination.limit + 1);
+
+    let reactors = builder
+        .build_query_as::<Reactor>()
+        .fetch_all(&mut *conn)
+        .await?;
+    Ok(Some(PaginationContainer::new(reactors, pagination.limit)))
+}
+
+pub async fn react_to_post<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: ReactToPost,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let target = Target::Post {
+        post_id: Uuid::parse_str(&data.post_id)?,
+    };
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sticker_id = Uuid::parse_str(&data.sticker_id)?;
+    react(executor, target, user_id, sticker_id).await
+}
+
+pub async fn delete_post_reaction<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: DeletePostReaction,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let target = Target::Post {
+        post_id: Uuid::parse_str(&data.post_id)?,
+    };
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sticker_id = Uuid::parse_str(&data.sticker_id)?;
+    delete_reaction(executor, target, user_id, sticker_id).await
+}
+
+pub async fn get_post_reactions<'a>(
+    executor: impl Acquire<'a, Database = Postgres>,
+    data: GetPostReactions,
+) -> Result<Option<Vec<Reaction>>, Box<dyn Error + Send + Sync>> {
+    let target = Target::Post {
+        post_id: Uuid::parse_str(&data.post_id)?,
+    };
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    get_reactions(executor, target, user_id).await
+}
+
+pub async fn get_post_reactors<'a>(
+    executor: impl Acquire<'a, Database = Postgres>,
+    data: GetPostReactors,
+    pagination: PaginationLimits<ReactionPaginationOptions>,
+) -> Result<Option<PaginationContainer<Reactor>>, Box<dyn Error + Send + Sync>> {
+    let target = Target::Post {
+        post_id: Uuid::parse_str(&data.post_id)?,
+    };
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sticker_id = Uuid::parse_str(&data.sticker_id)?;
+    get_reactors(executor, target, user_id, sticker_id, pagination).await
+}
+
+pub async fn react_to_comment<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: ReactToComment,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let target = Target::Comment {
+        post_id: Uuid::parse_str(&data.post_id)?,
+        comment_id: Uuid::parse_str(&data.comment_id)?,
+    };
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sticker_id = Uuid::parse_str(&data.sticker_id)?;
+    react(executor, target, user_id, sticker_id).await
+}
+
+pub async fn delete_comment_reaction<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: DeleteCommentReaction,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let target = Target::Comment {
+        post_id: Uuid::parse_str(&data.post_id)?,
+        comment_id: Uuid::parse_str(&data.comment_id)?,
+    };
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sticker_id = Uuid::parse_str(&data.sticker_id)?;
+    delete_reaction(executor, target, user_id, sticker_id).await
+}
+
+pub async fn get_comment_reactions<'a>(
+    executor: impl Acquire<'a, Database = Postgres>,
+    data: GetCommentReactions,
+) -> Result<Option<Vec<Reaction>>, Box<dyn Error + Send + Sync>> {
+    let target = Target::Comment {
+        post_id: Uuid::parse_str(&data.post_id)?,
+        comment_id: Uuid::parse_str(&data.comment_id)?,
+    };
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    get_reactions(executor, target, user_id).await
+}
+
+pub async fn get_comment_reactors<'a>(
+    executor: impl Acquire<'a, Database = Postgres>,
+    data: GetCommentReactors,
+    pagination: PaginationLimits<ReactionPaginationOptions>,
+) -> Result<Option<PaginationContainer<Reactor>>, Box<dyn Error + Send + Sync>> {
+    let target = Target::Comment {
+        post_id: Uuid::parse_str(&data.post_id)?,
+        comment_id: Uuid::parse_str(&data.comment_id)?,
+    };
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sticker_id = Uuid::parse_str(&data.sticker_id)?;
+    get_reactors(executor, target, user_id, sticker_id, pagination).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::{
+            comments::CreateComment,
+            posts::{CreatePost, PublishPost},
+            spaces::CreateSpace,
+            stickers::{CreateSticker, CreateStickers},
+            users::CreateUser,
+        },
+        storage::postgres::{self, comments, posts, spaces, stickers, users},
+        types::{AssetBackend, AssetVisibility},
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_reactions() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let author = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating author");
+
+        let reader = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Anish".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("anish-{random_suffix}@gmail.com"),
+                username: format!("anish-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/anish".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating reader");
+
+        let sticker = |visibility: AssetVisibility, name: &str| CreateSticker {
+            visibility,
+            friendly_name: name.to_owned(),
+            file_path: format!("stickers/{name}-{random_suffix}.png"),
+            backend: AssetBackend::Fs,
+        };
+        let sticker_ids = stickers::create_stickers(
+            &mut *txn,
+            CreateStickers {
+                user_id: author.clone(),
+                stickers: vec![
+                    sticker(AssetVisibility::Public, "turtle"),
+                    sticker(AssetVisibility::Private, "bomb"),
+                ],
+            },
+        )
+        .await
+        .expect("error creating stickers");
+        let (public, private) = (sticker_ids[0].clone(), sticker_ids[1].clone());
+
+        let space_id = spaces::create_space(
+            &mut *txn,
+            CreateSpace {
+                space_name: format!("Books {random_suffix}"),
+                bio: format!("All about books {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating space");
+
+        let post_id = posts::create_post(
+            &mut *txn,
+            CreatePost {
+                user_id: author.clone(),
+                space_id: space_id.clone(),
+                title: "The Westing Game".to_owned(),
+                content: "Sixteen heirs, one fortune.".to_owned(),
+                content_html: "".to_owned(),
+                toc: serde_json::json!([]),
+                image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+                private: false,
+                tags: vec![],
+                stickers: vec![],
+                read_time: 1,
+                word_count: 4,
+            },
+        )
+        .await
+        .expect("error creating post")
+        .expect("post is unexpectedly none");
+
+        let react = |user_id: &str, sticker_id: &str| ReactToPost {
+            user_id: user_id.to_owned(),
+            post_id: post_id.clone(),
+            sticker_id: sticker_id.to_owned(),
+        };
+
+        // drafts can't be reacted to
+        let on_draft = react_to_post(&mut *txn, react(&reader, &public))
+            .await
+            .expect("error reacting to post");
+        assert!(!on_draft);
+
+        posts::publish_post(
+            &mut *txn,
+            PublishPost {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+            },
+        )
+        .await
+        .expect("error publishing post");
+
+        for _ in 0..2 {
+            let reacted = react_to_post(&mut *txn, react(&reader, &public))
+                .await
+                .expect("error reacting to post");
+            assert!(reacted);
+        }
+        // someone else's private sticker isn't available to react with
+        let reacted = react_to_post(&mut *txn, react(&reader, &private))
+            .await
+            .expect("error reacting to post");
+        assert!(!reacted);
+
+        for sticker_id in [&public, &private] {
+            let reacted = react_to_post(&mut *txn, react(&author, sticker_id))
+                .await
+                .expect("error reacting to post");
+            assert!(reacted);
+        }
+
+        let get_reactions = |user_id: &str| GetPostReactions {
+            user_id: user_id.to_owned(),
+            post_id: post_id.clone(),
+        };
+
+        let seen_by_reader = get_post_reactions(&mut *txn, get_reactions(&reader))
+            .await
+            .expect("error fetching reactions")
+            .expect("post should be visible");
+        assert_eq!(seen_by_reader.len(), 1);
+        assert_eq!(seen_by_reader[0].sticker_id.to_string(), public);
+        assert_eq!(seen_by_reader[0].count, 2);
+        assert!(seen_by_reader[0].reacted);
+
+        let seen_by_author = get_post_reactions(&mut *txn, get_reactions(&author))
+            .await
+            .expect("error fetching reactions")
+            .expect("post should be visible");
+        assert_eq!(seen_by_author.len(), 2);
+
+        let pagination = || PaginationLimits {
+            offset: 0,
+            limit: 10,
+            opts: ReactionPaginationOptions { asc: true },
+        };
+        let get_reactors = |user_id: &str, sticker_id: &str| GetPostReactors {
+            user_id: user_id.to_owned(),
+            post_id: post_id.clone(),
+            sticker_id: sticker_id.to_owned(),
+        };
+
+        let reactors = get_post_reactors(&mut *txn, get_reactors(&reader, &public), pagination())
+            .await
+            .expect("error fetching reactors")
+            .expect("sticker should be visible");
+        assert_eq!(reactors.items.len(), 2);
+        assert!(reactors.done);
+
+        // the private sticker doesn't leak through the reactor listing either
+        let hidden = get_post_reactors(&mut *txn, get_reactors(&reader, &private), pagination())
+            .await
+            .expect("error fetching reactors");
+        assert!(hidden.is_none());
+
+        let deleted = delete_post_reaction(
+            &mut *txn,
+            DeletePostReaction {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+                sticker_id: public.clone(),
+            },
+        )
+        .await
+        .expect("error deleting reaction");
+        assert_eq!(deleted, 1);
+
+        let comment_id = comments::create_comment(
+            &mut *txn,
+            CreateComment {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                parent_id: None,
+                content: "Turtle knows.".to_owned(),
+                spam: false,
+            },
+        )
+        .await
+        .expect("error creating comment")
+        .expect("comment should have been created")
+        .0;
+
+        let reacted = react_to_comment(
+            &mut *txn,
+            ReactToComment {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+                comment_id: comment_id.clone(),
+                sticker_id: public.clone(),
+            },
+        )
+        .await
+        .expect("error reacting to comment");
+        assert!(reacted);
+
+        let on_comment = get_comment_reactions(
+            &mut *txn,
+            GetCommentReactions {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                comment_id: comment_id.clone(),
+            },
+        )
+        .await
+        .expect("error fetching reactions")
+        .expect("comment should be visible");
+        assert_eq!(on_comment.len(), 1);
+        assert_eq!(on_comment[0].count, 1);
+        assert!(!on_comment[0].reacted);
+
+        let reactors = get_comment_reactors(
+            &mut *txn,
+            GetCommentReactors {
+                user_id: author.clone(),
+                post_id: post_id.clone(),
+                comment_id: comment_id.clone(),
+                sticker_id: public.clone(),
+            },
+            pagination(),
+        )
+        .await
+        .expect("error fetching reactors")
+        .expect("sticker should be visible");
+        assert_eq!(reactors.items[0].user_id.to_string(), reader);
+
+        let deleted = delete_comment_reaction(
+            &mut *txn,
+            DeleteCommentReaction {
+                user_id: reader.clone(),
+                post_id: post_id.clone(),
+                comment_id,
+                sticker_id: public,
+            },
+        )
+        .await
+        .expect("error deleting reaction");
+        assert_eq!(deleted, 1);
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
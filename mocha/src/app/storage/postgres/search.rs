@@ -0,0 +1,301 @@
+use sqlx::{Executor, Postgres, QueryBuilder};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::{
+        pagination::{PaginationLimits, SearchPaginationOptions},
+        search::Search,
+    },
+    entities::search::SearchResult,
+    pagination::PaginationContainer,
+};
+
+const HIGHLIGHT_START: &str = "<mark>";
+const HIGHLIGHT_STOP: &str = "</mark>";
+
+/// Options for `ts_headline`. Fragments are separated by an ellipsis and kept short enough to fit
+/// in a result card.
+const HEADLINE_OPTIONS: &str =
+    "StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=30, MinWords=10, FragmentDelimiter=\" … \"";
+
+/// `ts_headline` drops anything that parses as a tag but passes the rest of the raw text through,
+/// stray `<` and `&` included. Escape everything and then bring back only the highlight markers.
+fn escape_snippet(snippet: &str) -> String {
+    let mut escaped = String::with_capacity(snippet.len());
+    // writing into a string cannot fail
+    let _ = pulldown_cmark::escape::escape_html(&mut escaped, snippet);
+    escaped
+        .replace("&lt;mark&gt;", HIGHLIGHT_START)
+        .replace("&lt;/mark&gt;", HIGHLIGHT_STOP)
+}
+
+/// Search published public posts, spaces and tags in one ranked list. Filtering by tag only
+/// returns posts carrying that tag and the tag itself, since spaces are never tagged.
+pub async fn search<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: Search,
+    pagination: PaginationLimits<SearchPaginationOptions>,
+) -> Result<PaginationContainer<SearchResult>, Box<dyn Error + Send + Sync>> {
+    let space_id = pagination
+        .opts
+        .space_id
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()?;
+    let tag_id = pagination
+        .opts
+        .tag_id
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()?;
+
+    let mut builder: QueryBuilder<Postgres> =
+        QueryBuilder::new("with q as (select websearch_to_tsquery('english', ");
+    builder.push_bind(data.query);
+    builder.push(") as query), o as (select ");
+    builder.push_bind(HEADLINE_OPTIONS);
+    builder.push(" as options) ");
+
+    builder.push(
+        "select 'post' as kind, p.id, p.space_id, p.title,
+         ts_headline('english', p.content, q.query, o.options) as snippet,
+         ts_rank(p.search, q.query) as rank
+         from jen.posts p, q, o
+         where p.search @@ q.query and p.published and p.visibility='public'",
+    );
+    if let Some(space_id) = space_id {
+        builder.push(" and p.space_id=").push_bind(space_id);
+    }
+    if let Some(tag_id) = tag_id {
+        builder
+            .push(
+                " and exists (select 1 from jen.post_tags pt where pt.post_id=p.id and pt.tag_id=",
+            )
+            .push_bind(tag_id)
+            .push(")");
+    }
+
+    if tag_id.is_none() {
+        builder.push(
+            " union all
+             select 'space' as kind, s.id, s.id as space_id, s.space_name as title,
+             ts_headline('english', s.bio, q.query, o.options) as snippet,
+             ts_rank(s.search, q.query) as rank
+             from jen.spaces s, q, o
+             where s.search @@ q.query",
+        );
+        if let Some(space_id) = space_id {
+            builder.push(" and s.id=").push_bind(space_id);
+        }
+    }
+
+    builder.push(
+        " union all
+         select 'tag' as kind, t.id, t.space_id, t.tag_name as title,
+         ts_headline('english', t.tag_description, q.query, o.options) as snippet,
+         ts_rank(t.search, q.query) as rank
+         from jen.tags t, q, o
+         where t.search @@ q.query",
+    );
+    if let Some(space_id) = space_id {
+        builder.push(" and t.space_id=").push_bind(space_id);
+    }
+    if let Some(tag_id) = tag_id {
+        builder.push(" and t.id=").push_bind(tag_id);
+    }
+
+    builder
+        .push(" order by rank desc, id offset ")
+        .push_bind(pagination.offset)
+        .push(" limit ")
+        .push_bind(pagination.limit + 1);
+
+    let limit = pagination.limit;
+    let results: Vec<SearchResult> = builder
+        .build_query_as::<SearchResult>()
+        .fetch_all(executor)
+        .await?
+        .into_iter()
+        .map(|result| SearchResult {
+            snippet: escape_snippet(&result.snippet),
+            ..result
+        })
+        .collect();
+
+    Ok(PaginationContainer::new(results, limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::{
+            pagination::{PaginationLimits, SearchPaginationOptions},
+            posts::CreatePost,
+            spaces::CreateSpace,
+            tags::CreateTag,
+            users::CreateUser,
+        },
+        entities::search::SearchResultKind,
+        storage::postgres::{self, posts, spaces, users},
+        util,
+    };
+
+    use super::*;
+
+    #[test]
+    pub fn test_escape_snippet() {
+        assert_eq!(
+            escape_snippet("<script>alert(1)</script> a <mark>bomb</mark>"),
+            "&lt;script&gt;alert(1)&lt;/script&gt; a <mark>bomb</mark>"
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_search() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+        // a made up word so other rows in the database can't match
+        let keyword = format!("turtlewexler{}", random_suffix.to_lowercase());
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let author = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating author");
+
+        let space_id = spaces::create_space(
+            &mut *txn,
+            CreateSpace {
+                space_name: format!("Books {random_suffix}"),
+                bio: format!("Everything about {keyword} and other heirs"),
+            },
+        )
+        .await
+        .expect("error creating space");
+
+        let (tag_id, _) = spaces::create_tag(
+            &mut *txn,
+            CreateTag {
+                space_id: space_id.clone(),
+                name: format!("mystery {random_suffix}"),
+                description: format!("Whodunits starring {keyword}"),
+            },
+        )
+        .await
+        .expect("error creating tag");
+
+        let new_post = |title: String, private: bool, tags: Vec<String>| CreatePost {
+            user_id: author.clone(),
+            space_id: space_id.clone(),
+            title,
+            content: format!("Sixteen heirs & one fortune and {keyword} kicking <shins"),
+            content_html: "".to_owned(),
+            toc: serde_json::json!([]),
+            image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+            private,
+            tags,
+            stickers: vec![],
+            read_time: 1,
+            word_count: 9,
+        };
+
+        let published = posts::create_post(
+            &mut *txn,
+            new_post(
+                format!("{keyword} and the Westing Game"),
+                false,
+                vec![tag_id.clone()],
+            ),
+        )
+        .await
+        .expect("error creating post")
+        .expect("post is unexpectedly none");
+        let private = posts::create_post(&mut *txn, new_post("Private".to_owned(), true, vec![]))
+            .await
+            .expect("error creating post")
+            .expect("post is unexpectedly none");
+        let draft = posts::create_post(&mut *txn, new_post("Draft".to_owned(), false, vec![]))
+            .await
+            .expect("error creating post")
+            .expect("post is unexpectedly none");
+
+        for post_id in [&published, &private] {
+            sqlx::query("update jen.posts set published=true where id=$1::uuid")
+                .bind(post_id)
+                .execute(&mut *txn)
+                .await
+                .expect("error publishing post");
+        }
+
+        let pagination = |space_id: Option<String>, tag_id: Option<String>| PaginationLimits {
+            offset: 0,
+            limit: 10,
+            opts: SearchPaginationOptions { space_id, tag_id },
+        };
+
+        let results = search(
+            &mut *txn,
+            Search {
+                query: keyword.clone(),
+            },
+            pagination(None, None),
+        )
+        .await
+        .expect("error searching");
+
+        assert!(results.done);
+        assert_eq!(results.items.len(), 3);
+        // the post mentions the keyword in its title, which outweighs a mention in a bio
+        assert_eq!(results.items[0].kind, SearchResultKind::Post);
+        assert_eq!(results.items[0].id.to_string(), published);
+        assert!(results.items[0].snippet.contains("<mark>"));
+        assert!(results.items[0].snippet.contains("&amp; one fortune"));
+        assert!(results.items[0].snippet.contains("&lt;shins"));
+        assert!(results
+            .items
+            .iter()
+            .all(|r| r.id.to_string() != private && r.id.to_string() != draft));
+
+        let by_tag = search(
+            &mut *txn,
+            Search {
+                query: keyword.clone(),
+            },
+            pagination(None, Some(tag_id.clone())),
+        )
+        .await
+        .expect("error searching");
+        assert_eq!(by_tag.items.len(), 2);
+        assert!(by_tag
+            .items
+            .iter()
+            .all(|r| r.kind != SearchResultKind::Space));
+
+        let other_space = search(
+            &mut *txn,
+            Search { query: keyword },
+            pagination(Some(Uuid::new_v4().to_string()), None),
+        )
+        .await
+        .expect("error searching");
+        assert!(other_space.items.is_empty());
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
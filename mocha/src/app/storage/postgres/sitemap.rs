@@ -0,0 +1,190 @@
+use sqlx::{Executor, Postgres};
+use std::error::Error;
+
+use crate::app::{
+    dto::sitemap::GetSitemapPage,
+    entities::sitemap::{SitemapEntry, SitemapPage},
+};
+
+/// Every page that belongs in the sitemap, in a stable order so that it can be split into files
+const SITEMAP_ENTRIES: &str = "with entries as (
+    select 'post' as kind, id, updated_at from jen.posts
+    where published and visibility='public'
+    union all
+    select 'space' as kind, id, updated_at from jen.spaces
+    union all
+    select 'tag' as kind, id, updated_at from jen.tags
+)";
+
+/// Split the sitemap into files of at most `page_size` urls and report when each one last changed
+pub async fn get_sitemap_pages<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    page_size: i64,
+) -> Result<Vec<SitemapPage>, Box<dyn Error + Send + Sync>> {
+    let sql = format!(
+        "{SITEMAP_ENTRIES}
+         select page, max(updated_at) as updated_at from (
+           select (row_number() over (order by kind, id) - 1) / $1 as page, updated_at
+           from entries
+         ) numbered group by page order by page"
+    );
+    let pages = sqlx::query_as::<_, SitemapPage>(&sql)
+        .bind(page_size)
+        .fetch_all(executor)
+        .await?;
+    Ok(pages)
+}
+
+pub async fn get_sitemap_entries<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetSitemapPage,
+    page_size: i64,
+) -> Result<Vec<SitemapEntry>, Box<dyn Error + Send + Sync>> {
+    let sql = format!(
+        "{SITEMAP_ENTRIES}
+         select kind, id, updated_at from entries order by kind, id offset $1 limit $2"
+    );
+    let entries = sqlx::query_as::<_, SitemapEntry>(&sql)
+        .bind(data.page * page_size)
+        .bind(page_size)
+        .fetch_all(executor)
+        .await?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::{
+            posts::{CreatePost, PublishPost},
+            spaces::CreateSpace,
+            tags::CreateTag,
+            users::CreateUser,
+        },
+        entities::sitemap::SitemapEntryKind,
+        storage::postgres::{self, posts, spaces, users},
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_sitemap() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let author = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating author");
+
+        let space_id = spaces::create_space(
+            &mut *txn,
+            CreateSpace {
+                space_name: format!("Books {random_suffix}"),
+                bio: format!("All about books {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating space");
+
+        let (tag_id, _) = spaces::create_tag(
+            &mut *txn,
+            CreateTag {
+                space_id: space_id.clone(),
+                name: format!("mystery {random_suffix}"),
+                description: format!("Whodunits {random_suffix}"),
+            },
+        )
+        .await
+        .expect("error creating tag");
+
+        let new_post = |private: bool| CreatePost {
+            user_id: author.clone(),
+            space_id: space_id.clone(),
+            title: "The Westing Game".to_owned(),
+            content: "Sixteen heirs, one fortune.".to_owned(),
+            content_html: "".to_owned(),
+            toc: serde_json::json!([]),
+            image_uri: "https://assets.anishsinha.com/westing".to_owned(),
+            private,
+            tags: vec![],
+            stickers: vec![],
+            read_time: 1,
+            word_count: 4,
+        };
+
+        let published = posts::create_post(&mut *txn, new_post(false))
+            .await
+            .expect("error creating post")
+            .expect("post is unexpectedly none");
+        let private = posts::create_post(&mut *txn, new_post(true))
+            .await
+            .expect("error creating post")
+            .expect("post is unexpectedly none");
+        let draft = posts::create_post(&mut *txn, new_post(false))
+            .await
+            .expect("error creating post")
+            .expect("post is unexpectedly none");
+
+        for post_id in [&published, &private] {
+            posts::publish_post(
+                &mut *txn,
+                PublishPost {
+                    user_id: author.clone(),
+                    post_id: post_id.clone(),
+                },
+            )
+            .await
+            .expect("error publishing post");
+        }
+
+        let everything = get_sitemap_entries(&mut *txn, GetSitemapPage { page: 0 }, i64::MAX / 2)
+            .await
+            .expect("error fetching sitemap entries");
+        let contains = |kind: SitemapEntryKind, id: &str| {
+            everything
+                .iter()
+                .any(|e| e.kind == kind && e.id.to_string() == id)
+        };
+        assert!(contains(SitemapEntryKind::Post, &published));
+        assert!(contains(SitemapEntryKind::Space, &space_id));
+        assert!(contains(SitemapEntryKind::Tag, &tag_id));
+        assert!(!contains(SitemapEntryKind::Post, &private));
+        assert!(!contains(SitemapEntryKind::Post, &draft));
+
+        let pages = get_sitemap_pages(&mut *txn, 2)
+            .await
+            .expect("error fetching sitemap pages");
+        assert_eq!(pages.len(), everything.len().div_ceil(2));
+
+        for page in pages {
+            let entries = get_sitemap_entries(&mut *txn, GetSitemapPage { page: page.page }, 2)
+                .await
+                .expect("error fetching sitemap entries");
+            assert!(!entries.is_empty() && entries.len() <= 2);
+            assert_eq!(
+                entries.iter().map(|e| e.updated_at).max(),
+                Some(page.updated_at)
+            );
+        }
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
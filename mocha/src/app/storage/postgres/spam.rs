@@ -0,0 +1,130 @@
+use sqlx::{Acquire, Executor, Postgres};
+use std::error::Error;
+
+use crate::app::entities::spam::{SpamLabel, SpamStats, TokenCounts};
+
+pub async fn get_spam_stats<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    tokens: &[String],
+) -> Result<SpamStats, Box<dyn Error + Send + Sync>> {
+    let mut conn = executor.acquire().await?;
+
+    let (spam_documents, ham_documents): (i32, i32) = sqlx::query_as(
+        "select coalesce(sum(documents) filter (where label='spam'), 0)::int,
+                coalesce(sum(documents) filter (where label='ham'), 0)::int
+         from jen.spam_corpus",
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let tokens = sqlx::query_as::<_, TokenCounts>(
+        "select token, spam_count, ham_count from jen.spam_tokens where token=any($1)",
+    )
+    .bind(tokens)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(SpamStats {
+        spam_documents,
+        ham_documents,
+        tokens,
+    })
+}
+
+/// Teach the filter that a comment made of `tokens` is `label`. A negative `delta` takes a lesson
+/// back, which is how a moderator changing their mind about a comment gets undone.
+pub async fn train_spam_filter<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    tokens: &[String],
+    label: SpamLabel,
+    delta: i32,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (spam_delta, ham_delta) = match label {
+        SpamLabel::Spam => (delta, 0),
+        SpamLabel::Ham => (0, delta),
+    };
+
+    let mut txn = executor.begin().await?;
+
+    sqlx::query(
+        "insert into jen.spam_tokens (token, spam_count, ham_count)
+         select token, greatest($2, 0), greatest($3, 0) from unnest($1::text[]) as token
+         on conflict (token) do update set
+           spam_count=greatest(spam_tokens.spam_count + $2, 0),
+           ham_count=greatest(spam_tokens.ham_count + $3, 0)",
+    )
+    .bind(tokens)
+    .bind(spam_delta)
+    .bind(ham_delta)
+    .execute(&mut *txn)
+    .await?;
+
+    sqlx::query("update jen.spam_corpus set documents=greatest(documents + $2, 0) where label=$1")
+        .bind(label)
+        .bind(delta)
+        .execute(&mut *txn)
+        .await?;
+
+    txn.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{spam, storage::postgres, util};
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_train_spam_filter() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6).to_lowercase();
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let spam_tokens = spam::tokenize(&format!("cheap pills {random_suffix}pills"));
+        let ham_tokens = spam::tokenize(&format!("who is the {random_suffix}bomber"));
+
+        let before = get_spam_stats(&mut *txn, &spam_tokens)
+            .await
+            .expect("error fetching spam stats");
+
+        for _ in 0..spam::MIN_DOCUMENTS {
+            train_spam_filter(&mut *txn, &spam_tokens, SpamLabel::Spam, 1)
+                .await
+                .expect("error training spam filter");
+            train_spam_filter(&mut *txn, &ham_tokens, SpamLabel::Ham, 1)
+                .await
+                .expect("error training spam filter");
+        }
+
+        let stats = get_spam_stats(&mut *txn, &spam_tokens)
+            .await
+            .expect("error fetching spam stats");
+        assert_eq!(
+            stats.spam_documents,
+            before.spam_documents + spam::MIN_DOCUMENTS
+        );
+        let token = format!("{random_suffix}pills");
+        let counts = stats
+            .tokens
+            .iter()
+            .find(|t| t.token == token)
+            .expect("token should have been learned");
+        assert_eq!(counts.spam_count, spam::MIN_DOCUMENTS);
+        assert_eq!(counts.ham_count, 0);
+
+        // taking a lesson back undoes it
+        train_spam_filter(&mut *txn, &spam_tokens, SpamLabel::Spam, -1)
+            .await
+            .expect("error untraining spam filter");
+        let stats = get_spam_stats(&mut *txn, &spam_tokens).await.unwrap();
+        let counts = stats.tokens.iter().find(|t| t.token == token).unwrap();
+        assert_eq!(counts.spam_count, spam::MIN_DOCUMENTS - 1);
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
@@ -0,0 +1,317 @@
+use sqlx::{Acquire, Executor, Postgres};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::auth::{
+        CreateRefreshToken, RevokeRefreshTokenFamily, RevokeSessionRefreshTokens,
+        RevokeUserRefreshTokens, RotateRefreshToken,
+    },
+    entities::auth::RefreshOutcome,
+};
+
+/// Store the hash of a refresh token that starts a new family
+pub async fn create_refresh_token<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: CreateRefreshToken,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let session_id = Uuid::parse_str(&data.session_id)?;
+
+    let sql = "insert into jen.refresh_tokens (user_id, session_id, family_id, token_hash,
+                                              expires_at)
+               values ($1, $2, $3, $4, current_timestamp + make_interval(secs => $5))
+               returning id";
+    let (id,): (Uuid,) = sqlx::query_as(sql)
+        .bind(user_id)
+        .bind(session_id)
+        .bind(Uuid::new_v4())
+        .bind(data.token_hash)
+        .bind(data.expires_in as f64)
+        .fetch_one(executor)
+        .await?;
+    Ok(id.to_string())
+}
+
+#[derive(sqlx::FromRow)]
+struct TokenState {
+    id: Uuid,
+    user_id: Uuid,
+    session_id: Option<Uuid>,
+    family_id: Uuid,
+    used: bool,
+    revoked: bool,
+    expired: bool,
+}
+
+/// Use up a refresh token and store its replacement. Replaying a token that was already used
+/// revokes every token in its family, including the one that replaced it.
+pub async fn rotate_refresh_token<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: RotateRefreshToken,
+) -> Result<RefreshOutcome, Box<dyn Error + Send + Sync>> {
+    let mut txn = executor.begin().await?;
+
+    let sql = "select id, user_id, session_id, family_id, used_at is not null as used,
+               revoked_at is not null as revoked, expires_at <= current_timestamp as expired
+               from jen.refresh_tokens where token_hash=$1 for update";
+    let current: Option<TokenState> = sqlx::query_as(sql)
+        .bind(&data.token_hash)
+        .fetch_optional(&mut *txn)
+        .await?;
+
+    let outcome = match current {
+        None => RefreshOutcome::Invalid,
+        Some(token) if token.revoked || token.expired => RefreshOutcome::Invalid,
+        Some(token) if token.used => {
+            sqlx::query(
+                "update jen.refresh_tokens set revoked_at=current_timestamp
+                 where family_id=$1 and revoked_at is null",
+            )
+            .bind(token.family_id)
+            .execute(&mut *txn)
+            .await?;
+            RefreshOutcome::Reused
+        }
+        Some(TokenState {
+            id,
+            user_id,
+            session_id,
+            family_id,
+            ..
+        }) => {
+            sqlx::query("update jen.refresh_tokens set used_at=current_timestamp where id=$1")
+                .bind(id)
+                .execute(&mut *txn)
+                .await?;
+            sqlx::query(
+                "insert into jen.refresh_tokens (user_id, session_id, family_id, parent_id,
+                                                 token_hash, expires_at)
+                 values ($1, $2, $3, $4, $5, current_timestamp + make_interval(secs => $6))",
+            )
+            .bind(user_id)
+            .bind(session_id)
+            .bind(family_id)
+            .bind(id)
+            .bind(data.new_token_hash)
+            .bind(data.expires_in as f64)
+            .execute(&mut *txn)
+            .await?;
+            RefreshOutcome::Rotated { user_id }
+        }
+    };
+
+    txn.commit().await?;
+    Ok(outcome)
+}
+
+/// Revoke every token in the family the given token belongs to
+pub async fn revoke_refresh_token_family<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: RevokeRefreshTokenFamily,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let sql = "update jen.refresh_tokens set revoked_at=current_timestamp
+               where revoked_at is null and family_id=(
+                 select family_id from jen.refresh_tokens where token_hash=$1
+               )";
+    let rows = sqlx::query(sql)
+        .bind(data.token_hash)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+/// Revoke every outstanding refresh token a user holds, across all of their families. Tokens
+/// issued with `except_session` are left alone.
+pub async fn revoke_user_refresh_tokens<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: RevokeUserRefreshTokens,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let except = data
+        .except_session
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()?;
+
+    let sql = "update jen.refresh_tokens set revoked_at=current_timestamp
+               where revoked_at is null and user_id=$1
+               and ($2::uuid is null or session_id is distinct from $2)";
+    let rows = sqlx::query(sql)
+        .bind(user_id)
+        .bind(except)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+/// Revoke the refresh tokens issued along with a session that has ended
+pub async fn revoke_session_refresh_tokens<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: RevokeSessionRefreshTokens,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let session_id = Uuid::parse_str(&data.session_id)?;
+
+    let sql = "update jen.refresh_tokens set revoked_at=current_timestamp
+               where revoked_at is null and session_id=$1";
+    let rows = sqlx::query(sql)
+        .bind(session_id)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        dto::users::CreateUser,
+        storage::postgres::{self, users},
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_refresh_token_rotation() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let user_id = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating user");
+
+        let hash = |name: &str| format!("{name}-{random_suffix}");
+        let session_id = Uuid::new_v4().to_string();
+        let token = |name: &str, session_id: &str, expires_in: i64| CreateRefreshToken {
+            user_id: user_id.clone(),
+            session_id: session_id.to_owned(),
+            token_hash: hash(name),
+            expires_in,
+        };
+
+        create_refresh_token(&mut *txn, token("first", &session_id, 60))
+            .await
+            .expect("error creating refresh token");
+
+        let rotate = |from: &str, to: &str| RotateRefreshToken {
+            token_hash: hash(from),
+            new_token_hash: hash(to),
+            expires_in: 60,
+        };
+
+        let rotated = rotate_refresh_token(&mut *txn, rotate("first", "second"))
+            .await
+            .expect("error rotating refresh token");
+        assert_eq!(
+            rotated,
+            RefreshOutcome::Rotated {
+                user_id: Uuid::parse_str(&user_id).unwrap()
+            }
+        );
+
+        // replaying the first token burns the whole family, including the second one
+        let replayed = rotate_refresh_token(&mut *txn, rotate("first", "third"))
+            .await
+            .expect("error rotating refresh token");
+        assert_eq!(replayed, RefreshOutcome::Reused);
+        let revoked = rotate_refresh_token(&mut *txn, rotate("second", "fourth"))
+            .await
+            .expect("error rotating refresh token");
+        assert_eq!(revoked, RefreshOutcome::Invalid);
+
+        let unknown = rotate_refresh_token(&mut *txn, rotate("unknown", "fifth"))
+            .await
+            .expect("error rotating refresh token");
+        assert_eq!(unknown, RefreshOutcome::Invalid);
+
+        create_refresh_token(&mut *txn, token("expired", &session_id, 0))
+            .await
+            .expect("error creating refresh token");
+        let expired = rotate_refresh_token(&mut *txn, rotate("expired", "sixth"))
+            .await
+            .expect("error rotating refresh token");
+        assert_eq!(expired, RefreshOutcome::Invalid);
+
+        create_refresh_token(&mut *txn, token("logout", &session_id, 60))
+            .await
+            .expect("error creating refresh token");
+        let revoked = revoke_refresh_token_family(
+            &mut *txn,
+            RevokeRefreshTokenFamily {
+                token_hash: hash("logout"),
+            },
+        )
+        .await
+        .expect("error revoking refresh tokens");
+        assert_eq!(revoked, 1);
+        let after_logout = rotate_refresh_token(&mut *txn, rotate("logout", "seventh"))
+            .await
+            .expect("error rotating refresh token");
+        assert_eq!(after_logout, RefreshOutcome::Invalid);
+
+        create_refresh_token(&mut *txn, token("everywhere", &session_id, 60))
+            .await
+            .expect("error creating refresh token");
+        let other_session = Uuid::new_v4().to_string();
+        create_refresh_token(&mut *txn, token("elsewhere", &other_session, 60))
+            .await
+            .expect("error creating refresh token");
+        // only the expired token and the one above were still unrevoked
+        let revoked = revoke_user_refresh_tokens(
+            &mut *txn,
+            RevokeUserRefreshTokens {
+                user_id: user_id.clone(),
+                except_session: Some(other_session.clone()),
+            },
+        )
+        .await
+        .expect("error revoking refresh tokens");
+        assert_eq!(revoked, 2);
+        let after_revoke = rotate_refresh_token(&mut *txn, rotate("everywhere", "eighth"))
+            .await
+            .expect("error rotating refresh token");
+        assert_eq!(after_revoke, RefreshOutcome::Invalid);
+
+        // replacements stay tied to the session, so ending it revokes them too
+        let rotated = rotate_refresh_token(&mut *txn, rotate("elsewhere", "ninth"))
+            .await
+            .expect("error rotating refresh token");
+        assert!(matches!(rotated, RefreshOutcome::Rotated { .. }));
+        let revoked = revoke_session_refresh_tokens(
+            &mut *txn,
+            RevokeSessionRefreshTokens {
+                session_id: other_session,
+            },
+        )
+        .await
+        .expect("error revoking refresh tokens");
+        // the used token and its replacement
+        assert_eq!(revoked, 2);
+        let after_session = rotate_refresh_token(&mut *txn, rotate("ninth", "tenth"))
+            .await
+            .expect("error rotating refresh token");
+        assert_eq!(after_session, RefreshOutcome::Invalid);
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
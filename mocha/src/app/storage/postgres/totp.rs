@@ -0,0 +1,461 @@
+use sqlx::{Acquire, Executor, Postgres, QueryBuilder, Transaction};
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::app::{
+    dto::auth::{
+        AttemptLoginChallenge, ConfirmTotp, CreateLoginChallenge, CreateTotp, DeleteTotp, GetTotp,
+        ReserveTotpAttempt, UseRecoveryCode, UseTotpStep,
+    },
+    entities::auth::{LoginChallenge, UserTotp},
+};
+
+/// Store a new, unconfirmed secret for a user. Returns `false` without changing anything if the
+/// user already has a confirmed authenticator; that one has to be removed first.
+pub async fn create_totp<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: CreateTotp,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "insert into jen.user_totp (user_id, secret) values ($1, $2)
+               on conflict (user_id) do update set secret=excluded.secret, last_used_step=null
+               where user_totp.confirmed_at is null";
+    let rows = sqlx::query(sql)
+        .bind(user_id)
+        .bind(data.secret)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows > 0)
+}
+
+pub async fn get_totp<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetTotp,
+) -> Result<Option<UserTotp>, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let totp = sqlx::query_as!(
+        UserTotp,
+        "select user_id, secret, confirmed_at, last_used_step, created_at, updated_at
+         from jen.user_totp where user_id=$1",
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(totp)
+}
+
+/// Turn on a pending authenticator once the user has entered a code from it, replacing any
+/// recovery codes they had with a new set. Returns `false` if there was nothing pending.
+pub async fn confirm_totp<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: ConfirmTotp,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let mut txn: Transaction<'_, Postgres> = executor.begin().await?;
+
+    let confirmed = sqlx::query(
+        "update jen.user_totp set confirmed_at=current_timestamp, last_used_step=$2
+         where user_id=$1 and confirmed_at is null",
+    )
+    .bind(user_id)
+    .bind(data.step)
+    .execute(&mut *txn)
+    .await?
+    .rows_affected();
+    if confirmed == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query("delete from jen.totp_recovery_codes where user_id=$1")
+        .bind(user_id)
+        .execute(&mut *txn)
+        .await?;
+    if !data.recovery_code_hashes.is_empty() {
+        let mut builder: QueryBuilder<Postgres> =
+            QueryBuilder::new("insert into jen.totp_recovery_codes (user_id, code_hash) ");
+        builder.push_values(data.recovery_code_hashes, |mut b, code_hash| {
+            b.push_bind(user_id).push_bind(code_hash);
+        });
+        builder.build().execute(&mut *txn).await?;
+    }
+
+    txn.commit().await?;
+    Ok(true)
+}
+
+/// Record that a code from the given time step was used. Returns `false` if a code from that step
+/// or a later one was already accepted, which means this one is being replayed.
+pub async fn use_totp_step<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: UseTotpStep,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "update jen.user_totp set last_used_step=$2
+               where user_id=$1 and confirmed_at is not null
+               and (last_used_step is null or last_used_step < $2)";
+    let rows = sqlx::query(sql)
+        .bind(user_id)
+        .bind(data.step)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows > 0)
+}
+
+/// Use up one of a user's recovery codes. Returns `false` if it isn't theirs or was already used.
+pub async fn use_recovery_code<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: UseRecoveryCode,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "update jen.totp_recovery_codes set used_at=current_timestamp
+               where user_id=$1 and code_hash=$2 and used_at is null";
+    let rows = sqlx::query(sql)
+        .bind(user_id)
+        .bind(data.code_hash)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows > 0)
+}
+
+/// Remove a user's authenticator along with their recovery codes
+pub async fn delete_totp<'a>(
+    executor: impl Executor<'a, Database = Postgres> + Acquire<'a, Database = Postgres>,
+    data: DeleteTotp,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let mut txn: Transaction<'_, Postgres> = executor.begin().await?;
+
+    sqlx::query("delete from jen.totp_recovery_codes where user_id=$1")
+        .bind(user_id)
+        .execute(&mut *txn)
+        .await?;
+    let rows = sqlx::query("delete from jen.user_totp where user_id=$1")
+        .bind(user_id)
+        .execute(&mut *txn)
+        .await?
+        .rows_affected();
+
+    txn.commit().await?;
+    Ok(rows)
+}
+
+pub async fn create_login_challenge<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: CreateLoginChallenge,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "insert into jen.login_challenges (user_id, token_hash, data, expires_at)
+               values ($1, $2, $3, current_timestamp + make_interval(secs => $4))
+               returning id";
+    let (id,): (Uuid,) = sqlx::query_as(sql)
+        .bind(user_id)
+        .bind(data.token_hash)
+        .bind(data.data)
+        .bind(data.expires_in as f64)
+        .fetch_one(executor)
+        .await?;
+    Ok(id.to_string())
+}
+
+/// Spend one of a login challenge's attempts. Returns the challenge, or `None` if it's been used,
+/// has expired or is out of attempts.
+pub async fn attempt_login_challenge<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: AttemptLoginChallenge,
+) -> Result<Option<LoginChallenge>, Box<dyn Error + Send + Sync>> {
+    let challenge = sqlx::query_as!(
+        LoginChallenge,
+        "update jen.login_challenges set attempts=attempts + 1
+         where token_hash=$1 and used_at is null and expires_at > current_timestamp
+         and attempts < $2
+         returning id, user_id, data, attempts, expires_at, created_at, updated_at",
+        data.token_hash,
+        data.max_attempts
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(challenge)
+}
+
+/// Use up a login challenge that was answered. Returns `false` if another request got there first.
+pub async fn complete_login_challenge<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    id: Uuid,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let rows = sqlx::query(
+        "update jen.login_challenges set used_at=current_timestamp where id=$1 and used_at is null",
+    )
+    .bind(id)
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(rows > 0)
+}
+
+/// Count an attempt at a user's second factor against them, failing or not. Returns `false` if
+/// the authenticator is locked. The attempt that reaches `max_failures` locks it for `lockout`
+/// seconds.
+pub async fn reserve_totp_attempt<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: ReserveTotpAttempt,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "update jen.user_totp set
+               failed_attempts=case when locked_until is null then failed_attempts + 1 else 1 end,
+               locked_until=case
+                 when (case when locked_until is null then failed_attempts + 1 else 1 end) >= $2
+                 then current_timestamp + make_interval(secs => $3) end
+               where user_id=$1 and (locked_until is null or locked_until <= current_timestamp)";
+    let rows = sqlx::query(sql)
+        .bind(user_id)
+        .bind(data.max_failures)
+        .bind(data.lockout as f64)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows > 0)
+}
+
+/// Forget a user's failed attempts once they've got their second factor right
+pub async fn reset_totp_failures<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: GetTotp,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    sqlx::query("update jen.user_totp set failed_attempts=0, locked_until=null where user_id=$1")
+        .bind(user_id)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Remove login challenges that can no longer be answered
+pub async fn delete_expired_login_challenges<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let rows = sqlx::query(
+        "delete from jen.login_challenges where expires_at <= current_timestamp or used_at is not null",
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::{
+        auth::tokens,
+        dto::users::CreateUser,
+        storage::postgres::{self, users},
+        util,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_totp() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let user_id = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating user");
+        let get = || GetTotp {
+            user_id: user_id.clone(),
+        };
+        let create = |secret: &[u8]| CreateTotp {
+            user_id: user_id.clone(),
+            secret: secret.to_vec(),
+        };
+        let use_step = |step: i64| UseTotpStep {
+            user_id: user_id.clone(),
+            step,
+        };
+        let use_code = |code: &str| UseRecoveryCode {
+            user_id: user_id.clone(),
+            code_hash: tokens::hash_token(code),
+        };
+
+        assert!(get_totp(&mut *txn, get()).await.unwrap().is_none());
+
+        // enrolling again before confirming just swaps the secret
+        assert!(create_totp(&mut *txn, create(b"first")).await.unwrap());
+        assert!(create_totp(&mut *txn, create(b"second")).await.unwrap());
+        let pending = get_totp(&mut *txn, get()).await.unwrap().unwrap();
+        assert_eq!(pending.secret, b"second");
+        assert!(pending.confirmed_at.is_none());
+        // nothing can be used until the authenticator is confirmed
+        assert!(!use_totp_step(&mut *txn, use_step(1)).await.unwrap());
+
+        let confirmed = confirm_totp(
+            &mut *txn,
+            ConfirmTotp {
+                user_id: user_id.clone(),
+                step: 10,
+                recovery_code_hashes: vec![tokens::hash_token("one"), tokens::hash_token("two")],
+            },
+        )
+        .await
+        .expect("error confirming totp");
+        assert!(confirmed);
+
+        let totp = get_totp(&mut *txn, get()).await.unwrap().unwrap();
+        assert!(totp.confirmed_at.is_some());
+        assert_eq!(totp.last_used_step, Some(10));
+        // a confirmed authenticator can't be overwritten by a new enrollment
+        assert!(!create_totp(&mut *txn, create(b"third")).await.unwrap());
+
+        assert!(!use_totp_step(&mut *txn, use_step(10)).await.unwrap());
+        assert!(use_totp_step(&mut *txn, use_step(11)).await.unwrap());
+        assert!(!use_totp_step(&mut *txn, use_step(9)).await.unwrap());
+
+        assert!(use_recovery_code(&mut *txn, use_code("one")).await.unwrap());
+        assert!(!use_recovery_code(&mut *txn, use_code("one")).await.unwrap());
+        assert!(!use_recovery_code(&mut *txn, use_code("three"))
+            .await
+            .unwrap());
+
+        // failures count across login challenges and lock the authenticator once they add up
+        let reserve = |lockout: i64| ReserveTotpAttempt {
+            user_id: user_id.clone(),
+            max_failures: 3,
+            lockout,
+        };
+        for _ in 0..2 {
+            assert!(reserve_totp_attempt(&mut *txn, reserve(60)).await.unwrap());
+        }
+        reset_totp_failures(&mut *txn, get()).await.unwrap();
+        for _ in 0..3 {
+            assert!(reserve_totp_attempt(&mut *txn, reserve(60)).await.unwrap());
+        }
+        assert!(!reserve_totp_attempt(&mut *txn, reserve(60)).await.unwrap());
+        // the lock runs out on its own
+        reset_totp_failures(&mut *txn, get()).await.unwrap();
+        for _ in 0..3 {
+            assert!(reserve_totp_attempt(&mut *txn, reserve(0)).await.unwrap());
+        }
+        assert!(reserve_totp_attempt(&mut *txn, reserve(0)).await.unwrap());
+
+        assert_eq!(
+            delete_totp(
+                &mut *txn,
+                DeleteTotp {
+                    user_id: user_id.clone()
+                }
+            )
+            .await
+            .unwrap(),
+            1
+        );
+        assert!(get_totp(&mut *txn, get()).await.unwrap().is_none());
+        assert!(!use_recovery_code(&mut *txn, use_code("two")).await.unwrap());
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+
+    #[tokio::test]
+    pub async fn test_login_challenges() {
+        util::test_util::init();
+        let pool = postgres::create_pool(5).await.expect("error creating pool");
+        let random_suffix = util::rng::random_string(6);
+
+        let mut txn = pool.begin().await.expect("error starting transaction");
+
+        let user_id = users::create_user(
+            &mut *txn,
+            CreateUser {
+                first_name: "Jenny".to_owned(),
+                last_name: "Sinha".to_owned(),
+                email: format!("jenny-{random_suffix}@gmail.com"),
+                username: format!("jenny-{random_suffix}"),
+                image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
+                hashed_password: None,
+                algorithm: None,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error creating user");
+        let create = |token: &str, expires_in: i64| CreateLoginChallenge {
+            user_id: user_id.clone(),
+            token_hash: format!("{token}-{random_suffix}"),
+            data: serde_json::json!({"user_agent": "test"}),
+            expires_in,
+        };
+        let attempt = |token: &str| AttemptLoginChallenge {
+            token_hash: format!("{token}-{random_suffix}"),
+            max_attempts: 2,
+        };
+
+        create_login_challenge(&mut *txn, create("good", 60))
+            .await
+            .expect("error creating login challenge");
+        let challenge = attempt_login_challenge(&mut *txn, attempt("good"))
+            .await
+            .unwrap()
+            .expect("challenge doesn't exist");
+        assert_eq!(challenge.user_id.to_string(), user_id);
+        assert_eq!(challenge.data["user_agent"], "test");
+
+        assert!(complete_login_challenge(&mut *txn, challenge.id)
+            .await
+            .unwrap());
+        assert!(!complete_login_challenge(&mut *txn, challenge.id)
+            .await
+            .unwrap());
+        assert!(attempt_login_challenge(&mut *txn, attempt("good"))
+            .await
+            .unwrap()
+            .is_none());
+
+        // challenges run out of attempts
+        create_login_challenge(&mut *txn, create("guessed", 60))
+            .await
+            .expect("error creating login challenge");
+        for _ in 0..2 {
+            attempt_login_challenge(&mut *txn, attempt("guessed"))
+                .await
+                .unwrap()
+                .expect("challenge doesn't exist");
+        }
+        assert!(attempt_login_challenge(&mut *txn, attempt("guessed"))
+            .await
+            .unwrap()
+            .is_none());
+
+        create_login_challenge(&mut *txn, create("expired", 0))
+            .await
+            .expect("error creating login challenge");
+        assert!(attempt_login_challenge(&mut *txn, attempt("expired"))
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(delete_expired_login_challenges(&mut *txn).await.unwrap() >= 2);
+
+        txn.rollback()
+            .await
+            .expect("error rolling back transaction");
+    }
+}
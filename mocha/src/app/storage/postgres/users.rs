@@ -1,7 +1,9 @@
 use sqlx::{types::Uuid, Acquire, Executor, Postgres, Transaction};
 use std::error::Error;
 
-use crate::app::dto::users::{CreateUser, DeleteUser, EditUser, GetUserByEmail, GetUserById};
+use crate::app::dto::users::{
+    CreateUser, DeleteUser, EditUser, GetUserByEmail, GetUserById, UpdateUserCredentials,
+};
 use crate::app::entities::users::{User, UserWithCredentials};
 use crate::app::types::HashAlgorithm;
 
@@ -18,10 +20,11 @@ pub async fn create_user<'a>(
     .await?;
 
     if let (Some(hash), Some(alg)) = (data.hashed_password, data.algorithm) {
-        sqlx::query(r#"insert into jen.user_credentials (user_id, credential_hash, alg) values ($1, $2, $3)"#)
+        sqlx::query(r#"insert into jen.user_credentials (user_id, credential_hash, alg, pepper_id) values ($1, $2, $3, $4)"#)
             .bind(user_id)
             .bind(hash)
             .bind(alg)
+            .bind(data.pepper_id)
             .execute(&mut *txn)
             .await?;
     }
@@ -46,7 +49,7 @@ pub async fn get_user_by_id<'a>(
     data: GetUserById,
 ) -> Result<Option<User>, Box<dyn Error + Send + Sync>> {
     let id = Uuid::parse_str(&data.id)?;
-    let user = sqlx::query_as!(User, r#"select id, first_name, last_name, email, username, image_uri, created_at, updated_at from jen.users where id=$1"#, id).fetch_optional(executor).await?;
+    let user = sqlx::query_as!(User, r#"select id, first_name, last_name, email, username, image_uri, email_verified_at, created_at, updated_at from jen.users where id=$1"#, id).fetch_optional(executor).await?;
     Ok(user)
 }
 
@@ -55,7 +58,7 @@ pub async fn get_user_by_email<'a>(
     executor: impl Executor<'a, Database = Postgres>,
     data: GetUserByEmail,
 ) -> Result<Option<User>, Box<dyn Error + Send + Sync>> {
-    let user = sqlx::query_as!(User, r#"select id, first_name, last_name, email, username, image_uri, created_at, updated_at from jen.users where email=$1"#, data.email).fetch_optional(executor).await?;
+    let user = sqlx::query_as!(User, r#"select id, first_name, last_name, email, username, image_uri, email_verified_at, created_at, updated_at from jen.users where email=$1"#, data.email).fetch_optional(executor).await?;
     Ok(user)
 }
 
@@ -67,6 +70,7 @@ pub async fn get_user_with_credentials_by_email<'a>(
         UserWithCredentials,
         r#"select users.id, first_name, last_name, email, username, image_uri, 
            jen.user_credentials.credential_hash, jen.user_credentials.alg as "alg!: HashAlgorithm", 
+           jen.user_credentials.pepper_id, 
            users.created_at, users.updated_at from jen.users join jen.user_credentials 
            on users.id=user_credentials.user_id and email=$1"#,
         data.email
@@ -76,6 +80,25 @@ pub async fn get_user_with_credentials_by_email<'a>(
     Ok(user)
 }
 
+/// Replace a user's password hash, e.g. after upgrading it to the current hashing policy
+pub async fn update_user_credentials<'a>(
+    executor: impl Executor<'a, Database = Postgres>,
+    data: UpdateUserCredentials,
+) -> Result<u64, Box<dyn Error + Send + Sync>> {
+    let user_id = Uuid::parse_str(&data.user_id)?;
+    let sql = "update jen.user_credentials set credential_hash=$2, alg=$3, pepper_id=$4
+               where user_id=$1";
+    let rows = sqlx::query(sql)
+        .bind(user_id)
+        .bind(data.hashed_password)
+        .bind(data.algorithm)
+        .bind(data.pepper_id)
+        .execute(executor)
+        .await?
+        .rows_affected();
+    Ok(rows)
+}
+
 pub async fn edit_user<'a>(
     executor: impl Executor<'a, Database = Postgres>,
     data: EditUser,
@@ -110,7 +133,10 @@ pub async fn delete_user<'a>(
 #[cfg(test)]
 mod tests {
     use crate::app::{
-        auth::CredentialManager, storage::postgres::create_pool, types::HashAlgorithm, util,
+        auth::{credentials::HashCosts, CredentialManager},
+        storage::postgres::create_pool,
+        types::HashAlgorithm,
+        util,
     };
 
     use super::*;
@@ -136,7 +162,7 @@ mod tests {
 
         assert!(nonexistent.is_none());
 
-        let manager = CredentialManager::new(HashAlgorithm::Argon2);
+        let manager = CredentialManager::with_costs(HashAlgorithm::Argon2, HashCosts::default());
         let hash = manager.create_hash(b"jennysinha").unwrap();
 
         let new_user = CreateUser {
@@ -147,6 +173,7 @@ mod tests {
             image_uri: "https://assets.anishsinha.com/jenny".to_owned(),
             hashed_password: Some(hash.to_owned()),
             algorithm: Some(HashAlgorithm::Argon2),
+            pepper_id: None,
         };
 
         let new_user = create_user(&mut *txn, new_user)
@@ -202,6 +229,38 @@ mod tests {
         assert!(by_email.is_some());
         assert_eq!(by_email.unwrap().username, "jen_sinha");
 
+        let bcrypt_manager =
+            CredentialManager::with_costs(HashAlgorithm::Bcrypt, HashCosts::default());
+        let updated = update_user_credentials(
+            &mut *txn,
+            UpdateUserCredentials {
+                user_id: new_user.clone(),
+                hashed_password: bcrypt_manager.create_hash(b"jennysinha").unwrap(),
+                algorithm: HashAlgorithm::Bcrypt,
+                pepper_id: None,
+            },
+        )
+        .await
+        .expect("error updating credentials");
+        assert_eq!(updated, 1);
+
+        let with_credentials = get_user_with_credentials_by_email(
+            &mut *txn,
+            GetUserByEmail {
+                email: email.clone(),
+            },
+        )
+        .await
+        .expect("error getting user with credentials")
+        .expect("user has no credentials");
+        assert_eq!(with_credentials.alg, HashAlgorithm::Bcrypt);
+        assert!(manager.verify_hash(
+            "jennysinha",
+            &with_credentials.credential_hash,
+            &with_credentials.alg,
+            with_credentials.pepper_id.as_deref()
+        ));
+
         delete_user(
             &mut *txn,
             DeleteUser {
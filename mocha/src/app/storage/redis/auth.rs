@@ -1,19 +1,67 @@
 use crate::app::{
-    dto::auth::{CreateSession, DeleteSession, GetSessionById},
+    dto::auth::{
+        CreateSession, DeleteSession, DeleteUserSessions, GetSessionById, GetSessionsByUserId,
+        TouchSession,
+    },
     entities::auth::Session,
     storage::errors::StorageError,
 };
+use mobc_redis::redis::{self, AsyncCommands};
+use std::cmp::Reverse;
 use uuid::Uuid;
 
-use super::{delete, get_json, set_json, RedisConn};
+use super::{delete, get_json, set_json_ex, RedisConn};
 
+/// Sessions are stored under their bare id. Each user also gets a set of their session ids so
+/// their devices can be listed and signed out without scanning the keyspace.
+fn user_sessions_key(user_id: &str) -> String {
+    format!("user_sessions:{user_id}")
+}
+
+/// Make sure a user's session index lives at least as long as a session that was just written to
+/// it. The index is never shortened since other sessions in it may outlive this one. Ids of
+/// sessions that expire before the index does are pruned when it's read.
+async fn extend_index(
+    conn: &mut RedisConn,
+    index_key: &str,
+    expires_in: usize,
+) -> Result<(), redis::RedisError> {
+    let ttl: i64 = conn.ttl(index_key).await?;
+    if ttl >= 0 && ttl as usize >= expires_in {
+        return Ok(());
+    }
+    conn.expire::<_, ()>(index_key, expires_in.max(1)).await
+}
+
+fn into_session(id: &str, data: CreateSession) -> Result<Session, StorageError> {
+    Ok(Session {
+        id: Uuid::parse_str(id).map_err(|e| {
+            log::error!("{}", e);
+            StorageError::RedisGetSession
+        })?,
+        user_id: Uuid::parse_str(&data.user_id).map_err(|_| StorageError::RedisGetSession)?,
+        data: data.data,
+        created_at: data.created_at,
+        updated_at: data.updated_at,
+    })
+}
+
+/// Start a session that redis drops after `expires_in` seconds unless it's touched again
 pub async fn start_session(
     conn: &mut RedisConn,
     data: CreateSession,
+    expires_in: usize,
 ) -> Result<String, StorageError> {
     let session_id = Uuid::new_v4().to_string();
+    let index_key = user_sessions_key(&data.user_id);
 
-    set_json(conn, &session_id, data)
+    set_json_ex(conn, &session_id, data, expires_in)
+        .await
+        .map_err(|_| StorageError::RedisStartSession)?;
+    conn.sadd::<_, _, ()>(&index_key, &session_id)
+        .await
+        .map_err(|_| StorageError::RedisStartSession)?;
+    extend_index(conn, &index_key, expires_in)
         .await
         .map_err(|_| StorageError::RedisStartSession)?;
 
@@ -21,9 +69,18 @@ pub async fn start_session(
 }
 
 pub async fn end_session(conn: &mut RedisConn, data: DeleteSession) -> Result<(), StorageError> {
+    let session: Option<CreateSession> = get_json(conn, &data.id)
+        .await
+        .map_err(|_| StorageError::RedisEndSession)?;
+
     delete(conn, &data.id)
         .await
         .map_err(|_| StorageError::RedisEndSession)?;
+    if let Some(session) = session {
+        conn.srem::<_, _, ()>(user_sessions_key(&session.user_id), &data.id)
+            .await
+            .map_err(|_| StorageError::RedisEndSession)?;
+    }
     Ok(())
 }
 
@@ -35,19 +92,95 @@ pub async fn get_session(
         .await
         .map_err(|_| StorageError::RedisGetSession)?;
 
-    let id = Uuid::parse_str(&data.id).map_err(|e| {
-        log::error!("{}", e);
-        StorageError::RedisGetSession
-    })?;
-
     match session {
-        Some(s) => Ok(Session {
-            id,
-            user_id: Uuid::parse_str(&s.user_id).map_err(|_| StorageError::RedisGetSession)?,
-            data: s.data,
-            created_at: s.created_at,
-            updated_at: s.updated_at,
-        }),
+        Some(s) => into_session(&data.id, s),
         None => Err(StorageError::NotFound)?,
     }
 }
+
+/// Record activity on a session by bumping its `updated_at` and pushing back its expiry
+pub async fn touch_session(conn: &mut RedisConn, data: TouchSession) -> Result<(), StorageError> {
+    let session: Option<CreateSession> = get_json(conn, &data.id)
+        .await
+        .map_err(|_| StorageError::RedisTouchSession)?;
+
+    if let Some(mut session) = session {
+        session.updated_at = chrono::offset::Utc::now();
+        let index_key = user_sessions_key(&session.user_id);
+        set_json_ex(conn, &data.id, session, data.expires_in)
+            .await
+            .map_err(|_| StorageError::RedisTouchSession)?;
+        extend_index(conn, &index_key, data.expires_in)
+            .await
+            .map_err(|_| StorageError::RedisTouchSession)?;
+    }
+    Ok(())
+}
+
+/// Get every live session a user has, most recently active first. Ids left in the index by
+/// sessions that no longer exist are cleaned up along the way.
+pub async fn get_user_sessions(
+    conn: &mut RedisConn,
+    data: GetSessionsByUserId,
+) -> Result<Vec<Session>, StorageError> {
+    let index_key = user_sessions_key(&data.user_id);
+    let ids: Vec<String> = conn
+        .smembers(&index_key)
+        .await
+        .map_err(|_| StorageError::RedisGetUserSessions)?;
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let values: Vec<Option<String>> = redis::cmd("MGET")
+        .arg(&ids)
+        .query_async(&mut **conn)
+        .await
+        .map_err(|_| StorageError::RedisGetUserSessions)?;
+
+    let mut sessions = vec![];
+    let mut stale = vec![];
+    for (id, value) in ids.iter().zip(values) {
+        match value.and_then(|v| serde_json::from_str::<CreateSession>(&v).ok()) {
+            Some(s) => sessions.push(into_session(id, s)?),
+            None => stale.push(id),
+        }
+    }
+
+    if !stale.is_empty() {
+        conn.srem::<_, _, ()>(&index_key, stale)
+            .await
+            .map_err(|_| StorageError::RedisGetUserSessions)?;
+    }
+
+    sessions.sort_by_key(|s| Reverse(s.updated_at));
+    Ok(sessions)
+}
+
+/// End all of a user's sessions, optionally keeping one of them. Returns how many were ended.
+pub async fn end_user_sessions(
+    conn: &mut RedisConn,
+    data: DeleteUserSessions,
+) -> Result<u64, StorageError> {
+    let index_key = user_sessions_key(&data.user_id);
+    let ids: Vec<String> = conn
+        .smembers(&index_key)
+        .await
+        .map_err(|_| StorageError::RedisEndUserSessions)?;
+    let ids: Vec<String> = ids
+        .into_iter()
+        .filter(|id| Some(id) != data.except.as_ref())
+        .collect();
+    if ids.is_empty() {
+        return Ok(0);
+    }
+
+    let (deleted, _): (u64, u64) = redis::pipe()
+        .atomic()
+        .del(&ids)
+        .srem(&index_key, &ids)
+        .query_async(&mut **conn)
+        .await
+        .map_err(|_| StorageError::RedisEndUserSessions)?;
+    Ok(deleted)
+}
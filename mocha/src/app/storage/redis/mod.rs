@@ -7,6 +7,7 @@ use std::error::Error;
 use std::time::Duration;
 
 pub mod auth;
+pub mod tokens;
 
 pub type RedisPool = Pool<RedisConnectionManager>;
 pub type RedisConn = Connection<RedisConnectionManager>;
@@ -39,7 +40,13 @@ pub enum CacheError {
     ServerError,
 }
 
-pub async fn set_json<'a, T>(conn: &mut RedisConn, key: &str, value: T) -> Result<(), CacheError>
+/// Store `value` as json under `key`, expiring after `seconds`
+pub async fn set_json_ex<'a, T>(
+    conn: &mut RedisConn,
+    key: &str,
+    value: T,
+    seconds: usize,
+) -> Result<(), CacheError>
 where
     T: Serialize + Deserialize<'a>,
 {
@@ -47,7 +54,7 @@ where
         .map(|s| s.as_bytes().to_vec())
         .map_err(|_| CacheError::InvalidData)?;
 
-    conn.set(key.to_owned(), bytes)
+    conn.set_ex::<_, _, ()>(key.to_owned(), bytes, seconds.max(1))
         .await
         .map_err(|_| CacheError::ServerError)?;
 
@@ -73,7 +80,9 @@ pub async fn delete(
     conn: &mut Connection<RedisConnectionManager>,
     key: &str,
 ) -> Result<(), CacheError> {
-    conn.del(key).await.map_err(|_| CacheError::ServerError)?;
+    conn.del::<_, ()>(key)
+        .await
+        .map_err(|_| CacheError::ServerError)?;
     Ok(())
 }
 
@@ -101,7 +110,7 @@ mod tests {
             age: 21,
         };
 
-        set_json(&mut conn, "test", t)
+        set_json_ex(&mut conn, "test", t, 60)
             .await
             .expect("error inserting value");
 
@@ -112,10 +121,11 @@ mod tests {
 
         println!("{:#?}", v1);
 
-        set_json(
+        set_json_ex(
             &mut conn,
             "key1",
             HashMap::<String, String>::from([("k1".to_owned(), "v1".to_owned())]),
+            60,
         )
         .await
         .expect("error inserting hashmap");
@@ -127,7 +137,7 @@ mod tests {
 
         println!("{:#?}", v2);
 
-        set_json(&mut conn, "key2", vec![4f64, 5f64, 6f64])
+        set_json_ex(&mut conn, "key2", vec![4f64, 5f64, 6f64], 60)
             .await
             .expect("error inserting float vector");
 
@@ -0,0 +1,53 @@
+use mobc_redis::redis::AsyncCommands;
+
+use crate::app::dto::auth::{CheckAccessToken, InvalidateUserTokens, RevokeAccessToken};
+
+use super::{CacheError, RedisConn};
+
+fn revoked_jti_key(jti: &str) -> String {
+    format!("revoked_jti:{jti}")
+}
+
+fn invalid_before_key(user_id: &str) -> String {
+    format!("tokens_invalid_before:{user_id}")
+}
+
+/// Put a single access token on the denylist
+pub async fn revoke_access_token(
+    conn: &mut RedisConn,
+    data: RevokeAccessToken,
+) -> Result<(), CacheError> {
+    conn.set_ex::<_, _, ()>(revoked_jti_key(&data.jti), 1, data.expires_in.max(1))
+        .await
+        .map_err(|_| CacheError::ServerError)?;
+    Ok(())
+}
+
+/// Reject every access token a user was issued up to now
+pub async fn invalidate_user_tokens(
+    conn: &mut RedisConn,
+    data: InvalidateUserTokens,
+) -> Result<(), CacheError> {
+    conn.set_ex::<_, _, ()>(
+        invalid_before_key(&data.user_id),
+        data.issued_before,
+        data.expires_in.max(1),
+    )
+    .await
+    .map_err(|_| CacheError::ServerError)?;
+    Ok(())
+}
+
+/// Look up whether a token's jti was denylisted and when the user's tokens were last invalidated
+pub async fn get_access_token_revocation(
+    conn: &mut RedisConn,
+    data: &CheckAccessToken,
+) -> Result<(bool, Option<usize>), CacheError> {
+    let (revoked, invalid_before): (Option<u8>, Option<usize>) = mobc_redis::redis::pipe()
+        .get(revoked_jti_key(&data.jti))
+        .get(invalid_before_key(&data.user_id))
+        .query_async(&mut **conn)
+        .await
+        .map_err(|_| CacheError::ServerError)?;
+    Ok((revoked.is_some(), invalid_before))
+}
@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone)]
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[sqlx(type_name = "jen.hash_algorithm")]
 #[sqlx(rename_all = "lowercase")]
 pub enum HashAlgorithm {
     Argon2,
     Bcrypt,
+    Scrypt,
+    Pbkdf2,
 }
 
 #[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy)]
@@ -25,3 +27,15 @@ pub enum AssetVisibility {
     Public,
     Private,
 }
+
+/// Where a comment is in moderation. Only approved comments are shown to anyone but their author.
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "jen.comment_status")]
+#[sqlx(rename_all = "lowercase")]
+pub enum CommentStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Spam,
+}
@@ -24,6 +24,165 @@ pub mod time {
     }
 }
 
+pub mod diff {
+    use similar::TextDiff;
+
+    /// Produce a line based unified diff between two strings. Identical inputs produce an empty
+    /// string rather than a bare header.
+    pub fn unified(old: &str, new: &str, old_header: &str, new_header: &str) -> String {
+        TextDiff::from_lines(old, new)
+            .unified_diff()
+            .header(old_header, new_header)
+            .to_string()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_unified() {
+            let old = "sixteen heirs\none fortune\n";
+            let new = "sixteen heirs\none fortune\nand a bomber\n";
+
+            let diff = unified(old, new, "a", "b");
+            assert!(diff.starts_with("--- a\n+++ b\n"));
+            assert!(diff.contains("+and a bomber"));
+
+            assert!(unified(old, old, "a", "b").is_empty());
+        }
+    }
+}
+
+pub mod http {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use actix_web::{
+        http::header::{self, EntityTag, Header, HttpDate, IfModifiedSince, IfNoneMatch},
+        HttpRequest, HttpResponse,
+    };
+    use sha2::{Digest, Sha256};
+
+    /// A strong etag derived from the bytes of a response body
+    pub fn etag(body: &[u8]) -> EntityTag {
+        let digest = Sha256::digest(body);
+        let tag: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+        EntityTag::new_strong(tag)
+    }
+
+    /// Whether the client's cached copy is still current, i.e. whether a 304 can be sent instead
+    /// of the body. As in RFC 9110, `If-None-Match` wins over `If-Modified-Since` when both are
+    /// present.
+    pub fn is_fresh(req: &HttpRequest, etag: &EntityTag, last_modified: SystemTime) -> bool {
+        if req.headers().contains_key(IfNoneMatch::name()) {
+            return match IfNoneMatch::parse(req) {
+                Ok(IfNoneMatch::Any) => true,
+                Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+                Err(_) => false,
+            };
+        }
+
+        // http dates only have second precision. Only an exact match counts, since a resource's
+        // last modified date can move backwards when its newest item goes away
+        let seconds = |t: SystemTime| t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).ok();
+        match IfModifiedSince::parse(req) {
+            Ok(IfModifiedSince(since)) => {
+                seconds(last_modified).is_some_and(|t| Some(t) == seconds(since.into()))
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Respond with `body` tagged with an etag and last modified date, or with an empty 304 if
+    /// the client already has it
+    pub fn conditional(
+        req: &HttpRequest,
+        body: String,
+        content_type: &str,
+        last_modified: SystemTime,
+    ) -> HttpResponse {
+        let etag = etag(body.as_bytes());
+        if is_fresh(req, &etag, last_modified) {
+            return HttpResponse::NotModified()
+                .insert_header(header::ETag(etag))
+                .insert_header(header::LastModified(HttpDate::from(last_modified)))
+                .finish();
+        }
+
+        HttpResponse::Ok()
+            .insert_header(header::ETag(etag))
+            .insert_header(header::LastModified(HttpDate::from(last_modified)))
+            .content_type(content_type)
+            .body(body)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use actix_web::{
+            http::header::{self, HttpDate},
+            test::TestRequest,
+        };
+
+        use super::*;
+
+        #[test]
+        pub fn test_is_fresh() {
+            let tag = etag(b"sixteen heirs");
+            let modified = UNIX_EPOCH + Duration::from_secs(1_694_000_000);
+
+            let plain = TestRequest::default().to_http_request();
+            assert!(!is_fresh(&plain, &tag, modified));
+
+            let matching = TestRequest::default()
+                .insert_header((header::IF_NONE_MATCH, tag.to_string()))
+                .to_http_request();
+            assert!(is_fresh(&matching, &tag, modified));
+
+            // a stale etag loses even if the date would have matched
+            let stale = TestRequest::default()
+                .insert_header((header::IF_NONE_MATCH, etag(b"one fortune").to_string()))
+                .insert_header((
+                    header::IF_MODIFIED_SINCE,
+                    HttpDate::from(modified).to_string(),
+                ))
+                .to_http_request();
+            assert!(!is_fresh(&stale, &tag, modified));
+
+            let unchanged = TestRequest::default()
+                .insert_header((
+                    header::IF_MODIFIED_SINCE,
+                    HttpDate::from(modified).to_string(),
+                ))
+                .to_http_request();
+            assert!(is_fresh(
+                &unchanged,
+                &tag,
+                modified + Duration::from_millis(300)
+            ));
+
+            let changed = TestRequest::default()
+                .insert_header((
+                    header::IF_MODIFIED_SINCE,
+                    HttpDate::from(modified).to_string(),
+                ))
+                .to_http_request();
+            assert!(!is_fresh(
+                &changed,
+                &tag,
+                modified + Duration::from_secs(60)
+            ));
+            // e.g. a feed whose newest post was unpublished
+            assert!(!is_fresh(
+                &changed,
+                &tag,
+                modified - Duration::from_secs(60)
+            ));
+        }
+    }
+}
+
 /// Everything in this module is only used in tests so it's alright if we annotate things with
 /// #[allow(unused)] because they are not used in the app but are necessary in tests
 #[cfg(test)]
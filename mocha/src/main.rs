@@ -4,7 +4,7 @@ use actix_web::{
 };
 use app::state::AppState;
 
-use crate::app::{errors::AppError, routes};
+use crate::app::{errors::AppError, routes, scheduler};
 
 mod app;
 
@@ -18,6 +18,8 @@ async fn main() -> std::io::Result<()> {
 
     log::info!("brewing mocha with almond milk...");
 
+    tokio::spawn(scheduler::run(state.clone()));
+
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
@@ -28,6 +30,7 @@ async fn main() -> std::io::Result<()> {
             )
             .app_data(state.clone())
             .service(web::scope("/api").configure(routes::config))
+            .configure(routes::site_config)
     })
     .bind(("0.0.0.0", 8888))?
     .run()